@@ -0,0 +1,208 @@
+//! This module contains the configuration of [`Builder`](crate::Builder) options
+//! for individual packages, types and fields
+
+use std::collections::BTreeMap;
+
+use crate::descriptor::{TypeName, TypePath};
+use crate::message::{Field, FieldType};
+
+/// A value configured for a set of path prefixes
+#[derive(Debug, Clone)]
+pub struct PathConfig<T> {
+    entries: Vec<(String, T)>,
+}
+
+impl<T> Default for PathConfig<T> {
+    fn default() -> Self {
+        Self {
+            entries: Default::default(),
+        }
+    }
+}
+
+impl<T: Copy> PathConfig<T> {
+    /// Sets the value for the path prefix `prefix`, e.g. `.mypackage.MyMessage`
+    pub fn insert(&mut self, prefix: impl Into<String>, value: T) {
+        self.entries.push((prefix.into(), value))
+    }
+
+    /// Returns the value of the most specific prefix of `path`, preferring
+    /// the most recently inserted value if several are equally specific
+    pub fn get(&self, path: &TypePath) -> Option<T> {
+        let mut ret = None;
+        let mut match_len = 0;
+        for (prefix, value) in &self.entries {
+            match path.prefix_match(prefix) {
+                Some(len) if len >= match_len => {
+                    ret = Some(*value);
+                    match_len = len;
+                }
+                _ => {}
+            }
+        }
+        ret
+    }
+
+    /// Returns the value for the field `field` of the message `message`
+    pub fn get_for_field(&self, message: &TypePath, field: &str) -> Option<T> {
+        self.get(&message.child(TypeName::new(field)))
+    }
+
+    /// Returns the configured path prefixes
+    pub fn prefixes(&self) -> impl Iterator<Item = &str> + '_ {
+        self.entries.iter().map(|(prefix, _)| prefix.as_str())
+    }
+}
+
+impl PathConfig<bool> {
+    /// Returns true if enabled for `path`
+    pub fn enabled(&self, path: &TypePath) -> bool {
+        self.get(path).unwrap_or(false)
+    }
+
+    /// Returns true if enabled for the field `field` of the message `message`
+    pub fn enabled_for_field(&self, message: &TypePath, field: &str) -> bool {
+        self.get_for_field(message, field).unwrap_or(false)
+    }
+}
+
+/// The JSON encoding of 64-bit integers, see [`Builder::int64_encoding`](crate::Builder::int64_encoding)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Int64Encoding {
+    /// Encode as strings, as required by the protobuf JSON mapping
+    #[default]
+    String,
+    /// Encode as numbers
+    Number,
+    /// Encode as numbers if exactly representable by an IEEE 754 double, i.e. if within
+    /// ±(2^53 - 1), and as strings otherwise
+    SafeNumber,
+}
+
+/// The options of a [`Builder`](crate::Builder) that can be configured by path
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// Configured for enumerations
+    pub retain_enum_prefix: PathConfig<bool>,
+    /// Configured for messages
+    pub ignore_unknown_fields: PathConfig<bool>,
+    /// Configured for fields
+    pub ignore_unknown_enum_values: PathConfig<bool>,
+    /// Configured for fields
+    pub btree_map: PathConfig<bool>,
+    /// Configured for fields
+    pub emit_fields: PathConfig<bool>,
+    /// Configured for enumerations
+    pub use_integers_for_enums: PathConfig<bool>,
+    /// Configured for fields
+    pub preserve_proto_field_names: PathConfig<bool>,
+    /// Configured for fields
+    pub bytes: PathConfig<bool>,
+    /// Configured for fields
+    pub int64_encoding: PathConfig<Int64Encoding>,
+    /// Custom serde `with` modules, keyed by the exact path of a field or type
+    pub serde_with: BTreeMap<String, String>,
+}
+
+impl Options {
+    /// Returns the paths every option is configured for
+    pub fn paths(&self) -> impl Iterator<Item = &str> + '_ {
+        [
+            &self.retain_enum_prefix,
+            &self.ignore_unknown_fields,
+            &self.ignore_unknown_enum_values,
+            &self.btree_map,
+            &self.emit_fields,
+            &self.use_integers_for_enums,
+            &self.preserve_proto_field_names,
+            &self.bytes,
+        ]
+        .into_iter()
+        .flat_map(PathConfig::prefixes)
+        .chain(self.int64_encoding.prefixes())
+        .chain(self.serde_with.keys().map(String::as_str))
+    }
+
+    /// Returns the custom serde `with` module for the field `field` of the message `message`,
+    /// preferring a module configured for the field to one configured for its type
+    pub fn serde_with(&self, message: &TypePath, field: &Field) -> Option<&str> {
+        let value_type = match &field.field_type {
+            FieldType::Map(_, value_type) => value_type.as_ref(),
+            field_type => field_type,
+        };
+
+        let type_path = match value_type {
+            FieldType::Message(path) | FieldType::Enum(path, _) => Some(path),
+            _ => None,
+        };
+
+        // Keyed by protobuf names, which unlike the rust paths are not escaped
+        self.serde_with
+            .get(&format!(".{}.{}", message.full_name(), field.name))
+            .or_else(|| self.serde_with.get(&format!(".{}", type_path?.full_name())))
+            .map(String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::descriptor::Package;
+
+    #[test]
+    fn test_path_config() {
+        let message = TypePath::new(Package::new("foo.bar")).child(TypeName::new("Baz"));
+
+        let mut config = PathConfig::default();
+        assert!(!config.enabled(&message));
+
+        config.insert(".", true);
+        assert!(config.enabled(&message));
+
+        // The most specific prefix wins, regardless of order
+        config.insert(".foo.bar.Baz.b", true);
+        config.insert(".foo.bar", false);
+        assert!(!config.enabled(&message));
+        assert!(config.enabled_for_field(&message, "b"));
+        assert!(!config.enabled_for_field(&message, "a"));
+        assert!(config.enabled(&TypePath::new(Package::new("foo.other"))));
+
+        // Of equally specific prefixes, the last inserted wins
+        config.insert(".foo.bar", true);
+        assert!(config.enabled_for_field(&message, "a"));
+    }
+
+    #[test]
+    fn test_serde_with_escaped_package() {
+        use crate::features::EnumType;
+        use crate::message::{FieldModifier, ScalarType};
+
+        // The rust path of the package is `google::r#type`
+        let package = TypePath::new(Package::new("google.type"));
+        let message = package.child(TypeName::new("Money"));
+        let field = |name: &str, field_type| Field {
+            name: name.to_string(),
+            json_name: None,
+            group_name: None,
+            field_modifier: FieldModifier::Optional,
+            field_type,
+            default_value: None,
+        };
+        let units = field("units", FieldType::Scalar(ScalarType::I64));
+        let currency = field(
+            "currency",
+            FieldType::Enum(package.child(TypeName::new("Currency")), EnumType::Open),
+        );
+
+        let mut options = Options::default();
+        options
+            .serde_with
+            .insert(".google.type.Money.units".to_string(), "units".to_string());
+        options
+            .serde_with
+            .insert(".google.type.Currency".to_string(), "currency".to_string());
+
+        assert_eq!(options.serde_with(&message, &units), Some("units"));
+        assert_eq!(options.serde_with(&message, &currency), Some("currency"));
+    }
+}
@@ -0,0 +1,227 @@
+//! This module contains the support for `pbjson-derive`, which generates the same
+//! implementations as [`Builder`](crate::Builder) for types declared with the `prost`
+//! derive macros, from their attributes rather than from protobuf descriptors
+//!
+//! As the attributes refer to other types by their rust path, rather than their
+//! protobuf name, each referenced type is assigned a placeholder protobuf name
+//! mapped to its rust path as an extern path
+//!
+//! This module is not part of the public API of this crate
+
+use crate::config::Options;
+use crate::descriptor::{Package, TypeName, TypePath};
+use crate::error::Result;
+use crate::features::EnumType;
+use crate::generator::{
+    generate_enum_deserialize, generate_enum_serialize, generate_message_deserialize,
+    generate_message_serialize, Crates,
+};
+use crate::message::{Field, FieldType, Message};
+use crate::resolver::Resolver;
+use proc_macro2::TokenStream;
+
+pub use crate::message::{FieldModifier, ScalarType};
+
+/// The implementation to generate
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trait {
+    Serialize,
+    Deserialize,
+}
+
+/// The options of a derived implementation, see the corresponding [`Builder`](crate::Builder)
+/// methods
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DeriveOptions {
+    pub ignore_unknown_fields: bool,
+    pub ignore_unknown_enum_values: bool,
+    pub emit_fields: bool,
+    pub use_integers_for_enums: bool,
+    pub preserve_proto_field_names: bool,
+}
+
+impl DeriveOptions {
+    fn options(&self) -> Options {
+        let mut options = Options::default();
+        let enabled = [
+            (
+                &mut options.ignore_unknown_fields,
+                self.ignore_unknown_fields,
+            ),
+            (
+                &mut options.ignore_unknown_enum_values,
+                self.ignore_unknown_enum_values,
+            ),
+            (&mut options.emit_fields, self.emit_fields),
+            (
+                &mut options.use_integers_for_enums,
+                self.use_integers_for_enums,
+            ),
+            (
+                &mut options.preserve_proto_field_names,
+                self.preserve_proto_field_names,
+            ),
+        ];
+        for (config, enabled) in enabled {
+            config.insert(".", enabled);
+        }
+        options
+    }
+}
+
+/// The type of a field, referring to enumerations and messages by their rust path
+#[derive(Debug, Clone)]
+pub enum DeriveType {
+    Scalar(ScalarType),
+    Enumeration(String),
+    Message(String),
+    Map(MapType, ScalarType, Box<Self>),
+}
+
+/// The rust type of a map field, as declared by its `#[prost(...)]` attribute
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MapType {
+    /// `map` or `hash_map`, i.e. a `HashMap`
+    HashMap,
+    /// `btree_map`, i.e. a `BTreeMap`
+    BTreeMap,
+}
+
+/// A field of a message
+#[derive(Debug, Clone)]
+pub struct DeriveField {
+    /// The name of the field, without any `r#` prefix
+    pub name: String,
+    pub field_modifier: FieldModifier,
+    pub field_type: DeriveType,
+}
+
+/// A message, i.e. a struct deriving `prost::Message`
+#[derive(Debug, Clone)]
+pub struct DeriveMessage {
+    pub name: String,
+    pub fields: Vec<DeriveField>,
+}
+
+/// A variant of an enumeration
+#[derive(Debug, Clone)]
+pub struct DeriveVariant {
+    /// The protobuf name of the variant, e.g. `MY_ENUM_FOO`
+    pub proto_name: String,
+    pub rust_name: String,
+    pub number: i32,
+}
+
+/// An enumeration, i.e. an enum deriving `prost::Enumeration`
+#[derive(Debug, Clone)]
+pub struct DeriveEnum {
+    pub name: String,
+    pub variants: Vec<DeriveVariant>,
+}
+
+/// The placeholder protobuf names assigned to rust types
+#[derive(Debug, Default)]
+struct Types {
+    extern_paths: Vec<(String, String)>,
+}
+
+impl Types {
+    /// Returns a type path with the protobuf name `name` that resolves to `rust_path`
+    fn declare(&mut self, name: String, rust_path: &str) -> TypePath {
+        self.extern_paths
+            .push((format!(".{}", name), rust_path.to_string()));
+        TypePath::new(Package::new("")).child(TypeName::new(name))
+    }
+
+    /// Returns a placeholder type path that resolves to `rust_path`
+    fn reference(&mut self, rust_path: &str) -> TypePath {
+        let name = format!("__Type{}", self.extern_paths.len());
+        self.declare(name, rust_path)
+    }
+
+    fn field_type(&mut self, field_type: &DeriveType) -> FieldType {
+        match field_type {
+            DeriveType::Scalar(scalar) => FieldType::Scalar(*scalar),
+            // prost represents all enumeration values as an i32
+            DeriveType::Enumeration(path) => FieldType::Enum(self.reference(path), EnumType::Open),
+            DeriveType::Message(path) => FieldType::Message(self.reference(path)),
+            DeriveType::Map(_, key, value) => {
+                FieldType::Map(*key, Box::new(self.field_type(value)))
+            }
+        }
+    }
+}
+
+impl DeriveMessage {
+    /// Generates the implementation of `generate` for this message
+    pub fn generate(&self, options: &DeriveOptions, generate: Trait) -> Result<TokenStream> {
+        let mut types = Types::default();
+        let message = Message {
+            path: types.declare(self.name.clone(), &self.name),
+            fields: self
+                .fields
+                .iter()
+                .map(|field| Field {
+                    name: field.name.clone(),
+                    json_name: None,
+                    group_name: None,
+                    field_modifier: field.field_modifier,
+                    field_type: types.field_type(&field.field_type),
+                    default_value: None,
+                })
+                .collect(),
+            one_ofs: vec![],
+            extensions: vec![],
+        };
+
+        let mut options = options.options();
+        for field in &self.fields {
+            if let DeriveType::Map(MapType::BTreeMap, _, _) = field.field_type {
+                let path = format!(".{}.{}", self.name, field.name);
+                options.btree_map.insert(path, true);
+            }
+        }
+
+        let package = Package::new("");
+        let crates = Crates::default();
+        let resolver = Resolver::new(
+            &types.extern_paths,
+            &package,
+            &options.retain_enum_prefix,
+            &crates,
+        );
+
+        match generate {
+            Trait::Serialize => generate_message_serialize(&resolver, &message, &options),
+            Trait::Deserialize => generate_message_deserialize(&resolver, &message, &options),
+        }
+    }
+}
+
+impl DeriveEnum {
+    /// Generates the implementation of `generate` for this enumeration
+    pub fn generate(&self, options: &DeriveOptions, generate: Trait) -> Result<TokenStream> {
+        let variants: Vec<_> = self
+            .variants
+            .iter()
+            .map(|variant| {
+                (
+                    variant.proto_name.clone(),
+                    variant.number,
+                    variant.rust_name.clone(),
+                )
+            })
+            .collect();
+
+        let crates = Crates::default();
+        match generate {
+            Trait::Serialize => generate_enum_serialize(
+                &self.name,
+                &variants,
+                options.use_integers_for_enums,
+                &crates,
+            ),
+            Trait::Deserialize => generate_enum_deserialize(&self.name, &variants, &crates),
+        }
+    }
+}
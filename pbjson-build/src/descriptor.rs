@@ -4,7 +4,7 @@
 use std::collections::btree_map::Entry;
 use std::collections::BTreeMap;
 use std::fmt::{Display, Formatter};
-use std::io::{Error, ErrorKind, Result};
+use std::ops::Bound;
 
 use itertools::{EitherOrBoth, Itertools};
 use prost_types::{
@@ -12,7 +12,12 @@ use prost_types::{
     FileDescriptorProto, FileDescriptorSet, MessageOptions, OneofDescriptorProto,
 };
 
+use crate::error::{Error, Result};
 use crate::escape::{escape_ident, escape_type};
+use crate::features::{
+    EnumFeatures, Features, FieldFeatures, FileFeatures, FileSetFeatures, MessageFeatures,
+    OneofFeatures,
+};
 
 #[derive(Debug, Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
 pub struct Package {
@@ -21,6 +26,11 @@ pub struct Package {
 
 impl Display for Package {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        // Matches the name prost uses for the output of files without a package
+        if self.path.is_empty() {
+            return write!(f, "_");
+        }
+
         write!(f, "{}", self.path[0].to_snake_case_ident())?;
         for element in &self.path[1..self.path.len()] {
             write!(f, ".{}", element.to_snake_case_ident())?;
@@ -30,6 +40,7 @@ impl Display for Package {
 }
 
 impl Package {
+    /// Creates a new package, an empty string denoting a file without a package
     pub fn new(s: impl AsRef<str>) -> Self {
         let s = s.as_ref();
         assert!(
@@ -38,6 +49,10 @@ impl Package {
             s
         );
 
+        if s.is_empty() {
+            return Self { path: vec![] };
+        }
+
         Self {
             path: s.split('.').map(TypeName::new).collect(),
         }
@@ -87,6 +102,10 @@ pub struct TypePath {
 
 impl Display for TypePath {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.package.path.is_empty() {
+            return write!(f, "{}", self.path.iter().join("."));
+        }
+
         self.package.fmt(f)?;
         for element in &self.path {
             write!(f, ".{}", element)?;
@@ -117,6 +136,11 @@ impl TypePath {
         self.package.path().iter().chain(self.path.iter())
     }
 
+    /// Returns the fully-qualified protobuf name, without a leading '.'
+    pub fn full_name(&self) -> String {
+        self.path().join(".")
+    }
+
     pub fn child(&self, name: TypeName) -> Self {
         let path = self
             .path
@@ -132,11 +156,10 @@ impl TypePath {
     }
 
     /// Performs a prefix match, returning the length of the match in path segments if any
+    ///
+    /// A `prefix` not starting with a '.' never matches
     pub fn prefix_match(&self, prefix: &str) -> Option<usize> {
-        let prefix = match prefix.strip_prefix('.') {
-            Some(prefix) => prefix,
-            None => panic!("prefix must start with a '.'"),
-        };
+        let prefix = prefix.strip_prefix('.')?;
 
         if prefix.is_empty() {
             return Some(0);
@@ -158,109 +181,352 @@ impl TypePath {
 #[derive(Debug, Clone, Default)]
 pub struct DescriptorSet {
     descriptors: BTreeMap<TypePath, Descriptor>,
+    /// The fully-qualified protobuf names of the registered descriptors
+    names: BTreeMap<String, TypePath>,
+    extensions: Vec<ExtensionDescriptor>,
 }
 
 impl DescriptorSet {
     pub fn register_encoded(&mut self, encoded: &[u8]) -> Result<()> {
-        let descriptors: FileDescriptorSet =
-            prost::Message::decode(encoded).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        let descriptors: FileDescriptorSet = prost::Message::decode(encoded)?;
+
+        // prost-types does not decode edition features, so decode them separately
+        let features: FileSetFeatures = prost::Message::decode(encoded)?;
 
-        for file in descriptors.file {
-            self.register_file_descriptor(file);
+        for (file, features) in descriptors.file.into_iter().zip(&features.file) {
+            self.register_file(file, features)?;
         }
 
         Ok(())
     }
 
-    pub fn register_file_descriptor(&mut self, file: FileDescriptorProto) {
-        let syntax = match file.syntax.as_deref() {
-            None | Some("proto2") => Syntax::Proto2,
-            Some("proto3") => Syntax::Proto3,
-            Some(s) => panic!("unknown syntax: {}", s),
-        };
+    /// Registers the types declared in `file`
+    ///
+    /// Registering a descriptor identical to one already registered, such as when
+    /// several descriptor sets include the same file, is a no-op
+    ///
+    /// As prost-types does not retain the edition of a file, files with
+    /// `syntax = "editions"` must instead be registered with [`Self::register_encoded`]
+    pub fn register_file_descriptor(&mut self, file: FileDescriptorProto) -> Result<()> {
+        self.register_file(file, &FileFeatures::default())
+    }
 
-        let package = Package::new(file.package.expect("expected package"));
+    fn register_file(&mut self, file: FileDescriptorProto, raw: &FileFeatures) -> Result<()> {
+        let file_name = file.name().to_string();
+        let features = match file.syntax.as_deref() {
+            None | Some("proto2") => Features::PROTO2,
+            Some("proto3") => Features::PROTO3,
+            Some("editions") => match raw.edition {
+                Some(edition) => match Features::for_edition(edition) {
+                    Some(features) => features,
+                    None => {
+                        return Err(Error::UnsupportedSyntax {
+                            file: file_name,
+                            syntax: format!("editions ({})", edition),
+                        })
+                    }
+                },
+                None => {
+                    return Err(Error::MissingProperty {
+                        file: file_name,
+                        path: "file".to_string(),
+                        property: "edition",
+                    })
+                }
+            },
+            Some(s) => {
+                return Err(Error::UnsupportedSyntax {
+                    file: file_name,
+                    syntax: s.to_string(),
+                })
+            }
+        }
+        .merge(raw.features());
+
+        let package = match file.package {
+            Some(package) if is_valid_package(&package) => Package::new(package),
+            Some(package) => {
+                return Err(Error::InvalidName {
+                    file: Some(file_name),
+                    name: package,
+                })
+            }
+            None => Package::new(""),
+        };
         let path = TypePath::new(package);
 
-        for descriptor in file.message_type {
-            self.register_message(&path, descriptor, syntax)
+        for (idx, descriptor) in file.message_type.into_iter().enumerate() {
+            let raw = raw.message_type.get(idx);
+            self.register_message(&file_name, &path, descriptor, features, raw)?
+        }
+
+        for (idx, descriptor) in file.enum_type.into_iter().enumerate() {
+            let raw = raw.enum_type.get(idx);
+            self.register_enum(&file_name, &path, descriptor, features, raw)?
         }
 
-        for descriptor in file.enum_type {
-            self.register_enum(&path, descriptor)
+        for descriptor in file.extension {
+            self.register_extension(&file_name, &path, descriptor)?
         }
+        Ok(())
     }
 
     pub fn iter(&self) -> impl Iterator<Item = (&TypePath, &Descriptor)> {
         self.descriptors.iter()
     }
 
-    fn register_message(&mut self, path: &TypePath, descriptor: DescriptorProto, syntax: Syntax) {
-        let name = TypeName::new(descriptor.name.expect("expected name"));
-        let child_path = path.child(name);
+    /// Returns the descriptor registered for `path`
+    pub fn get(&self, path: &TypePath) -> Option<&Descriptor> {
+        self.descriptors.get(path)
+    }
 
-        for child_descriptor in descriptor.enum_type {
-            self.register_enum(&child_path, child_descriptor)
+    /// Returns the registered proto2 extensions
+    pub fn extensions(&self) -> impl Iterator<Item = &ExtensionDescriptor> {
+        self.extensions.iter()
+    }
+
+    /// Resolves `type_name` as referenced from within `scope`, e.g. a message containing
+    /// a field of that type, following the protobuf scoping rules
+    ///
+    /// A fully-qualified name, starting with '.', is looked up directly. Otherwise the first
+    /// component of the name is searched for in `scope`, then each enclosing message and
+    /// package in turn, with the remainder of the name resolved within the first match
+    pub fn resolve(&self, scope: &TypePath, type_name: &str) -> Option<(&TypePath, &Descriptor)> {
+        if let Some(full_name) = type_name.strip_prefix('.') {
+            return self.lookup(full_name);
+        }
+
+        let first = type_name.split('.').next().unwrap_or(type_name);
+        let scope: Vec<_> = scope.path().map(ToString::to_string).collect();
+        for len in (0..=scope.len()).rev() {
+            let qualify = |name: &str| match len {
+                0 => name.to_string(),
+                _ => format!("{}.{}", scope[..len].join("."), name),
+            };
+
+            if self.is_defined(&qualify(first)) {
+                return self.lookup(&qualify(type_name));
+            }
+        }
+        None
+    }
+
+    /// Returns the descriptor with the fully-qualified name `full_name`
+    fn lookup(&self, full_name: &str) -> Option<(&TypePath, &Descriptor)> {
+        let path = self.names.get(full_name)?;
+        self.descriptors.get_key_value(path)
+    }
+
+    /// Returns true if `full_name` is the fully-qualified name of a registered
+    /// descriptor, or of a package or message containing one
+    fn is_defined(&self, full_name: &str) -> bool {
+        // As '.' sorts before any character valid in an identifier, the first name not
+        // less than `full_name` is either `full_name` or nested within it, if defined
+        let range = (Bound::Included(full_name), Bound::Unbounded);
+        match self.names.range::<str, _>(range).next() {
+            Some((name, _)) => match name.strip_prefix(full_name) {
+                Some(remainder) => remainder.is_empty() || remainder.starts_with('.'),
+                None => false,
+            },
+            None => false,
+        }
+    }
+
+    fn register_message(
+        &mut self,
+        file: &str,
+        path: &TypePath,
+        descriptor: DescriptorProto,
+        parent: Features,
+        raw: Option<&MessageFeatures>,
+    ) -> Result<()> {
+        let child_path = path.child(type_name(file, path, descriptor.name)?);
+        let features = parent.merge(raw.and_then(MessageFeatures::features));
+
+        for (idx, child_descriptor) in descriptor.enum_type.into_iter().enumerate() {
+            let raw = raw.and_then(|raw| raw.enum_type.get(idx));
+            self.register_enum(file, &child_path, child_descriptor, features, raw)?
+        }
+
+        for (idx, child_descriptor) in descriptor.nested_type.into_iter().enumerate() {
+            let raw = raw.and_then(|raw| raw.nested_type.get(idx));
+            self.register_message(file, &child_path, child_descriptor, features, raw)?
         }
 
-        for child_descriptor in descriptor.nested_type {
-            self.register_message(&child_path, child_descriptor, syntax)
+        for child_descriptor in descriptor.extension {
+            self.register_extension(file, &child_path, child_descriptor)?
         }
 
+        // A field inherits the features of its oneof, if any
+        let field_features = descriptor
+            .field
+            .iter()
+            .enumerate()
+            .map(|(idx, field)| {
+                let one_of = field
+                    .oneof_index
+                    .and_then(|one_of| raw?.oneof_decl.get(one_of as usize))
+                    .and_then(OneofFeatures::features);
+                let field = raw
+                    .and_then(|raw| raw.field.get(idx))
+                    .and_then(FieldFeatures::features);
+                features.merge(one_of).merge(field)
+            })
+            .collect();
+
         self.register_descriptor(
+            file,
             child_path.clone(),
             Descriptor::Message(MessageDescriptor {
+                file: file.to_string(),
                 path: child_path,
                 options: descriptor.options,
                 one_of: descriptor.oneof_decl,
                 fields: descriptor.field,
-                syntax,
+                field_features,
             }),
-        );
+        )
     }
 
-    fn register_enum(&mut self, path: &TypePath, descriptor: EnumDescriptorProto) {
-        let name = TypeName::new(descriptor.name.expect("expected name"));
+    fn register_enum(
+        &mut self,
+        file: &str,
+        path: &TypePath,
+        descriptor: EnumDescriptorProto,
+        parent: Features,
+        raw: Option<&EnumFeatures>,
+    ) -> Result<()> {
+        let child_path = path.child(type_name(file, path, descriptor.name)?);
+        if descriptor.value.iter().any(|value| value.name.is_none()) {
+            return Err(Error::MissingProperty {
+                file: file.to_string(),
+                path: child_path.to_string(),
+                property: "enum value name",
+            });
+        }
+
         self.register_descriptor(
-            path.child(name),
+            file,
+            child_path,
             Descriptor::Enum(EnumDescriptor {
                 values: descriptor.value,
+                features: parent.merge(raw.and_then(EnumFeatures::features)),
             }),
-        );
+        )
+    }
+
+    /// Registers an extension declared within the scope `path`
+    fn register_extension(
+        &mut self,
+        file: &str,
+        path: &TypePath,
+        field: FieldDescriptorProto,
+    ) -> Result<()> {
+        let full_name = path
+            .path()
+            .map(ToString::to_string)
+            .chain(std::iter::once(field.name().to_string()))
+            .join(".");
+
+        let extension = ExtensionDescriptor {
+            file: file.to_string(),
+            full_name,
+            scope: path.clone(),
+            field,
+        };
+
+        match self
+            .extensions
+            .iter()
+            .find(|e| e.full_name == extension.full_name)
+        {
+            Some(existing) if existing == &extension => Ok(()),
+            Some(_) => Err(Error::DuplicateDescriptor {
+                file: file.to_string(),
+                path: extension.full_name,
+            }),
+            None => {
+                self.extensions.push(extension);
+                Ok(())
+            }
+        }
     }
 
-    fn register_descriptor(&mut self, path: TypePath, descriptor: Descriptor) {
-        match self.descriptors.entry(path) {
-            Entry::Occupied(o) => panic!("descriptor already registered for {}", o.key()),
+    fn register_descriptor(
+        &mut self,
+        file: &str,
+        path: TypePath,
+        descriptor: Descriptor,
+    ) -> Result<()> {
+        match self.descriptors.entry(path.clone()) {
+            Entry::Occupied(o) if o.get() == &descriptor => return Ok(()),
+            Entry::Occupied(o) => {
+                return Err(Error::DuplicateDescriptor {
+                    file: file.to_string(),
+                    path: o.key().to_string(),
+                })
+            }
             Entry::Vacant(v) => v.insert(descriptor),
         };
+        self.names.insert(path.full_name(), path);
+        Ok(())
     }
 }
 
-#[derive(Debug, Clone, Copy)]
-pub enum Syntax {
-    Proto2,
-    Proto3,
+/// Returns true if `package` is a valid package name, or empty
+fn is_valid_package(package: &str) -> bool {
+    package.is_empty() || package.split('.').all(|s| !s.is_empty())
 }
 
-#[derive(Debug, Clone)]
+/// Returns the [`TypeName`] of a type declared in `path`
+fn type_name(file: &str, path: &TypePath, name: Option<String>) -> Result<TypeName> {
+    match name {
+        Some(name) if !name.is_empty() && !name.contains('.') => Ok(TypeName::new(name)),
+        Some(name) => Err(Error::InvalidName {
+            file: Some(file.to_string()),
+            name,
+        }),
+        None => Err(Error::MissingProperty {
+            file: file.to_string(),
+            path: path.to_string(),
+            property: "type name",
+        }),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum Descriptor {
     Enum(EnumDescriptor),
     Message(MessageDescriptor),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct EnumDescriptor {
     pub values: Vec<EnumValueDescriptorProto>,
+    pub features: Features,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct MessageDescriptor {
+    /// The name of the file declaring this message
+    pub file: String,
     pub path: TypePath,
     pub options: Option<MessageOptions>,
     pub one_of: Vec<OneofDescriptorProto>,
     pub fields: Vec<FieldDescriptorProto>,
-    pub syntax: Syntax,
+    /// The resolved features of each of `fields`
+    pub field_features: Vec<Features>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExtensionDescriptor {
+    /// The name of the file declaring this extension
+    pub file: String,
+    /// The fully-qualified protobuf name of the extension, e.g. `mypackage.my_extension`
+    pub full_name: String,
+    /// The package or message the extension is declared within
+    pub scope: TypePath,
+    pub field: FieldDescriptorProto,
 }
 
 impl MessageDescriptor {
@@ -283,6 +549,7 @@ mod tests {
             .child(TypeName::new("Bar"));
 
         assert_eq!(t.prefix_match("."), Some(0));
+        assert_eq!(t.prefix_match("foo"), None);
         assert_eq!(t.prefix_match(".."), None);
         assert_eq!(t.prefix_match(".foo"), Some(1));
         assert_eq!(t.prefix_match(".foo."), None);
@@ -294,6 +561,137 @@ mod tests {
         assert_eq!(t.prefix_match(".foo.bar.Baz.Bar.Boo"), None);
     }
 
+    #[test]
+    fn test_resolve() {
+        let message = |name: &str, nested_type: Vec<DescriptorProto>| DescriptorProto {
+            name: Some(name.to_string()),
+            nested_type,
+            ..Default::default()
+        };
+
+        let mut set = DescriptorSet::default();
+        set.register_file_descriptor(FileDescriptorProto {
+            package: Some("foo.bar".to_string()),
+            message_type: vec![
+                message(
+                    "Outer",
+                    vec![message("Inner", vec![message("Deep", vec![])])],
+                ),
+                message("Other", vec![]),
+            ],
+            ..Default::default()
+        })
+        .unwrap();
+        set.register_file_descriptor(FileDescriptorProto {
+            package: Some("foo.baz".to_string()),
+            message_type: vec![message("Other", vec![])],
+            ..Default::default()
+        })
+        .unwrap();
+
+        let scope = TypePath::new(Package::new("foo.bar"))
+            .child(TypeName::new("Outer"))
+            .child(TypeName::new("Inner"));
+
+        let resolve = |type_name: &str| {
+            set.resolve(&scope, type_name)
+                .map(|(path, _)| path.full_name())
+        };
+
+        assert_eq!(resolve("Deep").unwrap(), "foo.bar.Outer.Inner.Deep");
+        assert_eq!(resolve("Inner").unwrap(), "foo.bar.Outer.Inner");
+        assert_eq!(resolve("Inner.Deep").unwrap(), "foo.bar.Outer.Inner.Deep");
+        assert_eq!(resolve("Outer.Inner").unwrap(), "foo.bar.Outer.Inner");
+        assert_eq!(resolve("Other").unwrap(), "foo.bar.Other");
+        assert_eq!(resolve("bar.Other").unwrap(), "foo.bar.Other");
+        assert_eq!(resolve("baz.Other").unwrap(), "foo.baz.Other");
+        assert_eq!(resolve("foo.baz.Other").unwrap(), "foo.baz.Other");
+        assert_eq!(resolve(".foo.baz.Other").unwrap(), "foo.baz.Other");
+
+        // The remainder is only resolved within the innermost match of the first component
+        assert_eq!(resolve("Inner.Other"), None);
+        assert_eq!(resolve("Missing"), None);
+        assert_eq!(resolve(".Other"), None);
+    }
+
+    #[test]
+    fn test_register_duplicate() {
+        let file = |name: &str, field: &str| FileDescriptorProto {
+            name: Some(name.to_string()),
+            package: Some("foo".to_string()),
+            message_type: vec![DescriptorProto {
+                name: Some("Bar".to_string()),
+                field: vec![FieldDescriptorProto {
+                    name: Some(field.to_string()),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        let mut set = DescriptorSet::default();
+        set.register_file_descriptor(file("foo.proto", "a"))
+            .unwrap();
+        // Registering an identical file is a no-op
+        set.register_file_descriptor(file("foo.proto", "a"))
+            .unwrap();
+        assert_eq!(set.iter().count(), 1);
+
+        let err = set
+            .register_file_descriptor(file("foo.proto", "b"))
+            .unwrap_err();
+        assert!(matches!(
+            &err,
+            Error::DuplicateDescriptor { file, path } if file == "foo.proto" && path == "foo.Bar"
+        ));
+
+        // The same type declared by a different file conflicts
+        let err = set
+            .register_file_descriptor(file("bar.proto", "a"))
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "conflicting descriptor for foo.Bar in bar.proto, a different descriptor is already registered"
+        );
+
+        let err = set
+            .register_file_descriptor(FileDescriptorProto {
+                name: Some("baz.proto".to_string()),
+                syntax: Some("proto4".to_string()),
+                ..Default::default()
+            })
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "unsupported syntax \"proto4\" in baz.proto"
+        );
+    }
+
+    #[test]
+    fn test_no_package() {
+        let package = Package::new("");
+        assert!(package.path().is_empty());
+        assert_eq!(package.to_string(), "_");
+
+        let path = TypePath::new(package).child(TypeName::new("Foo"));
+        assert_eq!(path.to_string(), "Foo");
+        assert_eq!(path.full_name(), "Foo");
+        assert_eq!(path.prefix_match(".Foo"), Some(1));
+
+        let mut set = DescriptorSet::default();
+        set.register_file_descriptor(FileDescriptorProto {
+            message_type: vec![DescriptorProto {
+                name: Some("Foo".to_string()),
+                ..Default::default()
+            }],
+            ..Default::default()
+        })
+        .unwrap();
+        let (resolved, _) = set.resolve(&path, "Foo").unwrap();
+        assert_eq!(resolved, &path);
+    }
+
     #[test]
     fn test_handle_camel_case_in_package() {
         assert_eq!(
@@ -0,0 +1,129 @@
+//! This module contains the error type returned when registering descriptors
+//! or generating code
+
+use std::fmt::{Display, Formatter};
+
+/// The result type returned by this crate
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// An error registering descriptors with, or generating code from, a [`Builder`](crate::Builder)
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// An encoded `FileDescriptorSet` could not be decoded
+    Decode(prost::DecodeError),
+    /// Generated code could not be written
+    Io(std::io::Error),
+    /// A file declares a syntax that is not supported
+    UnsupportedSyntax { file: String, syntax: String },
+    /// A package, type or path name is not valid, either declared by `file` or, if `None`,
+    /// configured on the [`Builder`](crate::Builder)
+    InvalidName { file: Option<String>, name: String },
+    /// A descriptor is missing a property required to generate code, e.g. a name
+    MissingProperty {
+        file: String,
+        path: String,
+        property: &'static str,
+    },
+    /// Different descriptors were registered for the same type or extension
+    DuplicateDescriptor { file: String, path: String },
+    /// A type referenced by a field could not be resolved
+    UnresolvedType {
+        file: String,
+        path: String,
+        field: String,
+        type_name: String,
+    },
+    /// A field is not valid, e.g. a map with a floating point key
+    InvalidField {
+        file: String,
+        path: String,
+        field: String,
+        reason: String,
+    },
+    /// A module configured with [`Builder::serde_with`](crate::Builder::serde_with) for
+    /// the field `path` is not a valid rust path
+    InvalidModule { path: String, module: String },
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Decode(e) => write!(f, "failed to decode descriptors: {}", e),
+            Self::Io(e) => write!(f, "failed to write generated code: {}", e),
+            Self::UnsupportedSyntax { file, syntax } => {
+                write!(f, "unsupported syntax \"{}\" in {}", syntax, file)
+            }
+            Self::InvalidName {
+                file: Some(file),
+                name,
+            } => write!(f, "invalid name \"{}\" in {}", name, file),
+            Self::InvalidName { file: None, name } => write!(f, "invalid name \"{}\"", name),
+            Self::MissingProperty {
+                file,
+                path,
+                property,
+            } => write!(f, "{} is missing {} in {}", path, property, file),
+            Self::DuplicateDescriptor { file, path } => write!(
+                f,
+                "conflicting descriptor for {} in {}, a different descriptor is already registered",
+                path, file
+            ),
+            Self::UnresolvedType {
+                file,
+                path,
+                field,
+                type_name,
+            } => write!(
+                f,
+                "failed to resolve type \"{}\" of field {}.{} in {}",
+                type_name, path, field, file
+            ),
+            Self::InvalidField {
+                file,
+                path,
+                field,
+                reason,
+            } => write!(
+                f,
+                "invalid field {}.{} in {}: {}",
+                path, field, file, reason
+            ),
+            Self::InvalidModule { path, module } => {
+                write!(f, "invalid serde_with module \"{}\" for {}", module, path)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Decode(e) => Some(e),
+            Self::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<prost::DecodeError> for Error {
+    fn from(e: prost::DecodeError) -> Self {
+        Self::Decode(e)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+/// Allows `?` in functions returning [`std::io::Result`], e.g. existing `build.rs` scripts
+impl From<Error> for std::io::Error {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::Io(e) => e,
+            e => Self::other(e),
+        }
+    }
+}
@@ -0,0 +1,198 @@
+//! This module resolves the protobuf edition features that affect the JSON mapping
+//!
+//! Editions replace `syntax` with a set of features, declared with defaults for each
+//! edition and overridden by the options of a file, message, oneof, field or enum. As
+//! prost-types does not decode the `edition` of a file or the `features` of its options,
+//! these are decoded separately from the encoded descriptors, into a tree that mirrors
+//! the structure of the `FileDescriptorProto`
+//!
+//! Of the features, only `field_presence` and `enum_type` affect generated code.
+//! `json_format` only determines whether protoc validates the JSON mapping of a type,
+//! and the wire encoding features have no bearing on JSON
+
+/// The `edition` of a `FileDescriptorProto`, see `google.protobuf.Edition`
+const EDITION_2023: i32 = 1000;
+const EDITION_2024: i32 = 1001;
+
+/// Whether a field tracks presence, see `google.protobuf.FeatureSet.FieldPresence`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldPresence {
+    Explicit,
+    Implicit,
+    LegacyRequired,
+}
+
+/// Whether an enumeration is open, i.e. permits values without a corresponding
+/// variant, see `google.protobuf.FeatureSet.EnumType`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EnumType {
+    Open,
+    Closed,
+}
+
+/// The resolved features of a descriptor
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Features {
+    pub field_presence: FieldPresence,
+    pub enum_type: EnumType,
+}
+
+impl Features {
+    /// The features of a file with `syntax = "proto2"`
+    pub const PROTO2: Self = Self {
+        field_presence: FieldPresence::Explicit,
+        enum_type: EnumType::Closed,
+    };
+
+    /// The features of a file with `syntax = "proto3"`
+    pub const PROTO3: Self = Self {
+        field_presence: FieldPresence::Implicit,
+        enum_type: EnumType::Open,
+    };
+
+    /// Returns the default features of a file with `syntax = "editions"` and
+    /// the given `edition`, if supported
+    pub fn for_edition(edition: i32) -> Option<Self> {
+        match edition {
+            EDITION_2023 | EDITION_2024 => Some(Self {
+                field_presence: FieldPresence::Explicit,
+                enum_type: EnumType::Open,
+            }),
+            _ => None,
+        }
+    }
+
+    /// Returns these features with any features set in `overrides` applied
+    pub fn merge(self, overrides: Option<&FeatureSet>) -> Self {
+        let overrides = match overrides {
+            Some(overrides) => overrides,
+            None => return self,
+        };
+
+        Self {
+            field_presence: match overrides.field_presence {
+                Some(1) => FieldPresence::Explicit,
+                Some(2) => FieldPresence::Implicit,
+                Some(3) => FieldPresence::LegacyRequired,
+                _ => self.field_presence,
+            },
+            enum_type: match overrides.enum_type {
+                Some(1) => EnumType::Open,
+                Some(2) => EnumType::Closed,
+                _ => self.enum_type,
+            },
+        }
+    }
+}
+
+/// The subset of `google.protobuf.FeatureSet` affecting the JSON mapping
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct FeatureSet {
+    #[prost(int32, optional, tag = "1")]
+    pub field_presence: Option<i32>,
+    #[prost(int32, optional, tag = "2")]
+    pub enum_type: Option<i32>,
+}
+
+/// Declares a message containing only the `features` of an options message
+macro_rules! options {
+    ($name:ident, $tag:literal) => {
+        #[derive(Clone, PartialEq, prost::Message)]
+        pub struct $name {
+            #[prost(message, optional, tag = $tag)]
+            pub features: Option<FeatureSet>,
+        }
+    };
+}
+
+options!(FileOptions, "50");
+options!(MessageOptions, "12");
+options!(FieldOptions, "21");
+options!(OneofOptions, "1");
+options!(EnumOptions, "7");
+
+/// The features of a `google.protobuf.FileDescriptorSet`
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct FileSetFeatures {
+    #[prost(message, repeated, tag = "1")]
+    pub file: Vec<FileFeatures>,
+}
+
+/// The edition and features of a `google.protobuf.FileDescriptorProto`
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct FileFeatures {
+    #[prost(int32, optional, tag = "14")]
+    pub edition: Option<i32>,
+    #[prost(message, optional, tag = "8")]
+    pub options: Option<FileOptions>,
+    #[prost(message, repeated, tag = "4")]
+    pub message_type: Vec<MessageFeatures>,
+    #[prost(message, repeated, tag = "5")]
+    pub enum_type: Vec<EnumFeatures>,
+}
+
+/// The features of a `google.protobuf.DescriptorProto`
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct MessageFeatures {
+    #[prost(message, optional, tag = "7")]
+    pub options: Option<MessageOptions>,
+    #[prost(message, repeated, tag = "2")]
+    pub field: Vec<FieldFeatures>,
+    #[prost(message, repeated, tag = "3")]
+    pub nested_type: Vec<Self>,
+    #[prost(message, repeated, tag = "4")]
+    pub enum_type: Vec<EnumFeatures>,
+    #[prost(message, repeated, tag = "8")]
+    pub oneof_decl: Vec<OneofFeatures>,
+}
+
+/// The features of a `google.protobuf.FieldDescriptorProto`
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct FieldFeatures {
+    #[prost(message, optional, tag = "8")]
+    pub options: Option<FieldOptions>,
+}
+
+/// The features of a `google.protobuf.OneofDescriptorProto`
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct OneofFeatures {
+    #[prost(message, optional, tag = "2")]
+    pub options: Option<OneofOptions>,
+}
+
+/// The features of a `google.protobuf.EnumDescriptorProto`
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct EnumFeatures {
+    #[prost(message, optional, tag = "3")]
+    pub options: Option<EnumOptions>,
+}
+
+impl FileFeatures {
+    pub fn features(&self) -> Option<&FeatureSet> {
+        self.options.as_ref()?.features.as_ref()
+    }
+}
+
+impl MessageFeatures {
+    pub fn features(&self) -> Option<&FeatureSet> {
+        self.options.as_ref()?.features.as_ref()
+    }
+}
+
+impl FieldFeatures {
+    pub fn features(&self) -> Option<&FeatureSet> {
+        self.options.as_ref()?.features.as_ref()
+    }
+}
+
+impl OneofFeatures {
+    pub fn features(&self) -> Option<&FeatureSet> {
+        self.options.as_ref()?.features.as_ref()
+    }
+}
+
+impl EnumFeatures {
+    pub fn features(&self) -> Option<&FeatureSet> {
+        self.options.as_ref()?.features.as_ref()
+    }
+}
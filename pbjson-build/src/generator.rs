@@ -1,81 +1,107 @@
 //! This module contains the actual code generation logic
+//!
+//! The generated code is assembled as a [`TokenStream`] with [`quote`], and
+//! formatted with [`prettyplease`] once every item of a file is generated
 
-use std::fmt::{Display, Formatter};
-use std::io::{Result, Write};
+use proc_macro2::{Ident, TokenStream};
+use quote::{format_ident, quote, ToTokens};
+
+use crate::error::{Error, Result};
 
 mod enumeration;
+mod include;
 mod message;
 
-pub use enumeration::generate_enum;
-pub use message::generate_message;
+pub use enumeration::{generate_enum, generate_enum_deserialize, generate_enum_serialize};
+pub use include::generate_include_file;
+pub use message::{generate_message, generate_message_deserialize, generate_message_serialize};
 
-#[derive(Debug, Clone, Copy)]
-struct Indent(usize);
+/// Formats `tokens`, a sequence of items, as the contents of a rust source file
+pub fn format(tokens: TokenStream) -> String {
+    let file: syn::File = syn::parse2(tokens).expect("generated code is a sequence of items");
+    prettyplease::unparse(&file)
+}
 
-impl Display for Indent {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        for _ in 0..self.0 {
-            write!(f, "    ")?;
-        }
-        Ok(())
+/// The paths generated code refers to the `core` and `alloc` crates by, see
+/// [`crate::Builder::core_path`] and [`crate::Builder::alloc_path`]
+#[derive(Debug, Clone)]
+pub struct Crates {
+    pub core: TokenStream,
+    pub alloc: TokenStream,
+}
+
+impl Crates {
+    /// Parses the paths of the `core` and `alloc` crates, defaulting to `::core`
+    /// and the re-export of `alloc` by pbjson respectively
+    pub fn new(core: Option<&str>, alloc: Option<&str>) -> Result<Self> {
+        Ok(Self {
+            core: parse_path(core.unwrap_or("::core"))?.into_token_stream(),
+            alloc: parse_path(alloc.unwrap_or("::pbjson::private::alloc"))?.into_token_stream(),
+        })
     }
 }
 
-fn write_fields_array<'a, W: Write, I: Iterator<Item = &'a str>>(
-    writer: &mut W,
-    indent: usize,
-    variants: I,
-) -> Result<()> {
-    writeln!(writer, "{}const FIELDS: &[&str] = &[", Indent(indent))?;
-    for name in variants {
-        writeln!(writer, "{}\"{}\",", Indent(indent + 1), name)?;
+impl Default for Crates {
+    fn default() -> Self {
+        Self::new(None, None).expect("default crate paths are valid")
     }
-    writeln!(writer, "{}];", Indent(indent))?;
-    writeln!(writer)
 }
 
-fn write_serialize_start<W: Write>(indent: usize, rust_type: &str, writer: &mut W) -> Result<()> {
-    writeln!(
-        writer,
-        r#"{indent}impl serde::Serialize for {rust_type} {{
-{indent}    #[allow(deprecated)]
-{indent}    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
-{indent}    where
-{indent}        S: serde::Serializer,
-{indent}    {{"#,
-        indent = Indent(indent),
-        rust_type = rust_type
-    )
+/// Returns the identifier `name`, which may be a raw identifier, e.g. `r#type`
+fn ident(name: &str) -> Ident {
+    format_ident!("{}", name)
+}
+
+/// Parses the rust type `rust_type`, e.g. `super::foo::Bar` or `Vec<u8>`
+fn parse_type(rust_type: &str) -> Result<syn::Type> {
+    syn::parse_str(rust_type).map_err(|_| Error::InvalidName {
+        file: None,
+        name: rust_type.to_string(),
+    })
 }
 
-fn write_serialize_end<W: Write>(indent: usize, writer: &mut W) -> Result<()> {
-    writeln!(
-        writer,
-        r#"{indent}    }}
-{indent}}}"#,
-        indent = Indent(indent),
-    )
+/// Parses the rust path `path`, e.g. `::core`
+fn parse_path(path: &str) -> Result<syn::Path> {
+    syn::parse_str(path).map_err(|_| Error::InvalidName {
+        file: None,
+        name: path.to_string(),
+    })
 }
 
-fn write_deserialize_start<W: Write>(indent: usize, rust_type: &str, writer: &mut W) -> Result<()> {
-    writeln!(
-        writer,
-        r#"{indent}impl<'de> serde::Deserialize<'de> for {rust_type} {{
-{indent}    #[allow(deprecated)]
-{indent}    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
-{indent}    where
-{indent}        D: serde::Deserializer<'de>,
-{indent}    {{"#,
-        indent = Indent(indent),
-        rust_type = rust_type
-    )
+fn fields_array<'a, I: Iterator<Item = &'a str>>(names: I) -> TokenStream {
+    quote! {
+        const FIELDS: &[&str] = &[#(#names),*];
+    }
 }
 
-fn write_deserialize_end<W: Write>(indent: usize, writer: &mut W) -> Result<()> {
-    writeln!(
-        writer,
-        r#"{indent}    }}
-{indent}}}"#,
-        indent = Indent(indent),
-    )
+/// Returns an implementation of `serde::Serialize` for `rust_type` with the body `body`
+fn serialize_impl(rust_type: &syn::Type, body: TokenStream, crates: &Crates) -> TokenStream {
+    let core = &crates.core;
+    quote! {
+        impl serde::Serialize for #rust_type {
+            #[allow(deprecated)]
+            fn serialize<S>(&self, serializer: S) -> #core::result::Result<S::Ok, S::Error>
+            where
+                S: serde::Serializer,
+            {
+                #body
+            }
+        }
+    }
+}
+
+/// Returns an implementation of `serde::Deserialize` for `rust_type` with the body `body`
+fn deserialize_impl(rust_type: &syn::Type, body: TokenStream, crates: &Crates) -> TokenStream {
+    let core = &crates.core;
+    quote! {
+        impl<'de> serde::Deserialize<'de> for #rust_type {
+            #[allow(deprecated)]
+            fn deserialize<D>(deserializer: D) -> #core::result::Result<Self, D::Error>
+            where
+                D: serde::Deserializer<'de>,
+            {
+                #body
+            }
+        }
+    }
 }
@@ -4,24 +4,22 @@
 //! An enumeration should be decode-able from the full string variant name
 //! or its integer tag number, and should encode to the string representation
 
-use super::{
-    write_deserialize_end, write_deserialize_start, write_serialize_end, write_serialize_start,
-    Indent,
-};
+use super::{deserialize_impl, fields_array, ident, parse_type, serialize_impl, Crates};
 use crate::descriptor::{EnumDescriptor, TypePath};
-use crate::generator::write_fields_array;
+use crate::error::Result;
 use crate::resolver::Resolver;
+use proc_macro2::{Literal, TokenStream};
+use quote::quote;
 use std::collections::HashSet;
-use std::io::{Result, Write};
 
-pub fn generate_enum<W: Write>(
+pub fn generate_enum(
     resolver: &Resolver<'_>,
     path: &TypePath,
     descriptor: &EnumDescriptor,
-    writer: &mut W,
     use_integers_for_enums: bool,
-) -> Result<()> {
+) -> Result<TokenStream> {
     let rust_type = resolver.rust_type(path);
+    let crates = resolver.crates();
 
     let mut seen_numbers = HashSet::new();
     let variants: Vec<_> = descriptor
@@ -38,123 +36,160 @@ pub fn generate_enum<W: Write>(
         })
         .collect();
 
-    // Generate Serialize
-    write_serialize_start(0, &rust_type, writer)?;
-    if use_integers_for_enums {
-        writeln!(writer, "{}let variant = match self {{", Indent(2))?;
-        for (_, variant_number, rust_variant) in &variants {
-            writeln!(
-                writer,
-                "{}Self::{} => {},",
-                Indent(3),
-                rust_variant,
-                variant_number
-            )?;
-        }
-        writeln!(writer, "{}}};", Indent(2))?;
+    let mut tokens =
+        generate_enum_serialize(&rust_type, &variants, use_integers_for_enums, crates)?;
+    tokens.extend(generate_enum_deserialize(&rust_type, &variants, crates)?);
+    Ok(tokens)
+}
 
-        writeln!(writer, "{}serializer.serialize_i32(variant)", Indent(2))?;
+/// Generates the Serialize implementation of the enumeration `rust_type`, given the
+/// protobuf name, number and rust name of each of its `variants`
+pub fn generate_enum_serialize(
+    rust_type: &str,
+    variants: &[(String, i32, String)],
+    use_integers_for_enums: bool,
+    crates: &Crates,
+) -> Result<TokenStream> {
+    let rust_variants = variants
+        .iter()
+        .map(|(_, _, rust_variant)| ident(rust_variant));
+
+    let body = if use_integers_for_enums {
+        let numbers = variants
+            .iter()
+            .map(|(_, variant_number, _)| Literal::i32_unsuffixed(*variant_number));
+        quote! {
+            let variant = match self {
+                #(Self::#rust_variants => #numbers,)*
+            };
+            serializer.serialize_i32(variant)
+        }
     } else {
-        writeln!(writer, "{}let variant = match self {{", Indent(2))?;
-        for (variant_name, _, rust_variant) in &variants {
-            writeln!(
-                writer,
-                "{}Self::{} => \"{}\",",
-                Indent(3),
-                rust_variant,
-                variant_name
-            )?;
+        let names = variants.iter().map(|(variant_name, _, _)| variant_name);
+        quote! {
+            let variant = match self {
+                #(Self::#rust_variants => #names,)*
+            };
+            serializer.serialize_str(variant)
         }
-        writeln!(writer, "{}}};", Indent(2))?;
-
-        writeln!(writer, "{}serializer.serialize_str(variant)", Indent(2))?;
-    }
-    write_serialize_end(0, writer)?;
+    };
+    Ok(serialize_impl(&parse_type(rust_type)?, body, crates))
+}
 
-    // Generate Deserialize
-    write_deserialize_start(0, &rust_type, writer)?;
-    write_fields_array(writer, 2, variants.iter().map(|(name, _, _)| name.as_str()))?;
-    write_visitor(writer, 2, &rust_type, &variants)?;
+/// Generates the Deserialize implementation of the enumeration `rust_type`, given the
+/// protobuf name, number and rust name of each of its `variants`
+pub fn generate_enum_deserialize(
+    rust_type: &str,
+    variants: &[(String, i32, String)],
+    crates: &Crates,
+) -> Result<TokenStream> {
+    let rust_type = parse_type(rust_type)?;
+    let fields = fields_array(variants.iter().map(|(name, _, _)| name.as_str()));
+    let visitor = visitor(&rust_type, variants, crates);
 
     // Use deserialize_any to allow users to provide integers or strings
-    writeln!(
-        writer,
-        "{}deserializer.deserialize_any(GeneratedVisitor)",
-        Indent(2)
-    )?;
-
-    write_deserialize_end(0, writer)?;
-    Ok(())
+    let body = quote! {
+        #fields
+        #visitor
+        deserializer.deserialize_any(GeneratedVisitor)
+    };
+    Ok(deserialize_impl(&rust_type, body, crates))
 }
 
-fn write_visitor<W: Write>(
-    writer: &mut W,
-    indent: usize,
-    rust_type: &str,
+fn visitor(
+    rust_type: &syn::Type,
     variants: &[(String, i32, String)],
-) -> Result<()> {
+    crates: &Crates,
+) -> TokenStream {
+    let core = &crates.core;
+    let names = variants.iter().map(|(variant_name, _, _)| variant_name);
+    let rust_variants = variants
+        .iter()
+        .map(|(_, _, rust_variant)| ident(rust_variant));
+
     // Protobuf supports deserialization of enumerations both from string and integer values
-    writeln!(
-        writer,
-        r#"{indent}struct GeneratedVisitor;
-
-{indent}impl<'de> serde::de::Visitor<'de> for GeneratedVisitor {{
-{indent}    type Value = {rust_type};
-
-{indent}    fn expecting(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {{
-{indent}        write!(formatter, "expected one of: {{:?}}", &FIELDS)
-{indent}    }}
-
-{indent}    fn visit_i64<E>(self, v: i64) -> std::result::Result<Self::Value, E>
-{indent}    where
-{indent}        E: serde::de::Error,
-{indent}    {{
-{indent}        i32::try_from(v)
-{indent}            .ok()
-{indent}            .and_then(|x| x.try_into().ok())
-{indent}            .ok_or_else(|| {{
-{indent}                serde::de::Error::invalid_value(serde::de::Unexpected::Signed(v), &self)
-{indent}            }})
-{indent}    }}
-
-{indent}    fn visit_u64<E>(self, v: u64) -> std::result::Result<Self::Value, E>
-{indent}    where
-{indent}        E: serde::de::Error,
-{indent}    {{
-{indent}        i32::try_from(v)
-{indent}            .ok()
-{indent}            .and_then(|x| x.try_into().ok())
-{indent}            .ok_or_else(|| {{
-{indent}                serde::de::Error::invalid_value(serde::de::Unexpected::Unsigned(v), &self)
-{indent}            }})
-{indent}    }}
-
-{indent}    fn visit_str<E>(self, value: &str) -> std::result::Result<Self::Value, E>
-{indent}    where
-{indent}        E: serde::de::Error,
-{indent}    {{"#,
-        indent = Indent(indent),
-        rust_type = rust_type,
-    )?;
-
-    writeln!(writer, "{}match value {{", Indent(indent + 2))?;
-    for (variant_name, _, rust_variant) in variants {
-        writeln!(
-            writer,
-            "{}\"{}\" => Ok({}::{}),",
-            Indent(indent + 3),
-            variant_name,
-            rust_type,
-            rust_variant
-        )?;
+    quote! {
+        struct GeneratedVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for GeneratedVisitor {
+            type Value = #rust_type;
+
+            fn expecting(&self, formatter: &mut #core::fmt::Formatter<'_>) -> #core::fmt::Result {
+                write!(formatter, "expected one of: {:?}", FIELDS)
+            }
+
+            fn visit_i64<E>(self, v: i64) -> #core::result::Result<Self::Value, E>
+            where
+                E: serde::de::Error,
+            {
+                i32::try_from(v)
+                    .ok()
+                    .and_then(|x| x.try_into().ok())
+                    .ok_or_else(|| {
+                        serde::de::Error::invalid_value(serde::de::Unexpected::Signed(v), &self)
+                    })
+            }
+
+            fn visit_u64<E>(self, v: u64) -> #core::result::Result<Self::Value, E>
+            where
+                E: serde::de::Error,
+            {
+                i32::try_from(v)
+                    .ok()
+                    .and_then(|x| x.try_into().ok())
+                    .ok_or_else(|| {
+                        serde::de::Error::invalid_value(serde::de::Unexpected::Unsigned(v), &self)
+                    })
+            }
+
+            fn visit_str<E>(self, value: &str) -> #core::result::Result<Self::Value, E>
+            where
+                E: serde::de::Error,
+            {
+                match value {
+                    #(#names => Ok(#rust_type::#rust_variants),)*
+                    _ => Err(serde::de::Error::unknown_variant(value, FIELDS)),
+                }
+            }
+        }
     }
+}
 
-    writeln!(
-        writer,
-        "{indent}_ => Err(serde::de::Error::unknown_variant(value, FIELDS)),",
-        indent = Indent(indent + 3)
-    )?;
-    writeln!(writer, "{}}}", Indent(indent + 2))?;
-    writeln!(writer, "{}}}", Indent(indent + 1))?;
-    writeln!(writer, "{}}}", Indent(indent))
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generator::format;
+
+    #[test]
+    fn test_enum_serialize() {
+        let variants = [
+            ("LEVEL_LOW".to_string(), 0, "Low".to_string()),
+            ("LEVEL_HIGH".to_string(), -1, "High".to_string()),
+        ];
+
+        assert_eq!(
+            format(
+                generate_enum_serialize("super::Level", &variants, true, &Crates::default())
+                    .unwrap()
+            ),
+            r#"impl serde::Serialize for super::Level {
+    #[allow(deprecated)]
+    fn serialize<S>(&self, serializer: S) -> ::core::result::Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let variant = match self {
+            Self::Low => 0,
+            Self::High => -1,
+        };
+        serializer.serialize_i32(variant)
+    }
+}
+"#
+        );
+
+        let err = generate_enum_serialize("super::Level<", &variants, true, &Crates::default())
+            .unwrap_err();
+        assert_eq!(err.to_string(), "invalid name \"super::Level<\"");
+    }
 }
@@ -0,0 +1,145 @@
+//! This module contains the code to generate a file assembling the output
+//! of prost and pbjson into a tree of modules mirroring the protobuf packages
+
+use proc_macro2::TokenStream;
+use quote::quote;
+
+use super::ident;
+use crate::descriptor::{Package, TypeName};
+
+/// A module of the generated file
+#[derive(Debug, Default)]
+struct Module {
+    /// The files included into this module
+    files: Vec<String>,
+    /// The child modules, in the order they were declared
+    children: Vec<(String, Self)>,
+}
+
+impl Module {
+    /// Returns the module at `path` relative to this module, declaring it if necessary
+    fn child(&mut self, path: &[String]) -> &mut Self {
+        let (name, rest) = match path.split_first() {
+            Some(split) => split,
+            None => return self,
+        };
+
+        let idx = match self.children.iter().position(|(n, _)| n == name) {
+            Some(idx) => idx,
+            None => {
+                self.children.push((name.clone(), Self::default()));
+                self.children.len() - 1
+            }
+        };
+        self.children[idx].1.child(rest)
+    }
+
+    fn to_tokens(&self, relative: bool) -> TokenStream {
+        let files = self.files.iter().map(|file_name| match relative {
+            true => quote!(include!(#file_name);),
+            false => {
+                let file_name = format!("/{}", file_name);
+                quote!(include!(concat!(env!("OUT_DIR"), #file_name));)
+            }
+        });
+
+        let children = self.children.iter().map(|(name, module)| {
+            let name = ident(name);
+            let module = module.to_tokens(relative);
+            quote! {
+                pub mod #name {
+                    #module
+                }
+            }
+        });
+
+        quote! {
+            #(#files)*
+            #(#children)*
+        }
+    }
+}
+
+/// Returns a `pub mod` for each element of the path of each of `packages`,
+/// nested according to the package hierarchy, that includes the prost and
+/// pbjson output for the package
+///
+/// If `relative`, the output is included relative to the generated file,
+/// otherwise relative to `OUT_DIR`
+pub fn generate_include_file(packages: &[Package], relative: bool) -> TokenStream {
+    let mut packages: Vec<_> = packages.iter().collect();
+    packages.sort();
+
+    let mut root = Module::default();
+    for package in packages {
+        let path: Vec<_> = package
+            .path()
+            .iter()
+            .map(TypeName::to_snake_case_ident)
+            .collect();
+
+        let module = root.child(&path);
+        module.files.push(format!("{}.rs", package));
+        module.files.push(format!("{}.serde.rs", package));
+    }
+    root.to_tokens(relative)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generator::format;
+
+    #[test]
+    fn test_include_file() {
+        let packages = [
+            Package::new("foo.bar.baz"),
+            Package::new(""),
+            Package::new("foo.bar"),
+            Package::new("test.abstract.type"),
+            Package::new("foo.qux"),
+        ];
+
+        assert_eq!(
+            format(generate_include_file(&packages, true)),
+            r#"include!("_.rs");
+include!("_.serde.rs");
+pub mod foo {
+    pub mod bar {
+        include!("foo.bar.rs");
+        include!("foo.bar.serde.rs");
+        pub mod baz {
+            include!("foo.bar.baz.rs");
+            include!("foo.bar.baz.serde.rs");
+        }
+    }
+    pub mod qux {
+        include!("foo.qux.rs");
+        include!("foo.qux.serde.rs");
+    }
+}
+pub mod test {
+    pub mod r#abstract {
+        pub mod r#type {
+            include!("test.r#abstract.r#type.rs");
+            include!("test.r#abstract.r#type.serde.rs");
+        }
+    }
+}
+"#
+        );
+
+        assert_eq!(
+            format(generate_include_file(&packages[..1], false)),
+            r#"pub mod foo {
+    pub mod bar {
+        pub mod baz {
+            include!(concat!(env!("OUT_DIR"), "/foo.bar.baz.rs"));
+            include!(concat!(env!("OUT_DIR"), "/foo.bar.baz.serde.rs"));
+        }
+    }
+}
+"#
+        );
+    }
+}
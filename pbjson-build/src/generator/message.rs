@@ -6,12 +6,15 @@
 //! Importantly:
 //! - numeric types can be decoded from either a string or number
 //! - 32-bit integers and floats are encoded as numbers
-//! - 64-bit integers are encoded as strings
+//! - NaN and infinite floats are encoded as the strings "NaN", "Infinity" and "-Infinity"
+//! - 64-bit integers are encoded as strings, unless configured otherwise
 //! - repeated fields are encoded as arrays
 //! - bytes are base64 encoded
 //! - messages and maps are encoded as objects
 //! - fields are lowerCamelCase except where overridden by the proto definition
 //! - default values are not emitted on encode
+//! - null decodes as the default value, except for google.protobuf.Value
+//! - enumeration values without a known variant are encoded as integers
 //! - unrecognised fields error on decode
 //!
 //! Note: This will not generate code to correctly serialize/deserialize well-known-types
@@ -21,1034 +24,1379 @@
 //!
 //! [1]: https://developers.google.com/protocol-buffers/docs/proto3#json
 
-use std::io::{Result, Write};
+use proc_macro2::{Ident, Literal, TokenStream};
+use quote::{format_ident, quote, ToTokens};
 
-use crate::message::{Field, FieldModifier, FieldType, Message, OneOf, ScalarType};
+use crate::message::{DefaultValue, Field, FieldModifier, FieldType, Message, OneOf, ScalarType};
 
-use super::{
-    write_deserialize_end, write_deserialize_start, write_serialize_end, write_serialize_start,
-    Indent,
-};
+use super::{deserialize_impl, fields_array, ident, parse_type, serialize_impl, Crates};
+use crate::config::{Int64Encoding, Options};
 use crate::descriptor::TypePath;
-use crate::escape::escape_type;
-use crate::generator::write_fields_array;
+use crate::error::{Error, Result};
+use crate::features::EnumType;
 use crate::resolver::Resolver;
 
-pub fn generate_message<W: Write>(
+pub fn generate_message(
     resolver: &Resolver<'_>,
     message: &Message,
-    writer: &mut W,
-    ignore_unknown_fields: bool,
-    btree_map_paths: &[String],
-    emit_fields: bool,
-    preserve_proto_field_names: bool,
-) -> Result<()> {
-    let rust_type = resolver.rust_type(&message.path);
+    options: &Options,
+) -> Result<TokenStream> {
+    let mut tokens = generate_message_serialize(resolver, message, options)?;
+    tokens.extend(generate_message_deserialize(resolver, message, options)?);
+    Ok(tokens)
+}
 
-    // Generate Serialize
-    write_serialize_start(0, &rust_type, writer)?;
-    write_message_serialize(
-        resolver,
-        2,
-        message,
-        writer,
-        emit_fields,
-        preserve_proto_field_names,
-    )?;
-    write_serialize_end(0, writer)?;
+/// Generates the Serialize implementation of `message`
+pub fn generate_message_serialize(
+    resolver: &Resolver<'_>,
+    message: &Message,
+    options: &Options,
+) -> Result<TokenStream> {
+    let rust_type = parse_type(&resolver.rust_type(&message.path))?;
+    let body = message_serialize(resolver, message, options)?;
+    Ok(serialize_impl(&rust_type, body, resolver.crates()))
+}
 
-    // Generate Deserialize
-    write_deserialize_start(0, &rust_type, writer)?;
-    write_deserialize_message(
-        resolver,
-        2,
-        message,
-        &rust_type,
-        writer,
-        ignore_unknown_fields,
-        btree_map_paths,
-    )?;
-    write_deserialize_end(0, writer)?;
-    Ok(())
+/// Generates the Deserialize implementation of `message`
+pub fn generate_message_deserialize(
+    resolver: &Resolver<'_>,
+    message: &Message,
+    options: &Options,
+) -> Result<TokenStream> {
+    let rust_type = parse_type(&resolver.rust_type(&message.path))?;
+    let body = deserialize_message(resolver, message, &rust_type, options)?;
+    Ok(deserialize_impl(&rust_type, body, resolver.crates()))
 }
 
-fn write_field_empty_predicate<W: Write>(
-    member: &Field,
-    writer: &mut W,
-    emit_fields: bool,
-) -> Result<()> {
+fn field_empty_predicate(member: &Field, emit_fields: bool) -> TokenStream {
     if emit_fields {
-        return write!(writer, "true");
+        return quote!(true);
     }
 
+    let field = ident(&member.rust_field_name());
     match (&member.field_type, &member.field_modifier) {
         (_, FieldModifier::Required) => unreachable!(),
         (_, FieldModifier::Repeated)
         | (FieldType::Map(_, _), _)
         | (FieldType::Scalar(ScalarType::String), FieldModifier::UseDefault)
         | (FieldType::Scalar(ScalarType::Bytes), FieldModifier::UseDefault) => {
-            write!(writer, "!self.{}.is_empty()", member.rust_field_name())
+            quote!(!self.#field.is_empty())
         }
         (_, FieldModifier::Optional) | (FieldType::Message(_), _) => {
-            write!(writer, "self.{}.is_some()", member.rust_field_name())
+            quote!(self.#field.is_some())
         }
         (FieldType::Scalar(ScalarType::F64), FieldModifier::UseDefault)
         | (FieldType::Scalar(ScalarType::F32), FieldModifier::UseDefault) => {
-            write!(writer, "self.{} != 0.", member.rust_field_name())
-        }
-        (FieldType::Scalar(ScalarType::Bool), FieldModifier::UseDefault) => {
-            write!(writer, "self.{}", member.rust_field_name())
+            quote!(self.#field != 0.)
         }
-        (FieldType::Enum(_), FieldModifier::UseDefault)
+        (FieldType::Scalar(ScalarType::Bool), FieldModifier::UseDefault) => quote!(self.#field),
+        (FieldType::Enum(_, _), FieldModifier::UseDefault)
         | (FieldType::Scalar(ScalarType::I64), FieldModifier::UseDefault)
         | (FieldType::Scalar(ScalarType::I32), FieldModifier::UseDefault)
         | (FieldType::Scalar(ScalarType::U32), FieldModifier::UseDefault)
         | (FieldType::Scalar(ScalarType::U64), FieldModifier::UseDefault) => {
-            write!(writer, "self.{} != 0", member.rust_field_name())
+            quote!(self.#field != 0)
         }
     }
 }
 
-fn write_message_serialize<W: Write>(
+fn message_serialize(
     resolver: &Resolver<'_>,
-    indent: usize,
     message: &Message,
-    writer: &mut W,
-    emit_fields: bool,
-    preserve_proto_field_names: bool,
-) -> Result<()> {
-    write_struct_serialize_start(indent, message, writer, emit_fields)?;
+    options: &Options,
+) -> Result<TokenStream> {
+    let start = struct_serialize_start(resolver, message, options)?;
 
-    for field in &message.fields {
-        write_serialize_field(
+    let fields = message.fields.iter().map(|field| {
+        serialize_field(
             resolver,
-            indent,
             field,
-            writer,
-            emit_fields,
-            preserve_proto_field_names,
-        )?;
-    }
-
-    for one_of in &message.one_ofs {
-        write_serialize_one_of(indent, resolver, one_of, writer, preserve_proto_field_names)?;
-    }
+            serde_with(resolver, options, message, field)?.as_ref(),
+            options
+                .emit_fields
+                .enabled_for_field(&message.path, &field.name),
+            options
+                .preserve_proto_field_names
+                .enabled_for_field(&message.path, &field.name),
+            int64_encoding(options, &message.path, &field.name),
+        )
+    });
+    let fields = fields.collect::<Result<Vec<_>>>()?;
+
+    let one_ofs = message
+        .one_ofs
+        .iter()
+        .map(|one_of| serialize_one_of(resolver, message, one_of, options))
+        .collect::<Result<Vec<_>>>()?;
 
-    write_struct_serialize_end(indent, writer)
+    let extensions = message
+        .extensions
+        .iter()
+        .map(|extension| serialize_extension(resolver, extension, options, &message.path))
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(quote! {
+        #start
+        #(#fields)*
+        #(#one_ofs)*
+        #(#extensions)*
+        struct_ser.end()
+    })
 }
 
-fn write_struct_serialize_start<W: Write>(
-    indent: usize,
+fn struct_serialize_start(
+    resolver: &Resolver<'_>,
     message: &Message,
-    writer: &mut W,
-    emit_fields: bool,
-) -> Result<()> {
-    writeln!(writer, "{}use serde::ser::SerializeStruct;", Indent(indent))?;
-
+    options: &Options,
+) -> Result<TokenStream> {
     let required_len = message
         .fields
         .iter()
         .filter(|member| member.field_modifier.is_required())
         .count();
 
-    if required_len != message.fields.len() || !message.one_ofs.is_empty() {
-        writeln!(writer, "{}let mut len = {};", Indent(indent), required_len)?;
+    let len = Literal::usize_unsuffixed(required_len);
+    let len = if required_len != message.fields.len()
+        || !message.one_ofs.is_empty()
+        || !message.extensions.is_empty()
+    {
+        quote!(let mut len = #len;)
     } else {
-        writeln!(writer, "{}let len = {};", Indent(indent), required_len)?;
-    }
+        quote!(let len = #len;)
+    };
 
-    for field in &message.fields {
-        if field.field_modifier.is_required() {
-            continue;
-        }
-        write!(writer, "{}if ", Indent(indent))?;
-        write_field_empty_predicate(field, writer, emit_fields)?;
-        writeln!(writer, " {{")?;
-        writeln!(writer, "{}len += 1;", Indent(indent + 1))?;
-        writeln!(writer, "{}}}", Indent(indent))?;
-    }
+    let fields = message
+        .fields
+        .iter()
+        .filter(|field| !field.field_modifier.is_required())
+        .map(|field| {
+            let emit_fields = options
+                .emit_fields
+                .enabled_for_field(&message.path, &field.name);
+            field_empty_predicate(field, emit_fields)
+        });
+
+    let one_ofs = message.one_ofs.iter().map(|one_of| {
+        let field = ident(&one_of.rust_field_name());
+        quote!(self.#field.is_some())
+    });
+
+    let extensions = message
+        .extensions
+        .iter()
+        .map(|extension| {
+            let getter = extension_getter(resolver, extension)?;
+            Ok(quote!(#getter.is_some()))
+        })
+        .collect::<Result<Vec<_>>>()?;
 
-    for one_of in &message.one_ofs {
-        writeln!(
-            writer,
-            "{}if self.{}.is_some() {{",
-            Indent(indent),
-            one_of.rust_field_name()
-        )?;
-        writeln!(writer, "{}len += 1;", Indent(indent + 1))?;
-        writeln!(writer, "{}}}", Indent(indent))?;
-    }
+    let predicates = fields.chain(one_ofs).chain(extensions);
 
-    if !message.fields.is_empty() || !message.one_ofs.is_empty() {
-        writeln!(
-            writer,
-            "{}let mut struct_ser = serializer.serialize_struct(\"{}\", len)?;",
-            Indent(indent),
-            message.path
-        )?;
+    let name = message.path.to_string();
+    let struct_ser = if !message.fields.is_empty()
+        || !message.one_ofs.is_empty()
+        || !message.extensions.is_empty()
+    {
+        quote!(let mut struct_ser = serializer.serialize_struct(#name, len)?;)
     } else {
-        writeln!(
-            writer,
-            "{}let struct_ser = serializer.serialize_struct(\"{}\", len)?;",
-            Indent(indent),
-            message.path
-        )?;
-    }
-    Ok(())
-}
+        quote!(let struct_ser = serializer.serialize_struct(#name, len)?;)
+    };
 
-fn write_struct_serialize_end<W: Write>(indent: usize, writer: &mut W) -> Result<()> {
-    writeln!(writer, "{}struct_ser.end()", Indent(indent))
+    Ok(quote! {
+        use serde::ser::SerializeStruct;
+        #len
+        #(
+            if #predicates {
+                len += 1;
+            }
+        )*
+        #struct_ser
+    })
 }
 
-fn write_decode_variant<W: Write>(
+fn encode_variant(
     resolver: &Resolver<'_>,
-    indent: usize,
-    value: &str,
+    value: TokenStream,
     path: &TypePath,
-    writer: &mut W,
-) -> Result<()> {
-    writeln!(writer, "{}::try_from({})", resolver.rust_type(path), value)?;
-    write!(
-        writer,
-        "{}.map_err(|_| serde::ser::Error::custom(format!(\"Invalid variant {{}}\", {})))",
-        Indent(indent),
-        value
-    )
+) -> Result<TokenStream> {
+    let rust_type = parse_type(&resolver.rust_type(path))?;
+    Ok(quote!(pbjson::private::EnumSerialize::<#rust_type>::new(#value)))
 }
 
 /// Depending on the type of the field different ways of accessing field's value
 /// are needed - this allows decoupling the type serialization logic from the logic
 /// that manipulates its container e.g. Vec, Option, HashMap
-struct Variable<'a> {
+struct Variable {
     /// A reference to the field's value
-    as_ref: &'a str,
+    as_ref: TokenStream,
     /// The field's value
-    as_unref: &'a str,
+    as_unref: TokenStream,
     /// The field without any leading "&" or "*"
-    raw: &'a str,
+    raw: TokenStream,
 }
 
-fn write_serialize_variable<W: Write>(
+impl Variable {
+    /// The field `field` of `self`
+    fn field(field: &Field) -> Self {
+        let field = ident(&field.rust_field_name());
+        Self {
+            as_ref: quote!(&self.#field),
+            as_unref: quote!(self.#field),
+            raw: quote!(self.#field),
+        }
+    }
+
+    /// A reference to the value, named `v`
+    fn reference() -> Self {
+        Self {
+            as_ref: quote!(v),
+            as_unref: quote!(*v),
+            raw: quote!(v),
+        }
+    }
+}
+
+/// Returns the encoding of the 64-bit integers of the field `field` of the message `message`
+fn int64_encoding(options: &Options, message: &TypePath, field: &str) -> Int64Encoding {
+    options
+        .int64_encoding
+        .get_for_field(message, field)
+        .unwrap_or_default()
+}
+
+fn serialize_variable(
     resolver: &Resolver<'_>,
-    indent: usize,
     field: &Field,
-    variable: Variable<'_>,
-    writer: &mut W,
+    serde_with: Option<&SerdeWith>,
+    variable: &Variable,
     preserve_proto_field_names: bool,
-) -> Result<()> {
+    int64_encoding: Int64Encoding,
+) -> Result<TokenStream> {
     let json_name = field.json_name();
     let field_name = if preserve_proto_field_names {
-        field.name.as_str()
+        field.proto_name()
     } else {
         json_name.as_str()
     };
-    match &field.field_type {
-        FieldType::Scalar(scalar) => write_serialize_scalar_variable(
-            indent,
-            *scalar,
-            field.field_modifier,
+    if let Some(serde_with) = serde_with {
+        return Ok(serialize_with(
+            field,
+            serde_with,
             variable,
             field_name,
-            writer,
-        ),
-        FieldType::Enum(path) => {
-            write!(writer, "{}let v = ", Indent(indent))?;
-            match field.field_modifier {
-                FieldModifier::Repeated => {
-                    writeln!(writer, "{}.iter().cloned().map(|v| {{", variable.raw)?;
-                    write!(writer, "{}", Indent(indent + 1))?;
-                    write_decode_variant(resolver, indent + 2, "v", path, writer)?;
-                    writeln!(writer)?;
-                    write!(
-                        writer,
-                        "{}}}).collect::<Result<Vec<_>, _>>()",
-                        Indent(indent + 1)
-                    )
-                }
-                _ => write_decode_variant(resolver, indent + 1, variable.as_unref, path, writer),
-            }?;
-
-            writeln!(writer, "?;")?;
-            writeln!(
-                writer,
-                "{}struct_ser.serialize_field(\"{}\", &v)?;",
-                Indent(indent),
-                field_name,
-            )
-        }
-        FieldType::Map(_, value_type)
-            if matches!(
-                value_type.as_ref(),
-                FieldType::Scalar(ScalarType::I64)
-                    | FieldType::Scalar(ScalarType::U64)
-                    | FieldType::Scalar(ScalarType::Bytes)
-                    | FieldType::Enum(_)
-            ) =>
-        {
-            writeln!(
-                writer,
-                "{}let v: std::collections::HashMap<_, _> = {}.iter()",
-                Indent(indent),
-                variable.raw
-            )?;
+            resolver.crates(),
+        ));
+    }
 
-            match value_type.as_ref() {
-                FieldType::Scalar(ScalarType::I64) | FieldType::Scalar(ScalarType::U64) => {
-                    writeln!(
-                        writer,
-                        "{}.map(|(k, v)| (k, v.to_string())).collect();",
-                        Indent(indent + 1)
-                    )?;
-                }
-                FieldType::Scalar(ScalarType::Bytes) => {
-                    writeln!(
-                        writer,
-                        "{}.map(|(k, v)| (k, pbjson::private::base64::encode(v))).collect();",
-                        Indent(indent + 1)
-                    )?;
-                }
-                FieldType::Enum(path) => {
-                    writeln!(writer, "{}.map(|(k, v)| {{", Indent(indent + 1))?;
-                    write!(writer, "{}let v = ", Indent(indent + 2))?;
-                    write_decode_variant(resolver, indent + 3, "*v", path, writer)?;
-                    writeln!(writer, "?;")?;
-                    writeln!(writer, "{}Ok((k, v))", Indent(indent + 2))?;
-                    writeln!(
-                        writer,
-                        "{}}}).collect::<Result<_,_>>()?;",
-                        Indent(indent + 1)
-                    )?;
-                }
-                _ => unreachable!(),
-            }
-            writeln!(
-                writer,
-                "{}struct_ser.serialize_field(\"{}\", &v)?;",
-                Indent(indent),
-                field_name,
+    // The values are converted lazily by adapters from pbjson, so that serialization
+    // only allocates for the output
+    let raw = &variable.raw;
+    let value = match (&field.field_type, field.field_modifier) {
+        (FieldType::Map(_, value_type), _) => {
+            let element = Variable::reference();
+            serialize_value(resolver, value_type, &element, int64_encoding)?.map(
+                |value| quote!(pbjson::private::MapSerialize(#raw.iter().map(|(k, v)| (k, #value)))),
             )
         }
-        _ => {
-            writeln!(
-                writer,
-                "{}struct_ser.serialize_field(\"{}\", {})?;",
-                Indent(indent),
-                field_name,
-                variable.as_ref
+        (FieldType::Scalar(ScalarType::Bytes), FieldModifier::Repeated) => {
+            // Avoids a redundant closure, as the bytes are serialized by reference
+            Some(
+                quote!(pbjson::private::SeqSerialize(#raw.iter().map(pbjson::private::BytesSerialize))),
             )
         }
-    }
+        (field_type, FieldModifier::Repeated) => {
+            let element = Variable::reference();
+            serialize_value(resolver, field_type, &element, int64_encoding)?
+                .map(|value| quote!(pbjson::private::SeqSerialize(#raw.iter().map(|v| #value))))
+        }
+        (field_type, _) => serialize_value(resolver, field_type, variable, int64_encoding)?,
+    };
+
+    Ok(match value {
+        Some(value) => quote!(struct_ser.serialize_field(#field_name, &#value)?;),
+        None => {
+            let as_ref = &variable.as_ref;
+            quote!(struct_ser.serialize_field(#field_name, #as_ref)?;)
+        }
+    })
 }
 
-fn write_serialize_scalar_variable<W: Write>(
-    indent: usize,
-    scalar: ScalarType,
-    field_modifier: FieldModifier,
-    variable: Variable<'_>,
-    field_name: &str,
-    writer: &mut W,
-) -> Result<()> {
-    let conversion = match scalar {
-        ScalarType::I64 | ScalarType::U64 => "ToString::to_string",
-        ScalarType::Bytes => "pbjson::private::base64::encode",
-        _ => {
-            return writeln!(
-                writer,
-                "{}struct_ser.serialize_field(\"{}\", {})?;",
-                Indent(indent),
-                field_name,
-                variable.as_ref
-            );
+/// Returns the value of `variable`, of type `field_type`, to serialize in place of the
+/// variable itself, or `None` if the variable serializes as required by the JSON mapping
+fn serialize_value(
+    resolver: &Resolver<'_>,
+    field_type: &FieldType,
+    variable: &Variable,
+    int64_encoding: Int64Encoding,
+) -> Result<Option<TokenStream>> {
+    let as_unref = &variable.as_unref;
+    Ok(match field_type {
+        FieldType::Scalar(scalar) => serialize_scalar_value(*scalar, variable, int64_encoding),
+        FieldType::Enum(path, _) => Some(encode_variant(resolver, as_unref.clone(), path)?),
+        FieldType::Message(path) if is_int64_wrapper(path) => {
+            int64_wrapper_value(variable.raw.clone(), int64_encoding)
         }
+        _ => None,
+    })
+}
+
+/// Returns the value of the `google.protobuf.Int64Value` or `google.protobuf.UInt64Value`
+/// `wrapper` to serialize with `int64_encoding`, or `None` to serialize the wrapper itself
+fn int64_wrapper_value(wrapper: TokenStream, int64_encoding: Int64Encoding) -> Option<TokenStream> {
+    match int64_encoding {
+        // The wrappers serialize themselves as strings
+        Int64Encoding::String => None,
+        Int64Encoding::Number => Some(quote!(#wrapper.value)),
+        Int64Encoding::SafeNumber => {
+            Some(quote!(pbjson::private::SafeIntegerSerialize(#wrapper.value)))
+        }
+    }
+}
+
+/// Returns the rust type of the value of `extension`
+fn extension_rust_type(resolver: &Resolver<'_>, extension: &Field) -> Result<TokenStream> {
+    let crates = resolver.crates();
+    let rust_type = match &extension.field_type {
+        FieldType::Scalar(scalar) => scalar_rust_type(*scalar, crates),
+        FieldType::Enum(_, _) => quote!(i32),
+        FieldType::Message(path) => parse_type(&resolver.rust_type(path))?.into_token_stream(),
+        FieldType::Map(_, _) => unreachable!("extensions cannot be maps"),
     };
+    let alloc = &crates.alloc;
+    Ok(match extension.field_modifier {
+        FieldModifier::Repeated => quote!(#alloc::vec::Vec<#rust_type>),
+        _ => rust_type,
+    })
+}
 
-    match field_modifier {
-        FieldModifier::Repeated => {
-            writeln!(
-                writer,
-                "{}struct_ser.serialize_field(\"{}\", &{}.iter().map({}).collect::<Vec<_>>())?;",
-                Indent(indent),
-                field_name,
-                variable.raw,
-                conversion
-            )
+/// Returns the rust type of a value of type `scalar`
+fn scalar_rust_type(scalar: ScalarType, crates: &Crates) -> TokenStream {
+    let alloc = &crates.alloc;
+    match scalar {
+        ScalarType::F64 => quote!(f64),
+        ScalarType::F32 => quote!(f32),
+        ScalarType::I32 => quote!(i32),
+        ScalarType::I64 => quote!(i64),
+        ScalarType::U32 => quote!(u32),
+        ScalarType::U64 => quote!(u64),
+        ScalarType::Bool => quote!(bool),
+        ScalarType::String => quote!(#alloc::string::String),
+        ScalarType::Bytes => quote!(#alloc::vec::Vec<u8>),
+    }
+}
+
+/// Returns an expression reading the value of `extension` from `pbjson::ExtensionStorage`
+fn extension_getter(resolver: &Resolver<'_>, extension: &Field) -> Result<TokenStream> {
+    let rust_type = extension_rust_type(resolver, extension)?;
+    let name = extension_name(extension);
+    Ok(quote!(pbjson::ExtensionStorage::extension::<#rust_type>(self, #name)))
+}
+
+/// Returns the fully-qualified name of `extension`, without brackets
+fn extension_name(extension: &Field) -> &str {
+    extension.name.trim_start_matches('[').trim_end_matches(']')
+}
+
+fn serialize_extension(
+    resolver: &Resolver<'_>,
+    extension: &Field,
+    options: &Options,
+    message: &TypePath,
+) -> Result<TokenStream> {
+    let getter = extension_getter(resolver, extension)?;
+    // The bracketed extension name is used regardless of preserve_proto_field_names
+    let serialize = serialize_variable(
+        resolver,
+        extension,
+        None,
+        &Variable::reference(),
+        false,
+        options.int64_encoding.get(message).unwrap_or_default(),
+    )?;
+    Ok(quote! {
+        if let Some(v) = #getter {
+            let v = &*v;
+            #serialize
         }
-        _ => {
-            writeln!(
-                writer,
-                "{}#[allow(clippy::needless_borrow)]",
-                Indent(indent)
-            )?;
-            writeln!(
-                writer,
-                "{}struct_ser.serialize_field(\"{}\", {}(&{}).as_str())?;",
-                Indent(indent),
-                field_name,
-                conversion,
-                variable.raw,
-            )
+    })
+}
+
+/// Returns the value of `variable`, of type `scalar`, to serialize in place of the variable
+/// itself, or `None` if the variable serializes as required by the JSON mapping
+fn serialize_scalar_value(
+    scalar: ScalarType,
+    variable: &Variable,
+    int64_encoding: Int64Encoding,
+) -> Option<TokenStream> {
+    let as_unref = &variable.as_unref;
+    match scalar {
+        ScalarType::I64 | ScalarType::U64 => match int64_encoding {
+            Int64Encoding::String => Some(quote!(pbjson::private::Int64Serialize(#as_unref))),
+            Int64Encoding::Number => None,
+            Int64Encoding::SafeNumber => {
+                Some(quote!(pbjson::private::SafeIntegerSerialize(#as_unref)))
+            }
+        },
+        ScalarType::Bytes => {
+            let as_ref = &variable.as_ref;
+            Some(quote!(pbjson::private::BytesSerialize(#as_ref)))
+        }
+        ScalarType::F32 | ScalarType::F64 => {
+            Some(quote!(pbjson::private::FloatSerialize(#as_unref)))
         }
+        _ => None,
     }
 }
 
-fn write_serialize_field<W: Write>(
+fn serialize_field(
     resolver: &Resolver<'_>,
-    indent: usize,
     field: &Field,
-    writer: &mut W,
+    serde_with: Option<&SerdeWith>,
     emit_fields: bool,
     preserve_proto_field_names: bool,
-) -> Result<()> {
-    let as_ref = format!("&self.{}", field.rust_field_name());
-    let variable = Variable {
-        as_ref: as_ref.as_str(),
-        as_unref: &as_ref.as_str()[1..],
-        raw: &as_ref.as_str()[1..],
-    };
+    int64_encoding: Int64Encoding,
+) -> Result<TokenStream> {
+    let variable = Variable::field(field);
+    let as_unref = &variable.as_unref;
 
-    match &field.field_modifier {
-        FieldModifier::Required => {
-            write_serialize_variable(
+    Ok(match &field.field_modifier {
+        FieldModifier::Required => serialize_variable(
+            resolver,
+            field,
+            serde_with,
+            &variable,
+            preserve_proto_field_names,
+            int64_encoding,
+        )?,
+        FieldModifier::Optional if emit_fields && field.default_value.is_some() => {
+            // Emit the declared default if the field is not set
+            let default = default_value_expr(resolver, field)?.unwrap();
+            let serialize = serialize_variable(
                 resolver,
-                indent,
                 field,
-                variable,
-                writer,
+                serde_with,
+                &Variable::reference(),
                 preserve_proto_field_names,
+                int64_encoding,
             )?;
+            quote! {
+                {
+                    let default__ = #default;
+                    let v = #as_unref.as_ref().unwrap_or(&default__);
+                    #serialize
+                }
+            }
         }
         FieldModifier::Optional => {
-            writeln!(
-                writer,
-                "{}if let Some(v) = {}.as_ref() {{",
-                Indent(indent),
-                variable.as_unref
-            )?;
-            let variable = Variable {
-                as_ref: "v",
-                as_unref: "*v",
-                raw: "v",
-            };
-            write_serialize_variable(
+            let serialize = serialize_variable(
                 resolver,
-                indent + 1,
                 field,
-                variable,
-                writer,
+                serde_with,
+                &Variable::reference(),
                 preserve_proto_field_names,
+                int64_encoding,
             )?;
-            writeln!(writer, "{}}}", Indent(indent))?;
+            quote! {
+                if let Some(v) = #as_unref.as_ref() {
+                    #serialize
+                }
+            }
         }
         FieldModifier::Repeated | FieldModifier::UseDefault => {
-            write!(writer, "{}if ", Indent(indent))?;
-            write_field_empty_predicate(field, writer, emit_fields)?;
-            writeln!(writer, " {{")?;
-            write_serialize_variable(
+            let predicate = field_empty_predicate(field, emit_fields);
+            let serialize = serialize_variable(
                 resolver,
-                indent + 1,
                 field,
-                variable,
-                writer,
+                serde_with,
+                &variable,
                 preserve_proto_field_names,
+                int64_encoding,
             )?;
-            writeln!(writer, "{}}}", Indent(indent))?;
+            quote! {
+                if #predicate {
+                    #serialize
+                }
+            }
         }
-    }
-    Ok(())
+    })
 }
 
-fn write_serialize_one_of<W: Write>(
-    indent: usize,
+fn serialize_one_of(
     resolver: &Resolver<'_>,
+    message: &Message,
     one_of: &OneOf,
-    writer: &mut W,
-    preserve_proto_field_names: bool,
-) -> Result<()> {
-    writeln!(
-        writer,
-        "{}if let Some(v) = self.{}.as_ref() {{",
-        Indent(indent),
-        one_of.rust_field_name()
-    )?;
-
-    writeln!(writer, "{}match v {{", Indent(indent + 1))?;
-    for field in &one_of.fields {
-        writeln!(
-            writer,
-            "{}{}::{}(v) => {{",
-            Indent(indent + 2),
-            resolver.rust_type(&one_of.path),
-            field.rust_type_name(),
-        )?;
-        let variable = Variable {
-            as_ref: "v",
-            as_unref: "*v",
-            raw: "v",
-        };
-        write_serialize_variable(
+    options: &Options,
+) -> Result<TokenStream> {
+    let field_name = ident(&one_of.rust_field_name());
+    let rust_type = parse_type(&resolver.rust_type(&one_of.path))?;
+
+    let arms = one_of.fields.iter().map(|field| {
+        let variant = ident(&field.rust_type_name());
+        let serialize = serialize_variable(
             resolver,
-            indent + 3,
             field,
-            variable,
-            writer,
-            preserve_proto_field_names,
+            serde_with(resolver, options, message, field)?.as_ref(),
+            &Variable::reference(),
+            options
+                .preserve_proto_field_names
+                .enabled_for_field(&message.path, &field.name),
+            int64_encoding(options, &message.path, &field.name),
         )?;
-        writeln!(writer, "{}}}", Indent(indent + 2))?;
-    }
+        Ok(quote! {
+            #rust_type::#variant(v) => {
+                #serialize
+            }
+        })
+    });
+    let arms = arms.collect::<Result<Vec<_>>>()?;
 
-    writeln!(writer, "{}}}", Indent(indent + 1),)?;
-    writeln!(writer, "{}}}", Indent(indent))
+    Ok(quote! {
+        if let Some(v) = self.#field_name.as_ref() {
+            match v {
+                #(#arms)*
+            }
+        }
+    })
 }
 
-fn write_deserialize_message<W: Write>(
+/// Returns the local variable holding the value of the field `rust_field_name`
+fn field_variable(rust_field_name: &str) -> Ident {
+    format_ident!("{}__", rust_field_name)
+}
+
+fn deserialize_message(
     resolver: &Resolver<'_>,
-    indent: usize,
     message: &Message,
-    rust_type: &str,
-    writer: &mut W,
-    ignore_unknown_fields: bool,
-    btree_map_paths: &[String],
-) -> Result<()> {
-    write_deserialize_field_name(2, message, writer, ignore_unknown_fields)?;
-
-    writeln!(writer, "{}struct GeneratedVisitor;", Indent(indent))?;
-
-    writeln!(
-        writer,
-        r#"{indent}impl<'de> serde::de::Visitor<'de> for GeneratedVisitor {{
-{indent}    type Value = {rust_type};
-
-{indent}    fn expecting(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {{
-{indent}        formatter.write_str("struct {name}")
-{indent}    }}
-
-{indent}    fn visit_map<V>(self, mut map_: V) -> std::result::Result<{rust_type}, V::Error>
-{indent}        where
-{indent}            V: serde::de::MapAccess<'de>,
-{indent}    {{"#,
-        indent = Indent(indent),
-        name = message.path,
-        rust_type = rust_type,
-    )?;
-
-    for field in &message.fields {
-        writeln!(
-            writer,
-            "{}let mut {}__ = None;",
-            Indent(indent + 2),
-            field.rust_field_name(),
-        )?;
-    }
-
-    for one_of in &message.one_ofs {
-        writeln!(
-            writer,
-            "{}let mut {}__ = None;",
-            Indent(indent + 2),
-            one_of.rust_field_name(),
-        )?;
-    }
+    rust_type: &syn::Type,
+    options: &Options,
+) -> Result<TokenStream> {
+    let core = &resolver.crates().core;
+    let ignore_unknown_fields = options.ignore_unknown_fields.enabled(&message.path);
+    let field_name = deserialize_field_name(message, ignore_unknown_fields, resolver.crates());
+
+    let variables: Vec<_> = message
+        .fields
+        .iter()
+        .map(|field| field_variable(&field.rust_field_name()))
+        .chain(
+            message
+                .one_ofs
+                .iter()
+                .map(|one_of| field_variable(&one_of.rust_field_name())),
+        )
+        .chain((0..message.extensions.len()).map(extension_variable))
+        .collect();
 
-    if !message.fields.is_empty() || !message.one_ofs.is_empty() {
-        writeln!(
-            writer,
-            "{}while let Some(k) = map_.next_key()? {{",
-            Indent(indent + 2)
-        )?;
+    let ignore_unknown_enum_values = |field: &Field| {
+        options
+            .ignore_unknown_enum_values
+            .enabled_for_field(&message.path, &field.name)
+    };
+    let ignore_unknown_extension_enum_values =
+        options.ignore_unknown_enum_values.enabled(&message.path);
 
-        writeln!(writer, "{}match k {{", Indent(indent + 3))?;
+    // The variables of the fields whose value may be dropped, and which therefore
+    // track whether the field is present separately to detect duplicates
+    let seen_variables: Vec<_> = message
+        .fields
+        .iter()
+        .filter(|field| may_drop_value(field, ignore_unknown_enum_values(field)))
+        .map(|field| field_variable(&field.rust_field_name()))
+        .chain(
+            message
+                .one_ofs
+                .iter()
+                .filter(|one_of| {
+                    one_of
+                        .fields
+                        .iter()
+                        .any(|field| may_drop_value(field, ignore_unknown_enum_values(field)))
+                })
+                .map(|one_of| field_variable(&one_of.rust_field_name())),
+        )
+        .chain(
+            message
+                .extensions
+                .iter()
+                .enumerate()
+                .filter(|(_, extension)| {
+                    may_drop_value(extension, ignore_unknown_extension_enum_values)
+                })
+                .map(|(idx, _)| extension_variable(idx)),
+        )
+        .collect();
 
-        let btree_map = btree_map_paths
+    let visit = if !message.fields.is_empty()
+        || !message.one_ofs.is_empty()
+        || !message.extensions.is_empty()
+    {
+        let one_of_fields = message
+            .one_ofs
             .iter()
-            .any(|prefix| message.path.prefix_match(prefix.as_ref()).is_some());
+            .flat_map(|one_of| one_of.fields.iter().map(move |field| (field, Some(one_of))));
 
-        for field in &message.fields {
-            write_deserialize_field(resolver, indent + 4, field, None, btree_map, writer)?;
-        }
-
-        for one_of in &message.one_ofs {
-            for field in &one_of.fields {
-                write_deserialize_field(
+        let fields = message
+            .fields
+            .iter()
+            .map(|field| (field, None))
+            .chain(one_of_fields)
+            .map(|(field, one_of)| {
+                deserialize_field(
                     resolver,
-                    indent + 4,
                     field,
-                    Some(one_of),
-                    btree_map,
-                    writer,
-                )?;
+                    one_of,
+                    serde_with(resolver, options, message, field)?.as_ref(),
+                    options
+                        .btree_map
+                        .enabled_for_field(&message.path, &field.name),
+                    ignore_unknown_enum_values(field),
+                    &seen_variables,
+                )
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let extensions = message
+            .extensions
+            .iter()
+            .enumerate()
+            .map(|(idx, extension)| {
+                deserialize_extension(
+                    resolver,
+                    idx,
+                    extension,
+                    ignore_unknown_extension_enum_values,
+                    &seen_variables,
+                )
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let skip_field = ignore_unknown_fields.then(|| {
+            quote! {
+                GeneratedField::__SkipField__ => {
+                    let _ = map_.next_value::<serde::de::IgnoredAny>()?;
+                }
+            }
+        });
+
+        quote! {
+            while let Some(k) = map_.next_key()? {
+                match k {
+                    #(#fields)*
+                    #(#extensions)*
+                    #skip_field
+                }
             }
         }
-
-        if ignore_unknown_fields {
-            writeln!(
-                writer,
-                "{}GeneratedField::__SkipField__ => {{",
-                Indent(indent + 4),
-            )?;
-            writeln!(
-                writer,
-                "{}let _ = map_.next_value::<serde::de::IgnoredAny>()?;",
-                Indent(indent + 5),
-            )?;
-            writeln!(writer, "{}}}", Indent(indent + 4))?;
-        }
-
-        writeln!(writer, "{}}}", Indent(indent + 3))?;
-        writeln!(writer, "{}}}", Indent(indent + 2))?;
     } else {
-        writeln!(
-            writer,
-            "{}while map_.next_key::<GeneratedField>()?.is_some() {{",
-            Indent(indent + 2)
-        )?;
-        writeln!(
-            writer,
-            "{}let _ = map_.next_value::<serde::de::IgnoredAny>()?;",
-            Indent(indent + 3)
-        )?;
-        writeln!(writer, "{}}}", Indent(indent + 2))?;
-    }
+        quote! {
+            while map_.next_key::<GeneratedField>()?.is_some() {
+                let _ = map_.next_value::<serde::de::IgnoredAny>()?;
+            }
+        }
+    };
 
-    writeln!(writer, "{}Ok({} {{", Indent(indent + 2), rust_type)?;
-    for field in &message.fields {
-        match field.field_modifier {
+    let fields = message.fields.iter().map(|field| {
+        let name = ident(&field.rust_field_name());
+        let variable = field_variable(&field.rust_field_name());
+        Ok(match field.field_modifier {
+            // Hydrate the declared default, matching the prost Default implementation
+            FieldModifier::Required if field.default_value.is_some() => {
+                let default = default_value_expr(resolver, field)?.unwrap();
+                match field.field_type {
+                    FieldType::Scalar(ScalarType::String | ScalarType::Bytes) => {
+                        quote!(#name: #variable.unwrap_or_else(|| #default))
+                    }
+                    _ => quote!(#name: #variable.unwrap_or(#default)),
+                }
+            }
             FieldModifier::Required => {
-                writeln!(
-                    writer,
-                    "{indent}{field}: {field}__.ok_or_else(|| serde::de::Error::missing_field(\"{json_name}\"))?,",
-                    indent = Indent(indent + 3),
-                    field = field.rust_field_name(),
-                    json_name = field.json_name()
-                )?;
+                let json_name = field.json_name();
+                quote! {
+                    #name: #variable.ok_or_else(|| serde::de::Error::missing_field(#json_name))?
+                }
             }
             FieldModifier::UseDefault | FieldModifier::Repeated => {
-                // Note: this currently does not hydrate optional proto2 fields with defaults
-                writeln!(
-                    writer,
-                    "{indent}{field}: {field}__.unwrap_or_default(),",
-                    indent = Indent(indent + 3),
-                    field = field.rust_field_name()
-                )?;
-            }
-            _ => {
-                writeln!(
-                    writer,
-                    "{indent}{field}: {field}__,",
-                    indent = Indent(indent + 3),
-                    field = field.rust_field_name()
-                )?;
+                quote!(#name: #variable.unwrap_or_default())
             }
+            _ => quote!(#name: #variable),
+        })
+    });
+    let fields = fields.collect::<Result<Vec<_>>>()?;
+
+    let one_ofs = message.one_ofs.iter().map(|one_of| {
+        let name = ident(&one_of.rust_field_name());
+        let variable = field_variable(&one_of.rust_field_name());
+        quote!(#name: #variable)
+    });
+
+    let fields = fields.into_iter().chain(one_ofs);
+    let result = if message.extensions.is_empty() {
+        quote! {
+            Ok(#rust_type {
+                #(#fields,)*
+            })
         }
-    }
-    for one_of in &message.one_ofs {
-        writeln!(
-            writer,
-            "{indent}{field}: {field}__,",
-            indent = Indent(indent + 3),
-            field = one_of.rust_field_name(),
-        )?;
-    }
+    } else {
+        let extensions = message
+            .extensions
+            .iter()
+            .enumerate()
+            .map(|(idx, extension)| {
+                let variable = extension_variable(idx);
+                let rust_type = extension_rust_type(resolver, extension)?;
+                let name = extension_name(extension);
+                Ok(quote! {
+                    if let Some(v) = #variable {
+                        pbjson::ExtensionStorage::set_extension::<#rust_type>(&mut message, #name, v);
+                    }
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
 
-    writeln!(writer, "{}}})", Indent(indent + 2))?;
-    writeln!(writer, "{}}}", Indent(indent + 1))?;
-    writeln!(writer, "{}}}", Indent(indent))?;
-    writeln!(
-        writer,
-        "{}deserializer.deserialize_struct(\"{}\", FIELDS, GeneratedVisitor)",
-        Indent(indent),
-        message.path
-    )
+        quote! {
+            let mut message = #rust_type {
+                #(#fields,)*
+            };
+            #(#extensions)*
+            Ok(message)
+        }
+    };
+
+    let seen_flags = seen_variables.iter().map(seen_variable);
+    let expecting = format!("struct {}", message.path);
+    let name = message.path.to_string();
+    Ok(quote! {
+        #field_name
+        struct GeneratedVisitor;
+        impl<'de> serde::de::Visitor<'de> for GeneratedVisitor {
+            type Value = #rust_type;
+
+            fn expecting(&self, formatter: &mut #core::fmt::Formatter<'_>) -> #core::fmt::Result {
+                formatter.write_str(#expecting)
+            }
+
+            fn visit_map<V>(self, mut map_: V) -> #core::result::Result<#rust_type, V::Error>
+            where
+                V: serde::de::MapAccess<'de>,
+            {
+                #(let mut #variables = None;)*
+                #(let mut #seen_flags = false;)*
+                #visit
+                #result
+            }
+        }
+        deserializer.deserialize_struct(#name, FIELDS, GeneratedVisitor)
+    })
 }
 
-fn write_deserialize_field_name<W: Write>(
-    indent: usize,
+fn deserialize_field_name(
     message: &Message,
-    writer: &mut W,
     ignore_unknown_fields: bool,
-) -> Result<()> {
+    crates: &Crates,
+) -> TokenStream {
+    let core = &crates.core;
     let fields: Vec<_> = message
         .all_fields()
         .map(|field| {
             let json_name = field.json_name();
-            // only carry the original proto name if it's different from the provided json name
-            let proto_name =
-                Some(field.name.as_str()).filter(|proto_name| proto_name != &json_name);
-            (json_name, field.rust_type_name(), proto_name)
+            // only carry the original proto names if they're different from the provided json name
+            let mut proto_names: Vec<_> = [field.proto_name(), field.name.as_str()]
+                .into_iter()
+                .filter(|proto_name| proto_name != &json_name)
+                .collect();
+            proto_names.dedup();
+            (json_name, field_variant(field), proto_names)
         })
+        .chain(
+            message
+                .extensions
+                .iter()
+                .enumerate()
+                .map(|(idx, extension)| (extension.json_name(), extension_variant(idx), vec![])),
+        )
         .collect();
 
-    write_fields_array(
-        writer,
-        indent,
-        fields.iter().flat_map(|(json_name, _, proto_name)| {
-            proto_name.iter().copied().chain([json_name.as_str()])
-        }),
-    )?;
-    write_fields_enum(
-        writer,
-        indent,
-        fields.iter().map(|(_, type_name, _)| type_name.as_str()),
-        ignore_unknown_fields,
-    )?;
+    let fields_array = fields_array(fields.iter().flat_map(|(json_name, _, proto_names)| {
+        proto_names.iter().copied().chain([json_name.as_str()])
+    }));
 
-    writeln!(
-        writer,
-        r#"{indent}impl<'de> serde::Deserialize<'de> for GeneratedField {{
-{indent}    fn deserialize<D>(deserializer: D) -> std::result::Result<GeneratedField, D::Error>
-{indent}    where
-{indent}        D: serde::Deserializer<'de>,
-{indent}    {{
-{indent}        struct GeneratedVisitor;
-
-{indent}        impl<'de> serde::de::Visitor<'de> for GeneratedVisitor {{
-{indent}            type Value = GeneratedField;
-
-{indent}            fn expecting(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {{
-{indent}                write!(formatter, "expected one of: {{:?}}", &FIELDS)
-{indent}            }}
-
-{indent}            #[allow(unused_variables)]
-{indent}            fn visit_str<E>(self, value: &str) -> std::result::Result<GeneratedField, E>
-{indent}            where
-{indent}                E: serde::de::Error,
-{indent}            {{"#,
-        indent = Indent(indent)
-    )?;
+    let variants = fields.iter().map(|(_, variant, _)| variant);
+    let skip_field = ignore_unknown_fields.then(|| quote!(__SkipField__,));
 
-    if !fields.is_empty() {
-        writeln!(writer, "{}match value {{", Indent(indent + 4))?;
-        for (json_name, type_name, proto_name) in &fields {
-            if let Some(proto_name) = proto_name {
-                writeln!(
-                    writer,
-                    "{}\"{}\" | \"{}\" => Ok(GeneratedField::{}),",
-                    Indent(indent + 5),
-                    json_name,
-                    proto_name,
-                    escape_type(type_name.to_string())
-                )?;
-            } else {
-                writeln!(
-                    writer,
-                    "{}\"{}\" => Ok(GeneratedField::{}),",
-                    Indent(indent + 5),
-                    json_name,
-                    escape_type(type_name.to_string())
-                )?;
+    let unknown_field = match ignore_unknown_fields {
+        true => quote!(Ok(GeneratedField::__SkipField__)),
+        false => quote!(Err(serde::de::Error::unknown_field(value, FIELDS))),
+    };
+
+    // The value is only unused if every field is skipped
+    let value = match fields.is_empty() && ignore_unknown_fields {
+        true => format_ident!("_value"),
+        false => format_ident!("value"),
+    };
+
+    let visit_str = if !fields.is_empty() {
+        let arms = fields
+            .iter()
+            .map(|(json_name, variant, proto_names)| {
+                quote!(#json_name #(| #proto_names)* => Ok(GeneratedField::#variant),)
+            });
+        quote! {
+            match value {
+                #(#arms)*
+                _ => #unknown_field,
             }
         }
-        if ignore_unknown_fields {
-            writeln!(
-                writer,
-                "{}_ => Ok(GeneratedField::__SkipField__),",
-                Indent(indent + 5)
-            )?;
-        } else {
-            writeln!(
-                writer,
-                "{}_ => Err(serde::de::Error::unknown_field(value, FIELDS)),",
-                Indent(indent + 5)
-            )?;
-        }
-        writeln!(writer, "{}}}", Indent(indent + 4))?;
-    } else if ignore_unknown_fields {
-        writeln!(
-            writer,
-            "{}Ok(GeneratedField::__SkipField__)",
-            Indent(indent + 5)
-        )?;
     } else {
-        writeln!(
-            writer,
-            "{}Err(serde::de::Error::unknown_field(value, FIELDS))",
-            Indent(indent + 5)
-        )?;
-    }
+        unknown_field
+    };
 
-    writeln!(
-        writer,
-        r#"{indent}            }}
-{indent}        }}
-{indent}        deserializer.deserialize_identifier(GeneratedVisitor)
-{indent}    }}
-{indent}}}"#,
-        indent = Indent(indent)
-    )
-}
+    quote! {
+        #fields_array
 
-fn write_fields_enum<'a, W: Write, I: Iterator<Item = &'a str>>(
-    writer: &mut W,
-    indent: usize,
-    fields: I,
-    ignore_unknown_fields: bool,
-) -> Result<()> {
-    writeln!(
-        writer,
-        "{}#[allow(clippy::enum_variant_names)]",
-        Indent(indent)
-    )?;
-    writeln!(writer, "{}enum GeneratedField {{", Indent(indent))?;
-    for type_name in fields {
-        writeln!(
-            writer,
-            "{}{},",
-            Indent(indent + 1),
-            escape_type(type_name.to_string())
-        )?;
-    }
+        enum GeneratedField {
+            #(#variants,)*
+            #skip_field
+        }
+        impl<'de> serde::Deserialize<'de> for GeneratedField {
+            fn deserialize<D>(deserializer: D) -> #core::result::Result<GeneratedField, D::Error>
+            where
+                D: serde::Deserializer<'de>,
+            {
+                struct GeneratedVisitor;
+
+                impl<'de> serde::de::Visitor<'de> for GeneratedVisitor {
+                    type Value = GeneratedField;
+
+                    fn expecting(&self, formatter: &mut #core::fmt::Formatter<'_>) -> #core::fmt::Result {
+                        write!(formatter, "expected one of: {:?}", FIELDS)
+                    }
 
-    if ignore_unknown_fields {
-        writeln!(writer, "{}__SkipField__,", Indent(indent + 1))?;
+                    fn visit_str<E>(self, #value: &str) -> #core::result::Result<GeneratedField, E>
+                    where
+                        E: serde::de::Error,
+                    {
+                        #visit_str
+                    }
+                }
+                deserializer.deserialize_identifier(GeneratedVisitor)
+            }
+        }
     }
-
-    writeln!(writer, "{}}}", Indent(indent))
 }
 
-fn write_deserialize_field<W: Write>(
+fn deserialize_field(
     resolver: &Resolver<'_>,
-    indent: usize,
     field: &Field,
     one_of: Option<&OneOf>,
+    serde_with: Option<&SerdeWith>,
     btree_map: bool,
-    writer: &mut W,
-) -> Result<()> {
-    let field_name = match one_of {
-        Some(one_of) => one_of.rust_field_name(),
-        None => field.rust_field_name(),
+    ignore_unknown_enum_values: bool,
+    seen_variables: &[Ident],
+) -> Result<TokenStream> {
+    let variable = match one_of {
+        Some(one_of) => field_variable(&one_of.rust_field_name()),
+        None => field_variable(&field.rust_field_name()),
     };
-
+    let core = &resolver.crates().core;
     let json_name = field.json_name();
-    writeln!(
-        writer,
-        "{}GeneratedField::{} => {{",
-        Indent(indent),
-        field.rust_type_name()
-    )?;
-    writeln!(
-        writer,
-        "{}if {}__.is_some() {{",
-        Indent(indent + 1),
-        field_name
-    )?;
+    let variant = ident(&field.rust_type_name());
+    let field_variant = field_variant(field);
+
+    let value = match (serde_with, one_of) {
+        (Some(serde_with), _) => deserialize_with(resolver, field, one_of, serde_with, btree_map)?,
+        (None, Some(one_of)) => {
+            let rust_type = parse_type(&resolver.rust_type(&one_of.path))?;
+            let constructor = quote!(#rust_type::#variant);
+            match &field.field_type {
+                FieldType::Scalar(s) => match override_deserializer(*s) {
+                    Some(deserializer) => quote! {
+                        map_.next_value::<#core::option::Option<#deserializer>>()?
+                            .map(|x| #constructor(x.0))
+                    },
+                    None => quote! {
+                        map_.next_value::<#core::option::Option<_>>()?.map(#constructor)
+                    },
+                },
+                FieldType::Enum(path, enum_type) if ignore_unknown_enum_values => {
+                    let (deserializer, value) =
+                        enum_deserializer(resolver, path, *enum_type, format_ident!("x"))?;
+                    quote! {
+                        map_.next_value::<#core::option::Option<::pbjson::private::EnumDeserialize<#deserializer>>>()?
+                            .and_then(|x| x.0)
+                            .map(|x| #constructor(#value))
+                    }
+                }
+                FieldType::Enum(path, enum_type) => {
+                    let (deserializer, value) =
+                        enum_deserializer(resolver, path, *enum_type, format_ident!("x"))?;
+                    quote! {
+                        map_.next_value::<#core::option::Option<#deserializer>>()?
+                            .map(|x| #constructor(#value))
+                    }
+                }
+                FieldType::Message(path) if is_value(path) => {
+                    quote!(Some(#constructor(map_.next_value()?)))
+                }
+                FieldType::Message(_) => quote! {
+                    map_.next_value::<#core::option::Option<_>>()?.map(#constructor)
+                },
+                FieldType::Map(_, _) => unreachable!("one of cannot contain map fields"),
+            }
+        }
+        (None, None) => deserialize_value(resolver, field, btree_map, ignore_unknown_enum_values)?,
+    };
 
     // Note: this will report duplicate field if multiple value are specified for a one of
-    writeln!(
-        writer,
-        "{}return Err(serde::de::Error::duplicate_field(\"{}\"));",
-        Indent(indent + 2),
-        json_name
-    )?;
-    writeln!(writer, "{}}}", Indent(indent + 1))?;
-    write!(writer, "{}{}__ = ", Indent(indent + 1), field_name)?;
-
-    match one_of {
-        Some(one_of) => match &field.field_type {
-            FieldType::Scalar(s) => match override_deserializer(*s) {
-                Some(deserializer) => {
-                    write!(
-                        writer,
-                        "map_.next_value::<::std::option::Option<{}>>()?.map(|x| {}::{}(x.0))",
-                        deserializer,
-                        resolver.rust_type(&one_of.path),
-                        field.rust_type_name()
-                    )?;
-                }
-                None => {
-                    write!(
-                        writer,
-                        "map_.next_value::<::std::option::Option<_>>()?.map({}::{})",
-                        resolver.rust_type(&one_of.path),
-                        field.rust_type_name()
-                    )?;
+    let assign = assign_field(&variable, seen_variables, &json_name, value);
+    Ok(quote! {
+        GeneratedField::#field_variant => {
+            #assign
+        }
+    })
+}
+
+/// Returns true if a value of `field` may be dropped when deserializing, leaving the field
+/// unset although present
+fn may_drop_value(field: &Field, ignore_unknown_enum_values: bool) -> bool {
+    ignore_unknown_enum_values
+        && matches!(field.field_type, FieldType::Enum(_, _))
+        && !matches!(field.field_modifier, FieldModifier::Repeated)
+}
+
+/// Returns the local variable tracking whether the field of `variable` is present
+fn seen_variable(variable: &Ident) -> Ident {
+    format_ident!("seen_{}", variable)
+}
+
+/// Returns the statements assigning `value` to `variable`, the field `json_name`, returning
+/// a duplicate field error if it is already present
+fn assign_field(
+    variable: &Ident,
+    seen_variables: &[Ident],
+    json_name: &str,
+    value: TokenStream,
+) -> TokenStream {
+    match seen_variables.contains(variable) {
+        true => {
+            let seen = seen_variable(variable);
+            quote! {
+                if #seen {
+                    return Err(serde::de::Error::duplicate_field(#json_name));
                 }
-            },
-            FieldType::Enum(path) => {
-                write!(
-                    writer,
-                    "map_.next_value::<::std::option::Option<{}>>()?.map(|x| {}::{}(x as i32))",
-                    resolver.rust_type(path),
-                    resolver.rust_type(&one_of.path),
-                    field.rust_type_name()
-                )?;
+                #seen = true;
+                #variable = #value;
             }
-            FieldType::Message(_) => writeln!(
-                writer,
-                "map_.next_value::<::std::option::Option<_>>()?.map({}::{})",
-                resolver.rust_type(&one_of.path),
-                field.rust_type_name()
-            )?,
-            FieldType::Map(_, _) => unreachable!("one of cannot contain map fields"),
+        }
+        false => quote! {
+            if #variable.is_some() {
+                return Err(serde::de::Error::duplicate_field(#json_name));
+            }
+            #variable = #value;
         },
+    }
+}
+
+/// Returns the `GeneratedField` variant for `field`
+///
+/// The trailing underscores prevent the variants from sharing a common suffix, e.g. `Value`
+/// for fields named `foo_value` and `bar_value`, as well as any collision with the variants
+/// of extensions or skipped fields
+fn field_variant(field: &Field) -> Ident {
+    format_ident!("{}__", field.rust_type_name())
+}
+
+/// Returns the `GeneratedField` variant for the extension at `idx`
+fn extension_variant(idx: usize) -> Ident {
+    format_ident!("__Extension{}__", idx)
+}
+
+/// Returns the local variable holding the value of the extension at `idx`
+fn extension_variable(idx: usize) -> Ident {
+    format_ident!("extension_{}", idx)
+}
+
+fn deserialize_extension(
+    resolver: &Resolver<'_>,
+    idx: usize,
+    extension: &Field,
+    ignore_unknown_enum_values: bool,
+    seen_variables: &[Ident],
+) -> Result<TokenStream> {
+    let variant = extension_variant(idx);
+    let variable = extension_variable(idx);
+    let json_name = extension.json_name();
+    let value = deserialize_value(resolver, extension, false, ignore_unknown_enum_values)?;
+    let assign = assign_field(&variable, seen_variables, &json_name, value);
+    Ok(quote! {
+        GeneratedField::#variant => {
+            #assign
+        }
+    })
+}
 
-        None => match &field.field_type {
-            FieldType::Scalar(scalar) => {
-                write_encode_scalar_field(indent + 1, *scalar, field.field_modifier, writer)?;
+/// Returns an expression deserializing the value of `field` from `map_` into an `Option`
+fn deserialize_value(
+    resolver: &Resolver<'_>,
+    field: &Field,
+    btree_map: bool,
+    ignore_unknown_enum_values: bool,
+) -> Result<TokenStream> {
+    let Crates { core, alloc } = resolver.crates();
+    Ok(match &field.field_type {
+        FieldType::Scalar(scalar) => {
+            deserialize_scalar_value(*scalar, field.field_modifier, resolver.crates())
+        }
+        // A null value, or an unknown value, leaves the field unset
+        FieldType::Enum(path, enum_type) if ignore_unknown_enum_values => {
+            let (deserializer, value) =
+                enum_deserializer(resolver, path, *enum_type, format_ident!("x"))?;
+            match field.field_modifier {
+                FieldModifier::Repeated => quote! {
+                    map_.next_value::<#core::option::Option<#alloc::vec::Vec<::pbjson::private::EnumDeserialize<#deserializer>>>>()?
+                        .map(|x| x.into_iter().filter_map(|x| x.0.map(|x| #value)).collect())
+                },
+                _ => quote! {
+                    map_.next_value::<#core::option::Option<::pbjson::private::EnumDeserialize<#deserializer>>>()?
+                        .and_then(|x| x.0)
+                        .map(|x| #value)
+                },
+            }
+        }
+        // A null value leaves the field unset
+        FieldType::Enum(path, enum_type) => {
+            let (deserializer, value) =
+                enum_deserializer(resolver, path, *enum_type, format_ident!("x"))?;
+            match field.field_modifier {
+                FieldModifier::Repeated => quote! {
+                    map_.next_value::<#core::option::Option<#alloc::vec::Vec<#deserializer>>>()?
+                        .map(|x| x.into_iter().map(|x| #value).collect())
+                },
+                _ => quote! {
+                    map_.next_value::<#core::option::Option<#deserializer>>()?.map(|x| #value)
+                },
             }
-            FieldType::Enum(path) => match field.field_modifier {
-                FieldModifier::Optional => {
-                    write!(
-                        writer,
-                        "map_.next_value::<::std::option::Option<{}>>()?.map(|x| x as i32)",
-                        resolver.rust_type(path)
-                    )?;
+        }
+        // A null value leaves the field unset
+        FieldType::Map(key, value) => {
+            let map_type = map_type(btree_map, resolver.crates());
+            let (key_deserializer, map_k) = map_key_deserializer(*key, resolver.crates());
+            let (value_deserializer, map_v) = match value.as_ref() {
+                FieldType::Scalar(scalar) if scalar.is_numeric() => {
+                    let rust_type = scalar_rust_type(*scalar, resolver.crates());
+                    (
+                        quote!(::pbjson::private::NumberDeserialize<#rust_type>),
+                        quote!(v.0),
+                    )
                 }
-                FieldModifier::Repeated => {
-                    write!(
-                        writer,
-                        "Some(map_.next_value::<Vec<{}>>()?.into_iter().map(|x| x as i32).collect())",
-                        resolver.rust_type(path)
-                    )?;
+                FieldType::Scalar(ScalarType::Bytes) => {
+                    (quote!(::pbjson::private::BytesDeserialize<_>), quote!(v.0))
                 }
-                _ => {
-                    write!(
-                        writer,
-                        "Some(map_.next_value::<{}>()? as i32)",
-                        resolver.rust_type(path)
-                    )?;
+                FieldType::Enum(path, enum_type) if ignore_unknown_enum_values => {
+                    let (deserializer, value) =
+                        enum_deserializer(resolver, path, *enum_type, format_ident!("v"))?;
+                    (
+                        quote!(::pbjson::private::EnumDeserialize<#deserializer>),
+                        value,
+                    )
                 }
-            },
-            FieldType::Map(key, value) => {
-                write!(writer, "Some(")?;
-                writeln!(writer)?;
-                match btree_map {
-                    true => write!(
-                        writer,
-                        "{}map_.next_value::<std::collections::BTreeMap<",
-                        Indent(indent + 2),
-                    )?,
-                    false => write!(
-                        writer,
-                        "{}map_.next_value::<std::collections::HashMap<",
-                        Indent(indent + 2),
-                    )?,
+                FieldType::Enum(path, enum_type) => {
+                    enum_deserializer(resolver, path, *enum_type, format_ident!("v"))?
                 }
+                FieldType::Map(_, _) => unreachable!("protobuf disallows nested maps"),
+                _ => (quote!(_), quote!(v)),
+            };
 
-                let map_k = match key {
-                    ScalarType::Bytes | ScalarType::F32 | ScalarType::F64 => {
-                        panic!("protobuf disallows maps with floating point or bytes keys")
-                    }
-                    _ if key.is_numeric() => {
-                        write!(
-                            writer,
-                            "::pbjson::private::NumberDeserialize<{}>",
-                            key.rust_type()
-                        )?;
-                        "k.0"
-                    }
-                    _ => {
-                        write!(writer, "_")?;
-                        "k"
-                    }
-                };
-                write!(writer, ", ")?;
-                let map_v = match value.as_ref() {
-                    FieldType::Scalar(scalar) if scalar.is_numeric() => {
-                        write!(
-                            writer,
-                            "::pbjson::private::NumberDeserialize<{}>",
-                            scalar.rust_type()
-                        )?;
-                        "v.0"
-                    }
-                    FieldType::Scalar(ScalarType::Bytes) => {
-                        write!(writer, "::pbjson::private::BytesDeserialize<_>",)?;
-                        "v.0"
-                    }
-                    FieldType::Enum(path) => {
-                        write!(writer, "{}", resolver.rust_type(path))?;
-                        "v as i32"
-                    }
-                    FieldType::Map(_, _) => panic!("protobuf disallows nested maps"),
-                    _ => {
-                        write!(writer, "_")?;
-                        "v"
-                    }
-                };
-
-                writeln!(writer, ">>()?")?;
-                if map_k != "k" || map_v != "v" {
-                    writeln!(
-                        writer,
-                        "{}.into_iter().map(|(k,v)| ({}, {})).collect()",
-                        Indent(indent + 3),
-                        map_k,
-                        map_v,
-                    )?;
+            let next_value = quote! {
+                map_.next_value::<#core::option::Option<#map_type<#key_deserializer, #value_deserializer>>>()?
+            };
+            if ignore_unknown_enum_values && matches!(value.as_ref(), FieldType::Enum(_, _)) {
+                // Entries with an unknown enumeration value are dropped
+                quote! {
+                    #next_value
+                        .map(|x| x.into_iter().filter_map(|(k, v)| v.0.map(|v| (#map_k, #map_v))).collect())
                 }
-                write!(writer, "{})", Indent(indent + 1))?;
+            } else if map_k.to_string() != "k" || map_v.to_string() != "v" {
+                quote! {
+                    #next_value.map(|x| x.into_iter().map(|(k, v)| (#map_k, #map_v)).collect())
+                }
+            } else {
+                next_value
             }
-            FieldType::Message(_) => match field.field_modifier {
-                FieldModifier::Repeated => {
-                    // No explicit presence for repeated fields
-                    write!(writer, "Some(map_.next_value()?)")?;
+        }
+        // A google.protobuf.Value encodes null as NullValue, rather than the default
+        FieldType::Message(path)
+            if is_value(path) && !matches!(field.field_modifier, FieldModifier::Repeated) =>
+        {
+            quote!(Some(map_.next_value()?))
+        }
+        // A null value leaves the field unset
+        FieldType::Message(_) => quote!(map_.next_value()?),
+    })
+}
+
+/// Returns the type of a map field, depending on whether `btree_map` is enabled for it
+fn map_type(btree_map: bool, crates: &Crates) -> TokenStream {
+    let alloc = &crates.alloc;
+    match btree_map {
+        true => quote!(#alloc::collections::BTreeMap),
+        false => quote!(::std::collections::HashMap),
+    }
+}
+
+/// Returns the type to deserialize a map key of type `key` as, along with an
+/// expression converting such a key, named `k`, to the key of the map
+fn map_key_deserializer(key: ScalarType, crates: &Crates) -> (TokenStream, TokenStream) {
+    match key {
+        ScalarType::Bytes | ScalarType::F32 | ScalarType::F64 => {
+            unreachable!("protobuf disallows maps with floating point or bytes keys")
+        }
+        _ if key.is_numeric() => {
+            let rust_type = scalar_rust_type(key, crates);
+            (
+                quote!(::pbjson::private::NumberDeserialize<#rust_type>),
+                quote!(k.0),
+            )
+        }
+        _ => (quote!(_), quote!(k)),
+    }
+}
+
+/// Returns the type to deserialize a value of the enumeration `path` as, along with
+/// an expression converting such a value, named `variable`, to an `i32`
+///
+/// Open enumerations use a deserializer that also accepts integers without a
+/// corresponding variant, whereas closed enumerations reject them
+fn enum_deserializer(
+    resolver: &Resolver<'_>,
+    path: &TypePath,
+    enum_type: EnumType,
+    variable: Ident,
+) -> Result<(TokenStream, TokenStream)> {
+    let rust_type = parse_type(&resolver.rust_type(path))?;
+    Ok(match enum_type {
+        EnumType::Open => (
+            quote!(::pbjson::private::OpenEnumDeserialize<#rust_type>),
+            quote!(#variable.0),
+        ),
+        EnumType::Closed => (quote!(#rust_type), quote!(#variable as i32)),
+    })
+}
+
+/// A custom serde `with` module for the values of a field, see [`crate::Builder::serde_with`]
+struct SerdeWith {
+    /// The path of the module containing `serialize` and `deserialize` functions
+    module: syn::Path,
+    /// The rust type of a single value of the field
+    rust_type: TokenStream,
+}
+
+/// Returns the custom serde `with` module configured for `field` of `message`, if any
+fn serde_with(
+    resolver: &Resolver<'_>,
+    options: &Options,
+    message: &Message,
+    field: &Field,
+) -> Result<Option<SerdeWith>> {
+    let module = match options.serde_with(&message.path, field) {
+        Some(module) => module,
+        None => return Ok(None),
+    };
+
+    let value_type = match &field.field_type {
+        FieldType::Map(_, value_type) => value_type.as_ref(),
+        field_type => field_type,
+    };
+
+    let rust_type = match value_type {
+        FieldType::Scalar(ScalarType::Bytes)
+            if options.bytes.enabled_for_field(&message.path, &field.name) =>
+        {
+            quote!(::prost::bytes::Bytes)
+        }
+        FieldType::Scalar(scalar) => scalar_rust_type(*scalar, resolver.crates()),
+        FieldType::Enum(_, _) => quote!(i32),
+        FieldType::Message(path) => parse_type(&resolver.rust_type(path))?.into_token_stream(),
+        FieldType::Map(_, _) => unreachable!("protobuf disallows nested maps"),
+    };
+
+    let module = syn::parse_str(module).map_err(|_| Error::InvalidModule {
+        path: format!("{}.{}", message.path.full_name(), field.name),
+        module: module.to_string(),
+    })?;
+
+    Ok(Some(SerdeWith { module, rust_type }))
+}
+
+/// Serializes the value(s) of `field` with a custom serde `with` module, by way of
+/// a local wrapper type implementing `Serialize`
+fn serialize_with(
+    field: &Field,
+    serde_with: &SerdeWith,
+    variable: &Variable,
+    field_name: &str,
+    crates: &Crates,
+) -> TokenStream {
+    let core = &crates.core;
+    let SerdeWith { module, rust_type } = serde_with;
+    let raw = &variable.raw;
+    let value = match (&field.field_type, field.field_modifier) {
+        (FieldType::Map(_, _), _) => {
+            quote!(pbjson::private::MapSerialize(#raw.iter().map(|(k, v)| (k, SerializeWith(v)))))
+        }
+        (_, FieldModifier::Repeated) => {
+            quote!(pbjson::private::SeqSerialize(#raw.iter().map(SerializeWith)))
+        }
+        _ => {
+            let as_ref = &variable.as_ref;
+            quote!(SerializeWith(#as_ref))
+        }
+    };
+
+    quote! {
+        {
+            struct SerializeWith<'a>(&'a #rust_type);
+            impl serde::Serialize for SerializeWith<'_> {
+                fn serialize<S>(&self, serializer: S) -> #core::result::Result<S::Ok, S::Error>
+                where
+                    S: serde::Serializer,
+                {
+                    #module::serialize(self.0, serializer)
                 }
-                _ => write!(writer, "map_.next_value()?")?,
-            },
-        },
+            }
+            struct_ser.serialize_field(#field_name, &#value)?;
+        }
     }
-    writeln!(writer, ";")?;
-    writeln!(writer, "{}}}", Indent(indent))
 }
 
-fn override_deserializer(scalar: ScalarType) -> Option<&'static str> {
+/// Returns an expression deserializing the value of `field` from `map_` into an `Option`
+/// with a custom serde `with` module, by way of a local wrapper type implementing `Deserialize`
+fn deserialize_with(
+    resolver: &Resolver<'_>,
+    field: &Field,
+    one_of: Option<&OneOf>,
+    serde_with: &SerdeWith,
+    btree_map: bool,
+) -> Result<TokenStream> {
+    let Crates { core, alloc } = resolver.crates();
+    let SerdeWith { module, rust_type } = serde_with;
+    let value = match (one_of, &field.field_type, field.field_modifier) {
+        (Some(one_of), _, _) => {
+            let one_of_type = parse_type(&resolver.rust_type(&one_of.path))?;
+            let variant = ident(&field.rust_type_name());
+            quote! {
+                map_.next_value::<#core::option::Option<DeserializeWith>>()?
+                    .map(|x| #one_of_type::#variant(x.0))
+            }
+        }
+        (None, FieldType::Map(key, _), _) => {
+            let (key_deserializer, map_k) = map_key_deserializer(*key, resolver.crates());
+            let map_type = map_type(btree_map, resolver.crates());
+            quote! {
+                map_.next_value::<#core::option::Option<#map_type<#key_deserializer, DeserializeWith>>>()?
+                    .map(|x| x.into_iter().map(|(k, v)| (#map_k, v.0)).collect())
+            }
+        }
+        (None, _, FieldModifier::Repeated) => quote! {
+            map_.next_value::<#core::option::Option<#alloc::vec::Vec<DeserializeWith>>>()?
+                .map(|x| x.into_iter().map(|x| x.0).collect())
+        },
+        (None, _, _) => quote! {
+            map_.next_value::<#core::option::Option<DeserializeWith>>()?.map(|x| x.0)
+        },
+    };
+
+    Ok(quote! {
+        {
+            struct DeserializeWith(#rust_type);
+            impl<'de> serde::Deserialize<'de> for DeserializeWith {
+                fn deserialize<D>(deserializer: D) -> #core::result::Result<Self, D::Error>
+                where
+                    D: serde::Deserializer<'de>,
+                {
+                    #module::deserialize(deserializer).map(Self)
+                }
+            }
+            #value
+        }
+    })
+}
+
+/// Returns true if `path` is `google.protobuf.Value`, for which null is a valid value
+fn is_value(path: &TypePath) -> bool {
+    path.to_string() == "google.protobuf.Value"
+}
+
+fn is_int64_wrapper(path: &TypePath) -> bool {
+    let path = path.to_string();
+    path == "google.protobuf.Int64Value" || path == "google.protobuf.UInt64Value"
+}
+
+fn override_deserializer(scalar: ScalarType) -> Option<TokenStream> {
     match scalar {
-        ScalarType::Bytes => Some("::pbjson::private::BytesDeserialize<_>"),
-        _ if scalar.is_numeric() => Some("::pbjson::private::NumberDeserialize<_>"),
+        ScalarType::Bytes => Some(quote!(::pbjson::private::BytesDeserialize<_>)),
+        _ if scalar.is_numeric() => Some(quote!(::pbjson::private::NumberDeserialize<_>)),
         _ => None,
     }
 }
 
-fn write_encode_scalar_field<W: Write>(
-    indent: usize,
+fn deserialize_scalar_value(
     scalar: ScalarType,
     field_modifier: FieldModifier,
-    writer: &mut W,
-) -> Result<()> {
+    crates: &Crates,
+) -> TokenStream {
+    let Crates { core, alloc } = crates;
+    // A null value leaves the field unset, and so decodes as the default for
+    // fields without explicit presence
     let deserializer = match override_deserializer(scalar) {
         Some(deserializer) => deserializer,
-        None => {
-            return match field_modifier {
-                FieldModifier::Optional => {
-                    write!(writer, "map_.next_value()?")
-                }
-                _ => write!(writer, "Some(map_.next_value()?)"),
-            };
-        }
+        None => return quote!(map_.next_value()?),
     };
 
-    writeln!(writer)?;
-
     match field_modifier {
-        FieldModifier::Optional => {
-            writeln!(
-                writer,
-                "{}map_.next_value::<::std::option::Option<{}>>()?.map(|x| x.0)",
-                Indent(indent + 1),
-                deserializer
-            )?;
-        }
-        FieldModifier::Repeated => {
-            writeln!(
-                writer,
-                "{}Some(map_.next_value::<Vec<{}>>()?",
-                Indent(indent + 1),
-                deserializer
-            )?;
-            writeln!(
-                writer,
-                "{}.into_iter().map(|x| x.0).collect())",
-                Indent(indent + 2)
-            )?;
-        }
-        _ => {
-            writeln!(
-                writer,
-                "{}Some(map_.next_value::<{}>()?.0)",
-                Indent(indent + 1),
-                deserializer
-            )?;
-        }
+        FieldModifier::Repeated => quote! {
+            map_.next_value::<#core::option::Option<#alloc::vec::Vec<#deserializer>>>()?
+                .map(|x| x.into_iter().map(|x| x.0).collect())
+        },
+        _ => quote! {
+            map_.next_value::<#core::option::Option<#deserializer>>()?.map(|x| x.0)
+        },
     }
-    write!(writer, "{}", Indent(indent))
+}
+
+/// Returns a rust expression for the proto2 default value of `field`, if declared
+fn default_value_expr(resolver: &Resolver<'_>, field: &Field) -> Result<Option<TokenStream>> {
+    let default_value = match field.default_value.as_ref() {
+        Some(default_value) => default_value,
+        None => return Ok(None),
+    };
+    let alloc = &resolver.crates().alloc;
+    let expr = match default_value {
+        DefaultValue::F64(v) if v.is_nan() => quote!(f64::NAN),
+        DefaultValue::F64(v) if v.is_infinite() && *v > 0. => quote!(f64::INFINITY),
+        DefaultValue::F64(v) if v.is_infinite() => quote!(f64::NEG_INFINITY),
+        DefaultValue::F64(v) => Literal::f64_suffixed(*v).into_token_stream(),
+        DefaultValue::F32(v) if v.is_nan() => quote!(f32::NAN),
+        DefaultValue::F32(v) if v.is_infinite() && *v > 0. => quote!(f32::INFINITY),
+        DefaultValue::F32(v) if v.is_infinite() => quote!(f32::NEG_INFINITY),
+        DefaultValue::F32(v) => Literal::f32_suffixed(*v).into_token_stream(),
+        DefaultValue::I32(v) => Literal::i32_suffixed(*v).into_token_stream(),
+        DefaultValue::I64(v) => Literal::i64_suffixed(*v).into_token_stream(),
+        DefaultValue::U32(v) => Literal::u32_suffixed(*v).into_token_stream(),
+        DefaultValue::U64(v) => Literal::u64_suffixed(*v).into_token_stream(),
+        DefaultValue::Bool(v) => quote!(#v),
+        DefaultValue::String(v) => quote!(#alloc::string::String::from(#v)),
+        DefaultValue::Bytes(v) => {
+            let bytes = Literal::byte_string(v);
+            quote!(#bytes.as_slice().into())
+        }
+        DefaultValue::Enum(variant) => match &field.field_type {
+            FieldType::Enum(path, _) => {
+                let rust_type = parse_type(&resolver.rust_type(path))?;
+                let variant = ident(&resolver.rust_variant(path, variant));
+                quote!(#rust_type::#variant as i32)
+            }
+            _ => unreachable!("enumeration default for non-enumeration field"),
+        },
+    };
+    Ok(Some(expr))
 }
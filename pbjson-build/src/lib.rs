@@ -65,6 +65,27 @@
 //! The module will now contain the generated prost structs for your protobuf definition
 //! along with compliant implementations of [serde::Serialize][2] and [serde::Deserialize][3]
 //!
+//! Following the convention of prost, the implementations for types declared in files without
+//! a `package` are written to `_.serde.rs`, alongside the `_.rs` generated by prost
+//!
+//! Alternatively [`Builder::include_file`] generates a single file that declares a module
+//! for each package, and includes the output of both prost and pbjson into it
+//!
+//! # `no_std`
+//!
+//! The generated code only requires `core` and `alloc`, and so may be included in a `no_std`
+//! crate that disables the default `std` feature of `pbjson`, and of `pbjson-types`, which then
+//! requires its `btree-map` feature. As `HashMap` is not available without `std`, map fields
+//! must also be generated as a `BTreeMap` by both prost and pbjson
+//!
+//! ```ignore
+//! prost_build::Config::new().btree_map(["."]);
+//! pbjson_build::Builder::new().btree_map(["."]);
+//! ```
+//!
+//! The generated code refers to `core` as `::core` and to `alloc` through its re-export by
+//! `pbjson`, which can be changed with [`Builder::core_path`] and [`Builder::alloc_path`]
+//!
 //! [1]: https://docs.rs/prost-build
 //! [2]: https://docs.rs/serde/1.0.130/serde/trait.Serialize.html
 //! [3]: https://docs.rs/serde/1.0.130/serde/trait.Deserialize.html
@@ -79,19 +100,29 @@
     clippy::future_not_send
 )]
 
+use proc_macro2::TokenStream;
 use prost_types::FileDescriptorProto;
-use std::io::{BufWriter, Error, ErrorKind, Result, Write};
-use std::path::PathBuf;
+use std::io::{BufWriter, ErrorKind, Write};
+use std::path::{Path, PathBuf};
 
-use crate::descriptor::{Descriptor, Package};
+use crate::config::Options;
+use crate::descriptor::{Descriptor, Package, TypePath};
 use crate::message::resolve_message;
 use crate::{
-    generator::{generate_enum, generate_message},
+    generator::{format, generate_enum, generate_include_file, generate_message, Crates},
     resolver::Resolver,
 };
 
+pub use config::Int64Encoding;
+pub use error::{Error, Result};
+
+mod config;
+#[doc(hidden)]
+pub mod derive;
 mod descriptor;
+mod error;
 mod escape;
+mod features;
 mod generator;
 mod message;
 mod resolver;
@@ -101,13 +132,12 @@ pub struct Builder {
     descriptors: descriptor::DescriptorSet,
     exclude: Vec<String>,
     out_dir: Option<PathBuf>,
+    include_file: Option<PathBuf>,
     extern_paths: Vec<(String, String)>,
-    retain_enum_prefix: bool,
-    ignore_unknown_fields: bool,
-    btree_map_paths: Vec<String>,
-    emit_fields: bool,
-    use_integers_for_enums: bool,
-    preserve_proto_field_names: bool,
+    options: Options,
+    extensions: bool,
+    core_path: Option<String>,
+    alloc_path: Option<String>,
 }
 
 impl Builder {
@@ -128,6 +158,26 @@ impl Builder {
         self
     }
 
+    /// Configures the name of a file, written to the output directory by [`Self::build`],
+    /// that declares a tree of `pub mod` mirroring the protobuf packages and includes both
+    /// the prost and the pbjson output for each package, as with `prost_build::Config::include_file`
+    ///
+    /// This replaces a `pub mod` and two `include!` for each package with
+    ///
+    /// ```ignore
+    /// include!(concat!(env!("OUT_DIR"), "/_includes.rs"));
+    /// ```
+    ///
+    /// Only packages containing types generated by [`Self::build`], and not mapped to
+    /// an [`extern_path`](Self::extern_path), are included
+    pub fn include_file<P>(&mut self, path: P) -> &mut Self
+    where
+        P: Into<PathBuf>,
+    {
+        self.include_file = Some(path.into());
+        self
+    }
+
     /// Register an encoded `FileDescriptorSet` with this `Builder`
     pub fn register_descriptors(&mut self, descriptors: &[u8]) -> Result<&mut Self> {
         self.descriptors.register_encoded(descriptors)?;
@@ -135,9 +185,15 @@ impl Builder {
     }
 
     /// Register a decoded `FileDescriptor` with this `Builder`
-    pub fn register_file_descriptor(&mut self, file: FileDescriptorProto) -> &mut Self {
-        self.descriptors.register_file_descriptor(file);
-        self
+    ///
+    /// Registering a file identical to one already registered is a no-op, allowing
+    /// descriptor sets from several invocations of `protoc` to overlap
+    ///
+    /// As prost-types does not retain the edition of a file, files with
+    /// `syntax = "editions"` must instead be registered with [`Self::register_descriptors`]
+    pub fn register_file_descriptor(&mut self, file: FileDescriptorProto) -> Result<&mut Self> {
+        self.descriptors.register_file_descriptor(file)?;
+        Ok(self)
     }
 
     /// Don't generate code for the following type prefixes
@@ -151,7 +207,15 @@ impl Builder {
 
     /// Configures the code generator to not strip the enum name from variant names.
     pub fn retain_enum_prefix(&mut self) -> &mut Self {
-        self.retain_enum_prefix = true;
+        self.retain_enum_prefix_for(".", true)
+    }
+
+    /// Configures [`Self::retain_enum_prefix`] for the enumerations matching `path`
+    ///
+    /// Options configured for a path apply to the packages, types or fields it is a
+    /// prefix of, with the most specific path taking precedence
+    pub fn retain_enum_prefix_for(&mut self, path: impl Into<String>, enabled: bool) -> &mut Self {
+        self.options.retain_enum_prefix.insert(path, enabled);
         self
     }
 
@@ -169,62 +233,212 @@ impl Builder {
     /// Don't error out in the presence of unknown fields when deserializing,
     /// instead skip the field.
     pub fn ignore_unknown_fields(&mut self) -> &mut Self {
-        self.ignore_unknown_fields = true;
+        self.ignore_unknown_fields_for(".", true)
+    }
 
+    /// Configures [`Self::ignore_unknown_fields`] for the messages matching `path`,
+    /// see [`Self::retain_enum_prefix_for`]
+    pub fn ignore_unknown_fields_for(
+        &mut self,
+        path: impl Into<String>,
+        enabled: bool,
+    ) -> &mut Self {
+        self.options.ignore_unknown_fields.insert(path, enabled);
+        self
+    }
+
+    /// Don't error out in the presence of unknown enumeration values when deserializing,
+    /// instead treat singular fields as unset and drop the value from repeated and map fields.
+    pub fn ignore_unknown_enum_values(&mut self) -> &mut Self {
+        self.ignore_unknown_enum_values_for(".", true)
+    }
+
+    /// Configures [`Self::ignore_unknown_enum_values`] for the fields matching `path`,
+    /// see [`Self::retain_enum_prefix_for`]
+    pub fn ignore_unknown_enum_values_for(
+        &mut self,
+        path: impl Into<String>,
+        enabled: bool,
+    ) -> &mut Self {
+        self.options
+            .ignore_unknown_enum_values
+            .insert(path, enabled);
         self
     }
 
     /// Generate Rust BTreeMap implementations for Protobuf map type fields.
     pub fn btree_map<S: Into<String>, I: IntoIterator<Item = S>>(&mut self, paths: I) -> &mut Self {
-        self.btree_map_paths
-            .extend(paths.into_iter().map(Into::into));
+        for path in paths {
+            self.options.btree_map.insert(path, true);
+        }
         self
     }
 
     /// Output fields with their default values.
     pub fn emit_fields(&mut self) -> &mut Self {
-        self.emit_fields = true;
+        self.emit_fields_for(".", true)
+    }
+
+    /// Configures [`Self::emit_fields`] for the fields matching `path`,
+    /// see [`Self::retain_enum_prefix_for`]
+    pub fn emit_fields_for(&mut self, path: impl Into<String>, enabled: bool) -> &mut Self {
+        self.options.emit_fields.insert(path, enabled);
         self
     }
+
     // print integers instead of enum names.
     pub fn use_integers_for_enums(&mut self) -> &mut Self {
-        self.use_integers_for_enums = true;
+        self.use_integers_for_enums_for(".", true)
+    }
+
+    /// Configures [`Self::use_integers_for_enums`] for the enumerations matching `path`,
+    /// see [`Self::retain_enum_prefix_for`]
+    pub fn use_integers_for_enums_for(
+        &mut self,
+        path: impl Into<String>,
+        enabled: bool,
+    ) -> &mut Self {
+        self.options.use_integers_for_enums.insert(path, enabled);
+        self
+    }
+
+    /// Configures the JSON encoding of 64-bit integers, i.e. of `int64`, `uint64`, `sint64`,
+    /// `fixed64` and `sfixed64` fields and of `google.protobuf.Int64Value` and
+    /// `google.protobuf.UInt64Value` wrappers, defaults to [`Int64Encoding::String`]
+    ///
+    /// Integers are decoded from either strings or numbers regardless of this option
+    pub fn int64_encoding(&mut self, encoding: Int64Encoding) -> &mut Self {
+        self.int64_encoding_for(".", encoding)
+    }
+
+    /// Configures [`Self::int64_encoding`] for the fields matching `path`,
+    /// see [`Self::retain_enum_prefix_for`]
+    pub fn int64_encoding_for(
+        &mut self,
+        path: impl Into<String>,
+        encoding: Int64Encoding,
+    ) -> &mut Self {
+        self.options.int64_encoding.insert(path, encoding);
+        self
+    }
+
+    /// Serialize and deserialize the field `path`, e.g. `.my_package.MyMessage.my_field`, or
+    /// the fields of the type `path`, e.g. `.my_package.MyType`, with the functions of the rust
+    /// module `module`, as with serde's `#[serde(with = "module")]`
+    ///
+    /// The module must contain functions with the signatures
+    ///
+    /// ```ignore
+    /// pub fn serialize<S: serde::Serializer>(value: &T, serializer: S) -> Result<S::Ok, S::Error>
+    /// pub fn deserialize<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<T, D::Error>
+    /// ```
+    ///
+    /// Where `T` is the type of a single value, i.e. the element of a repeated field, the value
+    /// of a map field, or the contents of an optional field or a oneof. A module configured for
+    /// a field takes precedence over one configured for its type
+    pub fn serde_with(&mut self, path: impl Into<String>, module: impl Into<String>) -> &mut Self {
+        self.options.serde_with.insert(path.into(), module.into());
+        self
+    }
+
+    /// Configures the `bytes` fields matching `paths` as having been generated as
+    /// `bytes::Bytes`, as with `prost_build::Config::bytes`, rather than `Vec<u8>`
+    ///
+    /// This determines the type of the values passed to [`Self::serde_with`] modules
+    pub fn bytes<S: Into<String>, I: IntoIterator<Item = S>>(&mut self, paths: I) -> &mut Self {
+        for path in paths {
+            self.options.bytes.insert(path, true);
+        }
         self
     }
 
     /// Output fields with their original names as defined in their proto schemas, instead of
     /// lowerCamelCase
     pub fn preserve_proto_field_names(&mut self) -> &mut Self {
-        self.preserve_proto_field_names = true;
+        self.preserve_proto_field_names_for(".", true)
+    }
+
+    /// Configures [`Self::preserve_proto_field_names`] for the fields matching `path`,
+    /// see [`Self::retain_enum_prefix_for`]
+    pub fn preserve_proto_field_names_for(
+        &mut self,
+        path: impl Into<String>,
+        enabled: bool,
+    ) -> &mut Self {
+        self.options
+            .preserve_proto_field_names
+            .insert(path, enabled);
+        self
+    }
+
+    /// Serialize and deserialize proto2 extensions as fields named by the bracketed
+    /// fully-qualified extension name, e.g. `"[mypackage.my_extension]"`
+    ///
+    /// As prost does not generate storage for extensions, every message with a registered
+    /// extension must implement `pbjson::ExtensionStorage`
+    pub fn extensions(&mut self) -> &mut Self {
+        self.extensions = true;
+        self
+    }
+
+    /// Configures the path generated code refers to the `core` crate by, defaults to `::core`
+    pub fn core_path(&mut self, path: impl Into<String>) -> &mut Self {
+        self.core_path = Some(path.into());
+        self
+    }
+
+    /// Configures the path generated code refers to the `alloc` crate by, defaults to
+    /// `::pbjson::private::alloc`, which is re-exported by pbjson with or without `std`
+    ///
+    /// Map fields are generated as `std::collections::HashMap` unless configured
+    /// with [`Self::btree_map`], in which case they use the `BTreeMap` of this crate
+    pub fn alloc_path(&mut self, path: impl Into<String>) -> &mut Self {
+        self.alloc_path = Some(path.into());
         self
     }
 
     /// Generates code for all registered types where `prefixes` contains a prefix of
     /// the fully-qualified path of the type
     pub fn build<S: AsRef<str>>(&mut self, prefixes: &[S]) -> Result<()> {
-        let mut output: PathBuf = self.out_dir.clone().map(Ok).unwrap_or_else(|| {
+        let output: PathBuf = self.out_dir.clone().map(Ok).unwrap_or_else(|| {
             std::env::var_os("OUT_DIR")
                 .ok_or_else(|| {
-                    Error::new(ErrorKind::Other, "OUT_DIR environment variable is not set")
+                    std::io::Error::new(ErrorKind::Other, "OUT_DIR environment variable is not set")
                 })
                 .map(Into::into)
         })?;
-        output.push("FILENAME");
-
-        let write_factory = move |package: &Package| {
-            output.set_file_name(format!("{}.serde.rs", package));
-
+        let open = |file_name: &Path| {
             let file = std::fs::OpenOptions::new()
                 .write(true)
                 .truncate(true)
                 .create(true)
-                .open(&output)?;
+                .open(output.join(file_name))?;
 
             Ok(BufWriter::new(file))
         };
 
+        let write_factory = |package: &Package| open(Path::new(&format!("{}.serde.rs", package)));
+
         let writers = self.generate(prefixes, write_factory)?;
-        for (_, mut writer) in writers {
+        let mut packages = Vec::with_capacity(writers.len());
+        for (package, mut writer) in writers {
+            writer.flush()?;
+            packages.push(package);
+        }
+
+        if let Some(include_file) = &self.include_file {
+            // prost does not generate code for packages mapped to an extern path
+            packages.retain(|package| {
+                let path = TypePath::new(package.clone());
+                !self
+                    .extern_paths
+                    .iter()
+                    .any(|(prefix, _)| path.prefix_match(prefix).is_some())
+            });
+
+            let tokens = generate_include_file(&packages, self.out_dir.is_some());
+            let mut writer = open(include_file)?;
+            writer.write_all(format(tokens).as_bytes())?;
             writer.flush()?;
         }
 
@@ -236,11 +450,14 @@ impl Builder {
     /// This function is intended for use when writing output of code generation
     /// directly to output files is not desired. For most use cases inside a
     /// `build.rs` file, the [`build()`][Self::build] method should be preferred.
-    pub fn generate<S: AsRef<str>, W: Write, F: FnMut(&Package) -> Result<W>>(
+    pub fn generate<S: AsRef<str>, W: Write, F: FnMut(&Package) -> std::io::Result<W>>(
         &self,
         prefixes: &[S],
         mut write_factory: F,
     ) -> Result<Vec<(Package, W)>> {
+        self.validate_paths(prefixes)?;
+        let crates = Crates::new(self.core_path.as_deref(), self.alloc_path.as_deref())?;
+
         let iter = self.descriptors.iter().filter(move |(t, _)| {
             let exclude = self
                 .exclude
@@ -253,47 +470,147 @@ impl Builder {
         });
 
         // Exploit the fact descriptors is ordered to group together types from the same package
-        let mut ret: Vec<(Package, W)> = Vec::new();
+        let mut packages: Vec<(Package, TokenStream)> = Vec::new();
         for (type_path, descriptor) in iter {
-            let writer = match ret.last_mut() {
-                Some((package, writer)) if package == type_path.package() => writer,
+            let tokens = match packages.last_mut() {
+                Some((package, tokens)) if package == type_path.package() => tokens,
                 _ => {
-                    let package = type_path.package();
-                    ret.push((package.clone(), write_factory(package)?));
-                    &mut ret.last_mut().unwrap().1
+                    packages.push((type_path.package().clone(), TokenStream::new()));
+                    &mut packages.last_mut().unwrap().1
                 }
             };
 
             let resolver = Resolver::new(
                 &self.extern_paths,
                 type_path.package(),
-                self.retain_enum_prefix,
+                &self.options.retain_enum_prefix,
+                &crates,
             );
 
             match descriptor {
-                Descriptor::Enum(descriptor) => generate_enum(
+                Descriptor::Enum(descriptor) => tokens.extend(generate_enum(
                     &resolver,
                     type_path,
                     descriptor,
-                    writer,
-                    self.use_integers_for_enums,
-                )?,
+                    self.options.use_integers_for_enums.enabled(type_path),
+                )?),
                 Descriptor::Message(descriptor) => {
-                    if let Some(message) = resolve_message(&self.descriptors, descriptor) {
-                        generate_message(
-                            &resolver,
-                            &message,
-                            writer,
-                            self.ignore_unknown_fields,
-                            &self.btree_map_paths,
-                            self.emit_fields,
-                            self.preserve_proto_field_names,
-                        )?
+                    if let Some(mut message) = resolve_message(&self.descriptors, descriptor)? {
+                        if !self.extensions {
+                            message.extensions.clear();
+                        }
+
+                        tokens.extend(generate_message(&resolver, &message, &self.options)?)
                     }
                 }
             }
         }
 
+        let mut ret = Vec::with_capacity(packages.len());
+        for (package, tokens) in packages {
+            let mut writer = write_factory(&package)?;
+            writer.write_all(format(tokens).as_bytes())?;
+            ret.push((package, writer));
+        }
         Ok(ret)
     }
+
+    /// Returns an error if `prefixes`, or a path configured on this `Builder`, is not a
+    /// fully-qualified protobuf path starting with a '.'
+    fn validate_paths<S: AsRef<str>>(&self, prefixes: &[S]) -> Result<()> {
+        let paths = prefixes
+            .iter()
+            .map(AsRef::as_ref)
+            .chain(self.exclude.iter().map(String::as_str))
+            .chain(
+                self.extern_paths
+                    .iter()
+                    .map(|(proto_path, _)| proto_path.as_str()),
+            )
+            .chain(self.options.paths());
+
+        for path in paths {
+            if !path.starts_with('.') {
+                return Err(Error::InvalidName {
+                    file: None,
+                    name: path.to_string(),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prost_types::field_descriptor_proto::{Label, Type};
+    use prost_types::{DescriptorProto, FieldDescriptorProto};
+
+    /// Returns a builder with the message `foo.Bar`, with the field `baz`, registered
+    fn builder() -> Builder {
+        let mut builder = Builder::new();
+        builder
+            .register_file_descriptor(FileDescriptorProto {
+                name: Some("foo.proto".to_string()),
+                package: Some("foo".to_string()),
+                message_type: vec![DescriptorProto {
+                    name: Some("Bar".to_string()),
+                    field: vec![FieldDescriptorProto {
+                        name: Some("baz".to_string()),
+                        r#type: Some(Type::Int32 as i32),
+                        label: Some(Label::Optional as i32),
+                        ..Default::default()
+                    }],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            })
+            .unwrap();
+        builder
+    }
+
+    fn generate(builder: &Builder, prefixes: &[&str]) -> Result<Vec<(Package, Vec<u8>)>> {
+        builder.generate(prefixes, |_| Ok(Vec::new()))
+    }
+
+    #[test]
+    fn test_invalid_names() {
+        assert_eq!(generate(&builder(), &["."]).unwrap().len(), 1);
+
+        let err = generate(&builder(), &["foo"]).unwrap_err();
+        assert_eq!(err.to_string(), "invalid name \"foo\"");
+
+        let err = generate(builder().ignore_unknown_fields_for("foo.Bar", true), &["."]);
+        assert_eq!(err.unwrap_err().to_string(), "invalid name \"foo.Bar\"");
+
+        let err = generate(builder().extern_path(".foo", "::not a path"), &["."]).unwrap_err();
+        assert_eq!(err.to_string(), "invalid name \"::not a path::Bar\"");
+
+        // Existing build scripts returning std::io::Result can still use `?`
+        let err = std::io::Error::from(err);
+        assert_eq!(err.kind(), ErrorKind::Other);
+        assert_eq!(err.to_string(), "invalid name \"::not a path::Bar\"");
+    }
+
+    #[test]
+    fn test_invalid_serde_with_module() {
+        let err = generate(builder().serde_with(".foo.Bar.baz", "not a module"), &["."]);
+        assert_eq!(
+            err.unwrap_err().to_string(),
+            "invalid serde_with module \"not a module\" for foo.Bar.baz"
+        );
+    }
+
+    #[test]
+    fn test_crate_paths() {
+        let generated = generate(builder().core_path("::std").alloc_path("::std"), &["."]);
+        let (_, output) = generated.unwrap().pop().unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("::std::result::Result"));
+        assert!(!output.contains("core"));
+
+        let err = generate(builder().core_path("not a path"), &["."]).unwrap_err();
+        assert_eq!(err.to_string(), "invalid name \"not a path\"");
+    }
 }
@@ -9,8 +9,10 @@ use prost_types::{
     FieldDescriptorProto,
 };
 
-use crate::descriptor::{Descriptor, DescriptorSet, MessageDescriptor, Syntax, TypeName, TypePath};
+use crate::descriptor::{Descriptor, DescriptorSet, MessageDescriptor, TypeName, TypePath};
+use crate::error::{Error, Result};
 use crate::escape::{escape_ident, escape_type};
+use crate::features::{EnumType, Features, FieldPresence};
 
 #[derive(Debug, Clone, Copy)]
 pub enum ScalarType {
@@ -26,20 +28,6 @@ pub enum ScalarType {
 }
 
 impl ScalarType {
-    pub fn rust_type(&self) -> &'static str {
-        match self {
-            Self::F64 => "f64",
-            Self::F32 => "f32",
-            Self::I32 => "i32",
-            Self::I64 => "i64",
-            Self::U32 => "u32",
-            Self::U64 => "u64",
-            Self::Bool => "bool",
-            Self::String => "String",
-            Self::Bytes => "Vec<u8>",
-        }
-    }
-
     pub fn is_numeric(&self) -> bool {
         matches!(
             self,
@@ -51,7 +39,7 @@ impl ScalarType {
 #[derive(Debug, Clone)]
 pub enum FieldType {
     Scalar(ScalarType),
-    Enum(TypePath),
+    Enum(TypePath, EnumType),
     Message(TypePath),
     Map(ScalarType, Box<FieldType>),
 }
@@ -74,8 +62,28 @@ impl FieldModifier {
 pub struct Field {
     pub name: String,
     pub json_name: Option<String>,
+    /// The name of a proto2 group, used in place of `name` when preserving proto field names
+    pub group_name: Option<String>,
     pub field_modifier: FieldModifier,
     pub field_type: FieldType,
+    /// The proto2 default value, as declared with `[default = ...]`
+    pub default_value: Option<DefaultValue>,
+}
+
+/// A proto2 default value, parsed according to the type of the field
+#[derive(Debug, Clone)]
+pub enum DefaultValue {
+    F64(f64),
+    F32(f32),
+    I32(i32),
+    I64(i64),
+    U32(u32),
+    U64(u64),
+    Bool(bool),
+    String(String),
+    Bytes(Vec<u8>),
+    /// The protobuf name of an enumeration variant
+    Enum(String),
 }
 
 impl Field {
@@ -95,6 +103,11 @@ impl Field {
             .clone()
             .unwrap_or_else(|| self.name.to_lower_camel_case())
     }
+
+    /// Returns the name of this field when preserving proto field names
+    pub fn proto_name(&self) -> &str {
+        self.group_name.as_deref().unwrap_or(&self.name)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -116,6 +129,9 @@ pub struct Message {
     pub path: TypePath,
     pub fields: Vec<Field>,
     pub one_ofs: Vec<OneOf>,
+    /// The registered proto2 extensions of this message, named by their
+    /// bracketed fully-qualified name
+    pub extensions: Vec<Field>,
 }
 
 impl Message {
@@ -126,35 +142,91 @@ impl Message {
     }
 }
 
+/// A field being resolved, used to report errors
+#[derive(Debug, Clone, Copy)]
+struct FieldContext<'a> {
+    file: &'a str,
+    path: &'a TypePath,
+    field: &'a str,
+}
+
+impl<'a> FieldContext<'a> {
+    fn invalid(&self, reason: impl Into<String>) -> Error {
+        Error::InvalidField {
+            file: self.file.to_string(),
+            path: self.path.to_string(),
+            field: self.field.to_string(),
+            reason: reason.into(),
+        }
+    }
+
+    fn missing(&self, property: &'static str) -> Error {
+        Error::MissingProperty {
+            file: self.file.to_string(),
+            path: format!("{}.{}", self.path, self.field),
+            property,
+        }
+    }
+
+    fn unresolved(&self, type_name: &str) -> Error {
+        Error::UnresolvedType {
+            file: self.file.to_string(),
+            path: self.path.to_string(),
+            field: self.field.to_string(),
+            type_name: type_name.to_string(),
+        }
+    }
+}
+
 /// Resolve the provided message descriptor into a slightly less obtuse representation
 ///
 /// Returns None if the provided provided message is auto-generated
 pub fn resolve_message(
     descriptors: &DescriptorSet,
     message: &MessageDescriptor,
-) -> Option<Message> {
+) -> Result<Option<Message>> {
     if message.is_map() {
-        return None;
+        return Ok(None);
     }
 
     let mut fields = Vec::new();
     let mut one_of_fields = vec![Vec::new(); message.one_of.len()];
 
-    for field in &message.fields {
-        let field_type = field_type(descriptors, field);
-        let field_modifier = field_modifier(message, field, &field_type);
+    for (field, features) in message.fields.iter().zip(&message.field_features) {
+        let name = field.name.clone().ok_or_else(|| Error::MissingProperty {
+            file: message.file.clone(),
+            path: message.path.to_string(),
+            property: "field name",
+        })?;
+        let ctx = FieldContext {
+            file: &message.file,
+            path: &message.path,
+            field: &name,
+        };
+
+        let field_type = field_type(descriptors, ctx, &message.path, field)?;
+        let field_modifier = field_modifier(ctx, field, features, &field_type)?;
+        let default_value = match &field.default_value {
+            Some(value) => Some(parse_default_value(descriptors, ctx, &field_type, value)?),
+            None => None,
+        };
 
         let resolved = Field {
-            name: field.name.clone().expect("expected field to have name"),
+            name: name.clone(),
             json_name: field.json_name.clone(),
+            group_name: group_name(message, field, &field_type),
             field_type,
             field_modifier,
+            default_value,
         };
 
         // Treat synthetic one-of as normal
         let proto3_optional = field.proto3_optional.unwrap_or(false);
         match (field.oneof_index, proto3_optional) {
-            (Some(idx), false) => one_of_fields[idx as usize].push(resolved),
+            (Some(idx), false) => match one_of_fields.get_mut(idx as usize) {
+                Some(one_of) => one_of.push(resolved),
+                None => return Err(ctx.invalid(format!("oneof index {} out of range", idx))),
+            },
             _ => fields.push(resolved),
         }
     }
@@ -164,107 +236,574 @@ pub fn resolve_message(
     for (fields, descriptor) in one_of_fields.into_iter().zip(&message.one_of) {
         // Might be empty in the event of a synthetic one-of
         if !fields.is_empty() {
-            let name = descriptor.name.clone().expect("oneof with no name");
+            let name = match &descriptor.name {
+                Some(name) if !name.is_empty() && !name.contains('.') => name.clone(),
+                Some(name) => {
+                    return Err(Error::InvalidName {
+                        file: Some(message.file.clone()),
+                        name: name.clone(),
+                    })
+                }
+                None => {
+                    return Err(Error::MissingProperty {
+                        file: message.file.clone(),
+                        path: message.path.to_string(),
+                        property: "oneof name",
+                    })
+                }
+            };
             let path = message.path.child(TypeName::new(&name));
 
             one_ofs.push(OneOf { name, path, fields })
         }
     }
 
-    Some(Message {
+    let mut extensions = Vec::new();
+    for extension in descriptors.extensions() {
+        let ctx = FieldContext {
+            file: &extension.file,
+            path: &extension.scope,
+            field: extension.field.name(),
+        };
+
+        let extendee = extension.field.extendee();
+        match descriptors.resolve(&extension.scope, extendee) {
+            Some((path, _)) if path == &message.path => {}
+            Some(_) => continue,
+            None => return Err(ctx.unresolved(extendee)),
+        }
+
+        let name = format!("[{}]", extension.full_name);
+        extensions.push(Field {
+            json_name: Some(name.clone()),
+            group_name: None,
+            name,
+            field_type: field_type(descriptors, ctx, &extension.scope, &extension.field)?,
+            field_modifier: match label(ctx, &extension.field)? {
+                Label::Repeated => FieldModifier::Repeated,
+                _ => FieldModifier::Optional,
+            },
+            default_value: None,
+        })
+    }
+
+    Ok(Some(Message {
         path: message.path.clone(),
         fields,
         one_ofs,
-    })
+        extensions,
+    }))
+}
+
+fn label(ctx: FieldContext<'_>, field: &FieldDescriptorProto) -> Result<Label> {
+    let label = field.label.ok_or_else(|| ctx.missing("label"))?;
+    Label::try_from(label).map_err(|_| ctx.invalid(format!("unknown label {}", label)))
 }
 
 fn field_modifier(
-    message: &MessageDescriptor,
+    ctx: FieldContext<'_>,
     field: &FieldDescriptorProto,
+    features: &Features,
     field_type: &FieldType,
-) -> FieldModifier {
-    let label = Label::try_from(field.label.expect("expected label")).expect("valid label");
+) -> Result<FieldModifier> {
+    let label = label(ctx, field)?;
+    let expect_label = |expected: Label, kind: &str| match label == expected {
+        true => Ok(()),
+        false => Err(ctx.invalid(format!(
+            "expected {} field to have label {:?}, got {:?}",
+            kind, expected, label
+        ))),
+    };
+
     if field.proto3_optional.unwrap_or(false) {
-        assert_eq!(label, Label::Optional);
-        return FieldModifier::Optional;
+        expect_label(Label::Optional, "proto3 optional")?;
+        return Ok(FieldModifier::Optional);
     }
 
     if field.oneof_index.is_some() {
-        assert_eq!(label, Label::Optional);
-        return FieldModifier::Optional;
+        expect_label(Label::Optional, "oneof")?;
+        return Ok(FieldModifier::Optional);
     }
 
     if matches!(field_type, FieldType::Map(_, _)) {
-        assert_eq!(label, Label::Repeated);
-        return FieldModifier::Repeated;
+        expect_label(Label::Repeated, "map")?;
+        return Ok(FieldModifier::Repeated);
     }
 
-    match label {
-        Label::Optional => match message.syntax {
-            Syntax::Proto2 => FieldModifier::Optional,
-            Syntax::Proto3 => match field_type {
-                FieldType::Message(_) => FieldModifier::Optional,
-                _ => FieldModifier::UseDefault,
-            },
+    Ok(match label {
+        Label::Optional => match (features.field_presence, field_type) {
+            (FieldPresence::LegacyRequired, _) => FieldModifier::Required,
+            // Message fields always track presence
+            (FieldPresence::Implicit, FieldType::Message(_)) => FieldModifier::Optional,
+            (FieldPresence::Implicit, _) => FieldModifier::UseDefault,
+            (FieldPresence::Explicit, _) => FieldModifier::Optional,
         },
         Label::Required => FieldModifier::Required,
         Label::Repeated => FieldModifier::Repeated,
+    })
+}
+
+/// Returns the group name if `field` is a proto2 group, i.e. a group-typed field named
+/// after the lower-cased name of a message type nested alongside it
+fn group_name(
+    message: &MessageDescriptor,
+    field: &FieldDescriptorProto,
+    field_type: &FieldType,
+) -> Option<String> {
+    if field.r#type != Some(Type::Group as i32) {
+        return None;
+    }
+
+    match field_type {
+        FieldType::Message(path) => {
+            let mut segments = path.path();
+            let nested = segments
+                .by_ref()
+                .take(message.path.len())
+                .eq(message.path.path());
+            let name = segments.next().map(|name| name.to_string());
+            let lower = name.as_ref().map(|name| name.to_lowercase());
+            match nested && segments.next().is_none() && lower.as_deref() == Some(field.name()) {
+                true => name,
+                false => None,
+            }
+        }
+        _ => None,
     }
 }
 
-fn field_type(descriptors: &DescriptorSet, field: &FieldDescriptorProto) -> FieldType {
-    match field.type_name.as_ref() {
-        Some(type_name) => resolve_type(descriptors, type_name.as_str()),
-        None => {
-            let scalar =
-                match Type::try_from(field.r#type.expect("expected type")).expect("valid type") {
-                    Type::Double => ScalarType::F64,
-                    Type::Float => ScalarType::F32,
-                    Type::Int64 | Type::Sfixed64 | Type::Sint64 => ScalarType::I64,
-                    Type::Int32 | Type::Sfixed32 | Type::Sint32 => ScalarType::I32,
-                    Type::Uint64 | Type::Fixed64 => ScalarType::U64,
-                    Type::Uint32 | Type::Fixed32 => ScalarType::U32,
-                    Type::Bool => ScalarType::Bool,
-                    Type::String => ScalarType::String,
-                    Type::Bytes => ScalarType::Bytes,
-                    Type::Message | Type::Enum | Type::Group => panic!("no type name specified"),
-                };
-            FieldType::Scalar(scalar)
+/// Returns the type of `field`, resolving any type name relative to `scope`
+fn field_type(
+    descriptors: &DescriptorSet,
+    ctx: FieldContext<'_>,
+    scope: &TypePath,
+    field: &FieldDescriptorProto,
+) -> Result<FieldType> {
+    let r#type = match field.r#type {
+        Some(t) => Some(Type::try_from(t).map_err(|_| ctx.invalid(format!("unknown type {}", t)))?),
+        None => None,
+    };
+
+    match (r#type, field.type_name.as_ref()) {
+        // A group is a message encoded with delimiters instead of a length prefix,
+        // and so is encoded in JSON as any other message
+        (Some(Type::Group), Some(type_name)) => {
+            match resolve_type(descriptors, ctx, scope, type_name)? {
+                FieldType::Message(path) => Ok(FieldType::Message(path)),
+                _ => Err(ctx.invalid(format!("expected group {} to be a message", type_name))),
+            }
+        }
+        (_, Some(type_name)) => resolve_type(descriptors, ctx, scope, type_name.as_str()),
+        (r#type, None) => {
+            let scalar = match r#type.ok_or_else(|| ctx.missing("type"))? {
+                Type::Double => ScalarType::F64,
+                Type::Float => ScalarType::F32,
+                Type::Int64 | Type::Sfixed64 | Type::Sint64 => ScalarType::I64,
+                Type::Int32 | Type::Sfixed32 | Type::Sint32 => ScalarType::I32,
+                Type::Uint64 | Type::Fixed64 => ScalarType::U64,
+                Type::Uint32 | Type::Fixed32 => ScalarType::U32,
+                Type::Bool => ScalarType::Bool,
+                Type::String => ScalarType::String,
+                Type::Bytes => ScalarType::Bytes,
+                Type::Message | Type::Enum | Type::Group => return Err(ctx.missing("type name")),
+            };
+            Ok(FieldType::Scalar(scalar))
         }
     }
 }
 
-fn resolve_type(descriptors: &DescriptorSet, type_name: &str) -> FieldType {
-    assert!(
-        type_name.starts_with('.'),
-        "pbjson does not currently support resolving relative types"
-    );
-    let maybe_descriptor = descriptors
-        .iter()
-        .find(|(path, _)| path.prefix_match(type_name).is_some());
-
-    match maybe_descriptor {
-        Some((path, Descriptor::Enum(_))) => FieldType::Enum(path.clone()),
+fn resolve_type(
+    descriptors: &DescriptorSet,
+    ctx: FieldContext<'_>,
+    scope: &TypePath,
+    type_name: &str,
+) -> Result<FieldType> {
+    match descriptors.resolve(scope, type_name) {
+        Some((path, Descriptor::Enum(descriptor))) => {
+            Ok(FieldType::Enum(path.clone(), descriptor.features.enum_type))
+        }
         Some((path, Descriptor::Message(descriptor))) => match descriptor.is_map() {
             true => {
-                assert_eq!(descriptor.fields.len(), 2, "expected map to have 2 fields");
-                let key = &descriptor.fields[0];
-                let value = &descriptor.fields[1];
-
-                assert_eq!("key", key.name());
-                assert_eq!("value", value.name());
+                let (key, value) = match descriptor.fields.as_slice() {
+                    [key, value] if key.name() == "key" && value.name() == "value" => (key, value),
+                    _ => {
+                        return Err(ctx.invalid(format!(
+                            "expected map entry {} to have a key and value field",
+                            type_name
+                        )))
+                    }
+                };
 
-                let key_type = match field_type(descriptors, key) {
+                let key_type = match field_type(descriptors, ctx, path, key)? {
+                    FieldType::Scalar(ScalarType::Bytes | ScalarType::F32 | ScalarType::F64) => {
+                        return Err(ctx
+                            .invalid("protobuf disallows maps with floating point or bytes keys"))
+                    }
                     FieldType::Scalar(scalar) => scalar,
-                    _ => panic!("non scalar map key"),
+                    _ => return Err(ctx.invalid("protobuf disallows maps with non-scalar keys")),
+                };
+                let value_type = match field_type(descriptors, ctx, path, value)? {
+                    FieldType::Map(_, _) => {
+                        return Err(ctx.invalid("protobuf disallows nested maps"))
+                    }
+                    value_type => value_type,
                 };
-                let value_type = field_type(descriptors, value);
-                FieldType::Map(key_type, Box::new(value_type))
+                Ok(FieldType::Map(key_type, Box::new(value_type)))
+            }
+            false => Ok(FieldType::Message(path.clone())),
+        },
+        None => Err(ctx.unresolved(type_name)),
+    }
+}
+
+/// Parses the proto2 default `value` of a field of type `field_type`
+fn parse_default_value(
+    descriptors: &DescriptorSet,
+    ctx: FieldContext<'_>,
+    field_type: &FieldType,
+    value: &str,
+) -> Result<DefaultValue> {
+    fn parse<T: std::str::FromStr>(ctx: FieldContext<'_>, value: &str) -> Result<T> {
+        value
+            .parse()
+            .map_err(|_| ctx.invalid(format!("invalid default value \"{}\"", value)))
+    }
+
+    Ok(match field_type {
+        // protoc normalizes floating point defaults to "inf", "-inf" and "nan",
+        // all of which are accepted by FromStr
+        FieldType::Scalar(ScalarType::F64) => DefaultValue::F64(parse(ctx, value)?),
+        FieldType::Scalar(ScalarType::F32) => DefaultValue::F32(parse(ctx, value)?),
+        FieldType::Scalar(ScalarType::I32) => DefaultValue::I32(parse(ctx, value)?),
+        FieldType::Scalar(ScalarType::I64) => DefaultValue::I64(parse(ctx, value)?),
+        FieldType::Scalar(ScalarType::U32) => DefaultValue::U32(parse(ctx, value)?),
+        FieldType::Scalar(ScalarType::U64) => DefaultValue::U64(parse(ctx, value)?),
+        FieldType::Scalar(ScalarType::Bool) => DefaultValue::Bool(parse(ctx, value)?),
+        FieldType::Scalar(ScalarType::String) => DefaultValue::String(value.to_string()),
+        FieldType::Scalar(ScalarType::Bytes) => match unescape_c_string(value) {
+            Some(bytes) => DefaultValue::Bytes(bytes),
+            None => return Err(ctx.invalid(format!("invalid default value \"{}\"", value))),
+        },
+        FieldType::Enum(path, _) => match descriptors.get(path) {
+            Some(Descriptor::Enum(descriptor))
+                if descriptor.values.iter().any(|v| v.name() == value) =>
+            {
+                DefaultValue::Enum(value.to_string())
             }
-            // Note: This may actually be a group but it is non-trivial to detect this,
-            // they're deprecated, and pbjson doesn't need to be able to distinguish
-            false => FieldType::Message(path.clone()),
+            _ => return Err(ctx.invalid(format!("unknown default variant \"{}\"", value))),
         },
-        None => panic!("failed to resolve type: {}", type_name),
+        FieldType::Message(_) | FieldType::Map(_, _) => {
+            return Err(ctx.invalid("only scalar and enumeration fields can declare a default"))
+        }
+    })
+}
+
+/// Unescapes a C-style escaped string, as used by protoc for default bytes values
+fn unescape_c_string(s: &str) -> Option<Vec<u8>> {
+    let mut ret = Vec::with_capacity(s.len());
+    let mut bytes = s.bytes().peekable();
+    while let Some(b) = bytes.next() {
+        if b != b'\\' {
+            ret.push(b);
+            continue;
+        }
+
+        let escaped = match bytes.next()? {
+            b'n' => b'\n',
+            b'r' => b'\r',
+            b't' => b'\t',
+            b'a' => 0x07,
+            b'b' => 0x08,
+            b'f' => 0x0c,
+            b'v' => 0x0b,
+            c @ (b'\\' | b'\'' | b'"' | b'?') => c,
+            b'x' | b'X' => {
+                let mut value = 0_u8;
+                for _ in 0..2 {
+                    match bytes.peek().and_then(|c| (*c as char).to_digit(16)) {
+                        Some(digit) => value = value * 16 + digit as u8,
+                        None => break,
+                    }
+                    bytes.next();
+                }
+                value
+            }
+            c @ b'0'..=b'7' => {
+                let mut value = (c - b'0') as u32;
+                for _ in 0..2 {
+                    match bytes.peek().and_then(|c| (*c as char).to_digit(8)) {
+                        Some(digit) => value = value * 8 + digit,
+                        None => break,
+                    }
+                    bytes.next();
+                }
+                u8::try_from(value).ok()?
+            }
+            _ => return None,
+        };
+        ret.push(escaped);
+    }
+    Some(ret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::descriptor::Package;
+    use prost_types::{DescriptorProto, FileDescriptorProto, MessageOptions};
+
+    fn resolve(fields: Vec<FieldDescriptorProto>) -> Result<Option<Message>> {
+        let map_entry = DescriptorProto {
+            name: Some("FloatMapEntry".to_string()),
+            field: vec![
+                FieldDescriptorProto {
+                    name: Some("key".to_string()),
+                    r#type: Some(Type::Float as i32),
+                    label: Some(Label::Optional as i32),
+                    ..Default::default()
+                },
+                FieldDescriptorProto {
+                    name: Some("value".to_string()),
+                    r#type: Some(Type::String as i32),
+                    label: Some(Label::Optional as i32),
+                    ..Default::default()
+                },
+            ],
+            options: Some(MessageOptions {
+                map_entry: Some(true),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        let mut descriptors = DescriptorSet::default();
+        descriptors
+            .register_file_descriptor(FileDescriptorProto {
+                name: Some("foo.proto".to_string()),
+                package: Some("foo".to_string()),
+                message_type: vec![DescriptorProto {
+                    name: Some("Bar".to_string()),
+                    field: fields,
+                    nested_type: vec![map_entry],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            })
+            .unwrap();
+
+        let path = TypePath::new(Package::new("foo")).child(TypeName::new("Bar"));
+        match descriptors.get(&path).unwrap() {
+            Descriptor::Message(message) => resolve_message(&descriptors, message),
+            Descriptor::Enum(_) => unreachable!(),
+        }
+    }
+
+    fn field(r#type: Type, type_name: Option<&str>) -> FieldDescriptorProto {
+        FieldDescriptorProto {
+            name: Some("baz".to_string()),
+            r#type: Some(r#type as i32),
+            type_name: type_name.map(ToString::to_string),
+            label: Some(Label::Optional as i32),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_resolve_errors() {
+        let message = resolve(vec![field(Type::Int32, None)]).unwrap().unwrap();
+        assert_eq!(message.fields.len(), 1);
+
+        let err = resolve(vec![field(Type::Message, Some("Missing"))]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "failed to resolve type \"Missing\" of field foo.Bar.baz in foo.proto"
+        );
+
+        let err = resolve(vec![FieldDescriptorProto {
+            label: Some(Label::Repeated as i32),
+            ..field(Type::Message, Some("Bar.FloatMapEntry"))
+        }])
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid field foo.Bar.baz in foo.proto: protobuf disallows maps with floating point or bytes keys"
+        );
+
+        let err = resolve(vec![FieldDescriptorProto {
+            default_value: Some("seven".to_string()),
+            ..field(Type::Int32, None)
+        }])
+        .unwrap_err();
+        assert!(matches!(err, Error::InvalidField { field, .. } if field == "baz"));
+
+        let err = resolve(vec![FieldDescriptorProto {
+            label: None,
+            ..field(Type::Int32, None)
+        }])
+        .unwrap_err();
+        assert_eq!(err.to_string(), "foo.Bar.baz is missing label in foo.proto");
+    }
+
+    #[test]
+    fn test_editions() {
+        use crate::features::{
+            EnumFeatures, EnumOptions, FeatureSet, FieldFeatures, FieldOptions, FileFeatures,
+        };
+        use prost::Message as _;
+        use prost_types::{EnumDescriptorProto, EnumValueDescriptorProto};
+
+        // prost-types cannot encode features, so append them to the encoded
+        // descriptors, relying on protobuf merging repeated encodings of a message
+        #[derive(Clone, PartialEq, prost::Message)]
+        struct RawFile {
+            #[prost(bytes = "vec", repeated, tag = "4")]
+            message_type: Vec<Vec<u8>>,
+            #[prost(bytes = "vec", repeated, tag = "5")]
+            enum_type: Vec<Vec<u8>>,
+        }
+
+        #[derive(Clone, PartialEq, prost::Message)]
+        struct RawMessage {
+            #[prost(bytes = "vec", repeated, tag = "2")]
+            field: Vec<Vec<u8>>,
+        }
+
+        #[derive(Clone, PartialEq, prost::Message)]
+        struct RawFileSet {
+            #[prost(bytes = "vec", repeated, tag = "1")]
+            file: Vec<Vec<u8>>,
+        }
+
+        let features = |field_presence, enum_type| FeatureSet {
+            field_presence,
+            enum_type,
+        };
+
+        let field = |name: &str, r#type: Type, type_name: Option<&str>, presence| {
+            let descriptor = FieldDescriptorProto {
+                name: Some(name.to_string()),
+                r#type: Some(r#type as i32),
+                type_name: type_name.map(ToString::to_string),
+                label: Some(Label::Optional as i32),
+                ..Default::default()
+            };
+            let options = FieldFeatures {
+                options: Some(FieldOptions {
+                    features: Some(features(presence, None)),
+                }),
+            };
+            [descriptor.encode_to_vec(), options.encode_to_vec()].concat()
+        };
+
+        let enumeration = |name: &str, enum_type| {
+            let descriptor = EnumDescriptorProto {
+                name: Some(name.to_string()),
+                value: vec![EnumValueDescriptorProto {
+                    name: Some(format!("{}_UNKNOWN", name.to_uppercase())),
+                    number: Some(0),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            };
+            let options = EnumFeatures {
+                options: Some(EnumOptions {
+                    features: Some(features(None, enum_type)),
+                }),
+            };
+            [descriptor.encode_to_vec(), options.encode_to_vec()].concat()
+        };
+
+        let message = DescriptorProto {
+            name: Some("Bar".to_string()),
+            ..Default::default()
+        };
+        let fields = RawMessage {
+            field: vec![
+                field("explicit", Type::Int32, None, None),
+                field("implicit", Type::Int32, None, Some(2)),
+                field("required", Type::Int32, None, Some(3)),
+                field("implicit_message", Type::Message, Some("Bar"), Some(2)),
+                field("open", Type::Enum, Some("Open"), None),
+                field("closed", Type::Enum, Some("Closed"), None),
+            ],
+        };
+        let message = [message.encode_to_vec(), fields.encode_to_vec()].concat();
+
+        let file = |edition| {
+            let descriptor = FileDescriptorProto {
+                name: Some("foo.proto".to_string()),
+                package: Some("foo".to_string()),
+                syntax: Some("editions".to_string()),
+                ..Default::default()
+            };
+            let features = FileFeatures {
+                edition: Some(edition),
+                ..Default::default()
+            };
+            let types = RawFile {
+                message_type: vec![message.clone()],
+                enum_type: vec![enumeration("Open", None), enumeration("Closed", Some(2))],
+            };
+            let encoded = [
+                descriptor.encode_to_vec(),
+                features.encode_to_vec(),
+                types.encode_to_vec(),
+            ]
+            .concat();
+            RawFileSet {
+                file: vec![encoded],
+            }
+            .encode_to_vec()
+        };
+
+        let mut descriptors = DescriptorSet::default();
+        descriptors.register_encoded(&file(1000)).unwrap();
+
+        let path = TypePath::new(Package::new("foo")).child(TypeName::new("Bar"));
+        let message = match descriptors.get(&path).unwrap() {
+            Descriptor::Message(message) => resolve_message(&descriptors, message),
+            Descriptor::Enum(_) => unreachable!(),
+        };
+        let fields = message.unwrap().unwrap().fields;
+
+        let modifiers: Vec<_> = fields.iter().map(|f| f.field_modifier).collect();
+        assert!(matches!(
+            modifiers.as_slice(),
+            [
+                FieldModifier::Optional,
+                FieldModifier::UseDefault,
+                FieldModifier::Required,
+                FieldModifier::Optional,
+                FieldModifier::Optional,
+                FieldModifier::Optional,
+            ]
+        ));
+        assert!(matches!(
+            fields[4].field_type,
+            FieldType::Enum(_, EnumType::Open)
+        ));
+        assert!(matches!(
+            fields[5].field_type,
+            FieldType::Enum(_, EnumType::Closed)
+        ));
+
+        let err = DescriptorSet::default()
+            .register_encoded(&file(1234))
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "unsupported syntax \"editions (1234)\" in foo.proto"
+        );
+
+        // The edition is not retained by prost-types
+        let err = DescriptorSet::default()
+            .register_file_descriptor(FileDescriptorProto {
+                name: Some("foo.proto".to_string()),
+                package: Some("foo".to_string()),
+                syntax: Some("editions".to_string()),
+                ..Default::default()
+            })
+            .unwrap_err();
+        assert_eq!(err.to_string(), "file is missing edition in foo.proto");
     }
 }
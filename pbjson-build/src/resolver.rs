@@ -1,10 +1,13 @@
+use crate::config::PathConfig;
 use crate::descriptor::{Package, TypePath};
+use crate::generator::Crates;
 
 #[derive(Debug)]
 pub struct Resolver<'a> {
     extern_types: &'a [(String, String)],
-    retain_enum_prefix: bool,
+    retain_enum_prefix: &'a PathConfig<bool>,
     package: &'a Package,
+    crates: &'a Crates,
 }
 
 impl<'a> Resolver<'a> {
@@ -12,15 +15,22 @@ impl<'a> Resolver<'a> {
     pub fn new(
         extern_types: &'a [(String, String)],
         package: &'a Package,
-        retain_enum_prefix: bool,
+        retain_enum_prefix: &'a PathConfig<bool>,
+        crates: &'a Crates,
     ) -> Self {
         Resolver {
             extern_types,
             package,
             retain_enum_prefix,
+            crates,
         }
     }
 
+    /// Returns the paths of the `core` and `alloc` crates
+    pub fn crates(&self) -> &'a Crates {
+        self.crates
+    }
+
     /// Lookup an extern type, returns the rust path followed by the number of
     /// leading path segments to skip (they are already included in the rust path)
     fn resolve_extern(&self, path: &TypePath) -> Option<(String, usize)> {
@@ -89,7 +99,7 @@ impl<'a> Resolver<'a> {
     pub fn rust_variant(&self, enumeration: &TypePath, variant: &str) -> String {
         use heck::ToUpperCamelCase;
         let variant = variant.to_upper_camel_case();
-        match self.retain_enum_prefix {
+        match self.retain_enum_prefix.enabled(enumeration) {
             true => variant,
             false => {
                 let prefix = enumeration.path().last().unwrap().to_upper_camel_case();
@@ -121,7 +131,14 @@ mod tests {
                 "foo::bar::Buz".to_string(),
             ),
         ];
-        let resolver = Resolver::new(extern_types, &resolver_package, false);
+        let retain_enum_prefix = PathConfig::default();
+        let crates = Crates::default();
+        let resolver = Resolver::new(
+            extern_types,
+            &resolver_package,
+            &retain_enum_prefix,
+            &crates,
+        );
 
         // A type in the same package
         let same_type = TypePath::new(resolver_package.clone()).child(TypeName::new("Foo"));
@@ -179,8 +196,12 @@ mod tests {
     #[test]
     // https://github.com/influxdata/pbjson/issues/48
     fn test_resolver_shared_prefix_false_match() {
+        let package = Package::new("test.api.v1");
+        let retain_enum_prefix = PathConfig::default();
+        let crates = Crates::default();
+        let resolver = Resolver::new(&[], &package, &retain_enum_prefix, &crates);
         assert_eq!(
-            Resolver::new(&[], &Package::new("test.api.v1"), false).rust_type(
+            resolver.rust_type(
                 &TypePath::new(Package::new("test.domain.v1"))
                     .child(TypeName::new("Foo"))
                     .child(TypeName::new("Bar"))
@@ -189,10 +210,33 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_resolver_no_package() {
+        let root = Package::new("");
+        let root_type = TypePath::new(root.clone())
+            .child(TypeName::new("Foo"))
+            .child(TypeName::new("Bar"));
+        let other_type = TypePath::new(Package::new("test.common")).child(TypeName::new("Baz"));
+
+        let retain_enum_prefix = PathConfig::default();
+        let crates = Crates::default();
+
+        let resolver = Resolver::new(&[], &root, &retain_enum_prefix, &crates);
+        assert_eq!(resolver.rust_type(&root_type), "foo::Bar");
+        assert_eq!(resolver.rust_type(&other_type), "test::common::Baz");
+
+        let package = Package::new("test.syntax3");
+        let retain_enum_prefix = PathConfig::default();
+        let resolver = Resolver::new(&[], &package, &retain_enum_prefix, &crates);
+        assert_eq!(resolver.rust_type(&root_type), "super::super::foo::Bar");
+    }
+
     #[test]
     fn test_variant() {
         let package = Package::new("test.syntax3");
-        let resolver = Resolver::new(&[], &package, false);
+        let retain_enum_prefix = PathConfig::default();
+        let crates = Crates::default();
+        let resolver = Resolver::new(&[], &package, &retain_enum_prefix, &crates);
 
         let tests = [
             ("MyEnum", "MyEnumFoo", "Foo"),
@@ -213,4 +257,22 @@ mod tests {
             );
         }
     }
+    #[test]
+    fn test_variant_retain_enum_prefix() {
+        let package = Package::new("test.syntax3");
+        let retained = TypePath::new(package.clone()).child(TypeName::new("Retained"));
+        let stripped = TypePath::new(package.clone()).child(TypeName::new("Stripped"));
+
+        let mut retain_enum_prefix = PathConfig::default();
+        retain_enum_prefix.insert(".test", true);
+        retain_enum_prefix.insert(".test.syntax3.Stripped", false);
+
+        let crates = Crates::default();
+        let resolver = Resolver::new(&[], &package, &retain_enum_prefix, &crates);
+        assert_eq!(
+            resolver.rust_variant(&retained, "RETAINED_FOO"),
+            "RetainedFoo"
+        );
+        assert_eq!(resolver.rust_variant(&stripped, "STRIPPED_FOO"), "Foo");
+    }
 }
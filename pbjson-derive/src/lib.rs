@@ -0,0 +1,411 @@
+//! `pbjson-derive` derives [`serde::Serialize`][1] and [`serde::Deserialize`][2]
+//! implementations, compliant with the [protobuf JSON mapping][3], for types declared
+//! with the `prost` derive macros rather than generated by `prost-build`
+//!
+//! The implementations are generated by the same code as [`pbjson-build`][4], from the
+//! `#[prost(...)]` attributes of the type, and so behave identically
+//!
+//! # Usage
+//!
+//! Add `prost`, `pbjson`, `pbjson-derive` and `serde` to your `Cargo.toml`, then
+//!
+//! ```ignore
+//! use pbjson_derive::{PbJsonDeserialize, PbJsonSerialize};
+//!
+//! #[derive(Clone, PartialEq, prost::Message, PbJsonSerialize, PbJsonDeserialize)]
+//! pub struct MyMessage {
+//!     #[prost(int64, tag = "1")]
+//!     pub value: i64,
+//!     #[prost(enumeration = "MyEnum", optional, tag = "2")]
+//!     pub kind: Option<i32>,
+//! }
+//!
+//! #[derive(
+//!     Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord,
+//!     prost::Enumeration, PbJsonSerialize, PbJsonDeserialize,
+//! )]
+//! #[repr(i32)]
+//! pub enum MyEnum {
+//!     Unspecified = 0,
+//!     #[pbjson(name = "LEGACY")]
+//!     Legacy = 1,
+//! }
+//! ```
+//!
+//! As with `prost-build`, the protobuf name of a field is assumed to be the name of the rust
+//! field, and the protobuf name of an enumeration variant is assumed to be prefixed with the
+//! name of the enumeration, e.g. `MY_ENUM_UNSPECIFIED`, unless set with `#[pbjson(name = "...")]`
+//!
+//! As `prost-build` only strips this prefix if present, the variants of an enumeration whose
+//! protobuf names are not prefixed with its name must be named explicitly. The prefix can
+//! instead be set for the whole enumeration, with `#[pbjson(prefix = "MY_PREFIX")]` for names
+//! such as `MY_PREFIX_UNSPECIFIED`, or `#[pbjson(no_prefix)]` for names such as `UNSPECIFIED`
+//!
+//! The options of `pbjson-build` can be enabled for a type with a `#[pbjson(...)]` attribute,
+//! e.g. `#[pbjson(emit_fields, ignore_unknown_fields)]`
+//!
+//! Oneof fields are not supported, as the variants of a oneof are declared by a separate type
+//!
+//! [1]: https://docs.rs/serde/1.0.130/serde/trait.Serialize.html
+//! [2]: https://docs.rs/serde/1.0.130/serde/trait.Deserialize.html
+//! [3]: https://developers.google.com/protocol-buffers/docs/proto3#json
+//! [4]: https://docs.rs/pbjson-build
+
+#![deny(rustdoc::broken_intra_doc_links, rustdoc::bare_urls, rust_2018_idioms)]
+#![warn(
+    missing_debug_implementations,
+    clippy::explicit_iter_loop,
+    clippy::use_self,
+    clippy::clone_on_ref_ptr,
+    clippy::future_not_send
+)]
+
+use heck::ToShoutySnakeCase;
+use pbjson_build::derive::{
+    DeriveEnum, DeriveField, DeriveMessage, DeriveOptions, DeriveType, DeriveVariant,
+    FieldModifier, MapType, ScalarType, Trait,
+};
+use proc_macro::TokenStream;
+use quote::ToTokens;
+use syn::ext::IdentExt;
+use syn::{
+    Attribute, Data, DataEnum, DataStruct, DeriveInput, Expr, Fields, GenericArgument, Lit, LitStr,
+    PathArguments, Type, UnOp,
+};
+
+/// Derives `serde::Serialize` for a type deriving `prost::Message` or `prost::Enumeration`
+#[proc_macro_derive(PbJsonSerialize, attributes(pbjson, prost))]
+pub fn derive_serialize(input: TokenStream) -> TokenStream {
+    derive(input, Trait::Serialize)
+}
+
+/// Derives `serde::Deserialize` for a type deriving `prost::Message` or `prost::Enumeration`
+#[proc_macro_derive(PbJsonDeserialize, attributes(pbjson, prost))]
+pub fn derive_deserialize(input: TokenStream) -> TokenStream {
+    derive(input, Trait::Deserialize)
+}
+
+fn derive(input: TokenStream, generate: Trait) -> TokenStream {
+    let input = syn::parse_macro_input!(input as DeriveInput);
+    expand(&input, generate)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+fn expand(input: &DeriveInput, generate: Trait) -> syn::Result<proc_macro2::TokenStream> {
+    if !input.generics.params.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &input.generics,
+            "generic types are not supported",
+        ));
+    }
+
+    let enumeration = matches!(input.data, Data::Enum(_));
+    let TypeOptions { options, prefix } = parse_options(&input.attrs, enumeration)?;
+    let name = input.ident.to_string();
+    let generated = match &input.data {
+        Data::Struct(data) => parse_message(name, data)?.generate(&options, generate),
+        Data::Enum(data) => parse_enum(name, prefix, data)?.generate(&options, generate),
+        Data::Union(_) => {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                "unions are not supported",
+            ))
+        }
+    };
+    generated.map_err(|e| syn::Error::new_spanned(&input.ident, e))
+}
+
+/// The options of the `#[pbjson(...)]` attributes of a type
+#[derive(Default)]
+struct TypeOptions {
+    options: DeriveOptions,
+    /// The prefix of the protobuf names of the variants of an enumeration, if not
+    /// the name of the enumeration, empty for no prefix
+    prefix: Option<String>,
+}
+
+/// Parses the options of `#[pbjson(...)]` attributes, `enumeration` if of an enumeration
+fn parse_options(attrs: &[Attribute], enumeration: bool) -> syn::Result<TypeOptions> {
+    let mut ret = TypeOptions::default();
+    let options = &mut ret.options;
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("pbjson")) {
+        attr.parse_nested_meta(|meta| {
+            let ident = meta.path.get_ident().map(ToString::to_string);
+            let option = match ident.as_deref() {
+                Some("prefix") if enumeration => {
+                    ret.prefix = Some(meta.value()?.parse::<LitStr>()?.value());
+                    return Ok(());
+                }
+                Some("no_prefix") if enumeration => {
+                    ret.prefix = Some(String::new());
+                    return Ok(());
+                }
+                Some("ignore_unknown_fields") => &mut options.ignore_unknown_fields,
+                Some("ignore_unknown_enum_values") => &mut options.ignore_unknown_enum_values,
+                Some("emit_fields") => &mut options.emit_fields,
+                Some("use_integers_for_enums") => &mut options.use_integers_for_enums,
+                Some("preserve_proto_field_names") => &mut options.preserve_proto_field_names,
+                _ => return Err(meta.error("unsupported pbjson option")),
+            };
+            *option = true;
+            Ok(())
+        })?;
+    }
+    Ok(ret)
+}
+
+fn parse_message(name: String, data: &DataStruct) -> syn::Result<DeriveMessage> {
+    let fields = match &data.fields {
+        Fields::Named(fields) => fields
+            .named
+            .iter()
+            .map(parse_field)
+            .collect::<syn::Result<_>>()?,
+        Fields::Unit => vec![],
+        Fields::Unnamed(fields) => {
+            return Err(syn::Error::new_spanned(
+                fields,
+                "tuple structs are not supported",
+            ))
+        }
+    };
+    Ok(DeriveMessage { name, fields })
+}
+
+/// The type of a field, as declared by its `#[prost(...)]` attribute
+enum Kind {
+    Scalar(ScalarType),
+    Enumeration(String),
+    Message,
+    Map(MapType, LitStr),
+}
+
+/// The label of a field, as declared by its `#[prost(...)]` attribute
+enum Label {
+    Optional,
+    Required,
+    Repeated,
+}
+
+fn parse_field(field: &syn::Field) -> syn::Result<DeriveField> {
+    let ident = field.ident.as_ref().expect("named field");
+    let attr = field
+        .attrs
+        .iter()
+        .find(|attr| attr.path().is_ident("prost"))
+        .ok_or_else(|| syn::Error::new_spanned(ident, "expected a #[prost(...)] attribute"))?;
+
+    let mut kind = None;
+    let mut label = None;
+    attr.parse_nested_meta(|meta| {
+        let name = meta
+            .path
+            .get_ident()
+            .map(ToString::to_string)
+            .unwrap_or_default();
+
+        match name.as_str() {
+            "optional" => label = Some(Label::Optional),
+            "required" => label = Some(Label::Required),
+            "repeated" => label = Some(Label::Repeated),
+            "message" | "group" => kind = Some(Kind::Message),
+            "enumeration" => {
+                kind = Some(Kind::Enumeration(meta.value()?.parse::<LitStr>()?.value()))
+            }
+            "map" | "hash_map" => kind = Some(Kind::Map(MapType::HashMap, meta.value()?.parse()?)),
+            "btree_map" => kind = Some(Kind::Map(MapType::BTreeMap, meta.value()?.parse()?)),
+            "oneof" => return Err(meta.error("oneof fields are not supported")),
+            // Attributes that do not affect the JSON mapping
+            "tag" | "tags" | "default" | "packed" => {
+                meta.value()?.parse::<Lit>()?;
+            }
+            "boxed" => {}
+            _ => match scalar_type(&name) {
+                Some(scalar) => {
+                    // e.g. `bytes = "vec"`
+                    if meta.input.peek(syn::Token![=]) {
+                        meta.value()?.parse::<LitStr>()?;
+                    }
+                    kind = Some(Kind::Scalar(scalar))
+                }
+                None => return Err(meta.error("unsupported prost attribute")),
+            },
+        }
+        Ok(())
+    })?;
+
+    let field_type = match kind {
+        Some(Kind::Scalar(scalar)) => DeriveType::Scalar(scalar),
+        Some(Kind::Enumeration(path)) => DeriveType::Enumeration(path),
+        Some(Kind::Message) => DeriveType::Message(message_type(&field.ty)),
+        Some(Kind::Map(map_type, spec)) => parse_map(map_type, &spec, &field.ty)?,
+        None => {
+            return Err(syn::Error::new_spanned(
+                attr,
+                "expected the protobuf type of the field",
+            ))
+        }
+    };
+
+    let field_modifier = match (&field_type, label) {
+        (DeriveType::Map(_, _, _), _) | (_, Some(Label::Repeated)) => FieldModifier::Repeated,
+        (_, Some(Label::Required)) => FieldModifier::Required,
+        // Message fields always track presence
+        (_, Some(Label::Optional)) | (DeriveType::Message(_), None) => FieldModifier::Optional,
+        (_, None) => FieldModifier::UseDefault,
+    };
+
+    Ok(DeriveField {
+        name: ident.unraw().to_string(),
+        field_modifier,
+        field_type,
+    })
+}
+
+/// Parses the key and value type of a map, e.g. `"string, enumeration(MyEnum)"`
+fn parse_map(map_type: MapType, spec: &LitStr, ty: &Type) -> syn::Result<DeriveType> {
+    let error = |message: &str| syn::Error::new_spanned(spec, message);
+
+    let value = spec.value();
+    let (key, value) = value
+        .split_once(',')
+        .ok_or_else(|| error("expected a key and value type"))?;
+    let key = scalar_type(key.trim()).ok_or_else(|| error("unsupported map key type"))?;
+
+    let value = value.trim();
+    let value = match value.strip_prefix("enumeration(") {
+        Some(path) => DeriveType::Enumeration(
+            path.strip_suffix(')')
+                .ok_or_else(|| error("expected a closing parenthesis"))?
+                .trim()
+                .to_string(),
+        ),
+        // The rust type of the values is the last type argument of the map
+        None if value == "message" => {
+            match generic_arguments(ty).and_then(|(_, args)| args.last().copied()) {
+                Some(ty) => DeriveType::Message(message_type(ty)),
+                None => return Err(syn::Error::new_spanned(ty, "expected a map type")),
+            }
+        }
+        None => DeriveType::Scalar(
+            scalar_type(value).ok_or_else(|| error("unsupported map value type"))?,
+        ),
+    };
+    Ok(DeriveType::Map(map_type, key, Box::new(value)))
+}
+
+/// Returns the scalar type with the protobuf name `name`
+fn scalar_type(name: &str) -> Option<ScalarType> {
+    Some(match name {
+        "double" => ScalarType::F64,
+        "float" => ScalarType::F32,
+        "int32" | "sint32" | "sfixed32" => ScalarType::I32,
+        "int64" | "sint64" | "sfixed64" => ScalarType::I64,
+        "uint32" | "fixed32" => ScalarType::U32,
+        "uint64" | "fixed64" => ScalarType::U64,
+        "bool" => ScalarType::Bool,
+        "string" => ScalarType::String,
+        "bytes" => ScalarType::Bytes,
+        _ => return None,
+    })
+}
+
+/// Returns the rust path of the message type of a field, without any
+/// enclosing `Option`, `Vec` or `Box`
+fn message_type(ty: &Type) -> String {
+    match generic_arguments(ty) {
+        Some((wrapper, args))
+            if args.len() == 1 && matches!(wrapper.as_str(), "Option" | "Vec" | "Box") =>
+        {
+            message_type(args[0])
+        }
+        _ => ty.to_token_stream().to_string().replace(' ', ""),
+    }
+}
+
+/// Returns the name of the last segment of the path `ty`, along with its type arguments
+fn generic_arguments(ty: &Type) -> Option<(String, Vec<&Type>)> {
+    let segment = match ty {
+        Type::Path(path) => path.path.segments.last()?,
+        _ => return None,
+    };
+
+    let args = match &segment.arguments {
+        PathArguments::AngleBracketed(args) => args
+            .args
+            .iter()
+            .filter_map(|arg| match arg {
+                GenericArgument::Type(ty) => Some(ty),
+                _ => None,
+            })
+            .collect(),
+        _ => return None,
+    };
+    Some((segment.ident.to_string(), args))
+}
+
+fn parse_enum(name: String, prefix: Option<String>, data: &DataEnum) -> syn::Result<DeriveEnum> {
+    let prefix = prefix.unwrap_or_else(|| name.to_shouty_snake_case());
+    let variants = data
+        .variants
+        .iter()
+        .map(|variant| {
+            if !matches!(variant.fields, Fields::Unit) {
+                return Err(syn::Error::new_spanned(variant, "expected a unit variant"));
+            }
+
+            let number = match &variant.discriminant {
+                Some((_, expr)) => parse_number(expr)?,
+                None => {
+                    return Err(syn::Error::new_spanned(
+                        variant,
+                        "expected an explicit discriminant",
+                    ))
+                }
+            };
+
+            let mut proto_name = None;
+            for attr in variant
+                .attrs
+                .iter()
+                .filter(|attr| attr.path().is_ident("pbjson"))
+            {
+                attr.parse_nested_meta(|meta| match meta.path.is_ident("name") {
+                    true => {
+                        proto_name = Some(meta.value()?.parse::<LitStr>()?.value());
+                        Ok(())
+                    }
+                    false => Err(meta.error("unsupported pbjson attribute")),
+                })?;
+            }
+
+            let rust_name = variant.ident.to_string();
+            let proto_name = proto_name.unwrap_or_else(|| match prefix.is_empty() {
+                true => rust_name.to_shouty_snake_case(),
+                false => format!("{}_{}", prefix, rust_name.to_shouty_snake_case()),
+            });
+            Ok(DeriveVariant {
+                proto_name,
+                rust_name,
+                number,
+            })
+        })
+        .collect::<syn::Result<_>>()?;
+
+    Ok(DeriveEnum { name, variants })
+}
+
+/// Parses an integer discriminant, e.g. `1` or `-1`
+fn parse_number(expr: &Expr) -> syn::Result<i32> {
+    match expr {
+        Expr::Lit(lit) => match &lit.lit {
+            Lit::Int(int) => int.base10_parse(),
+            _ => Err(syn::Error::new_spanned(expr, "expected an integer")),
+        },
+        Expr::Unary(unary) if matches!(unary.op, UnOp::Neg(_)) => parse_number(&unary.expr)?
+            .checked_neg()
+            .ok_or_else(|| syn::Error::new_spanned(expr, "integer out of range")),
+        _ => Err(syn::Error::new_spanned(expr, "expected an integer")),
+    }
+}
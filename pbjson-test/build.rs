@@ -1,5 +1,6 @@
 //! Compiles Protocol Buffers definitions into native Rust types
 
+use pbjson_build::Int64Encoding;
 use std::env;
 use std::path::PathBuf;
 
@@ -10,11 +11,14 @@ fn main() -> Result<()> {
     let root = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("protos");
 
     let proto_files = vec![
+        root.join("syntax2.proto"),
         root.join("syntax3.proto"),
         root.join("common.proto"),
         root.join("duplicate_name.proto"),
         root.join("duplicate_number.proto"),
         root.join("escape.proto"),
+        root.join("no_package.proto"),
+        root.join("options.proto"),
     ];
 
     // Tell cargo to recompile if any of these proto files are changed
@@ -32,8 +36,14 @@ fn main() -> Result<()> {
         .bytes([".test"])
         .protoc_arg("--experimental_allow_proto3_optional");
 
+    // prost replaces rather than extends the paths configured with btree_map
     if cfg!(feature = "btree") {
         prost_config.btree_map([".test"]);
+    } else {
+        prost_config.btree_map([
+            ".test.options.Overrides.sorted",
+            ".test.options.Mirror.sorted",
+        ]);
     }
 
     prost_config.compile_protos(&proto_files, &[root])?;
@@ -48,6 +58,10 @@ fn main() -> Result<()> {
         builder.ignore_unknown_fields();
     }
 
+    if cfg!(feature = "ignore-unknown-enum-values") {
+        builder.ignore_unknown_enum_values();
+    }
+
     if cfg!(feature = "btree") {
         builder.btree_map([".test"]);
     }
@@ -64,7 +78,52 @@ fn main() -> Result<()> {
         builder.preserve_proto_field_names();
     }
 
-    builder.build(&[".test"])?;
+    if cfg!(feature = "extensions") {
+        builder.extensions();
+    }
+
+    // Overrides taking precedence over the above for the types in options.proto
+    builder
+        .emit_fields_for(".test.options", false)
+        .emit_fields_for(".test.options.Overrides.emitted", true)
+        .preserve_proto_field_names_for(".test.options", false)
+        .preserve_proto_field_names_for(".test.options.Overrides.proto_name", true)
+        .use_integers_for_enums_for(".test.options", false)
+        .use_integers_for_enums_for(".test.options.IntegerEnum", true)
+        .ignore_unknown_enum_values_for(".test.options", false)
+        .ignore_unknown_enum_values_for(".test.options.Overrides.lenient_enums", true)
+        .ignore_unknown_fields_for(".test.options", false)
+        .ignore_unknown_fields_for(".test.options.Lenient", true)
+        .int64_encoding_for(".test.options", Int64Encoding::String)
+        .int64_encoding_for(".test.options.NumberInt64s", Int64Encoding::Number)
+        .int64_encoding_for(".test.options.SafeInt64s", Int64Encoding::SafeNumber)
+        .btree_map([
+            ".test.options.Overrides.sorted",
+            ".test.options.Mirror.sorted",
+        ])
+        .bytes([".test"])
+        .serde_with(
+            ".test.options.CustomSerde.number",
+            "crate::hooks::string_number",
+        )
+        .serde_with(
+            ".test.options.CustomSerde.optional_number",
+            "crate::hooks::string_number",
+        )
+        .serde_with(
+            ".test.options.CustomSerde.numbers",
+            "crate::hooks::string_number",
+        )
+        .serde_with(
+            ".test.options.CustomSerde.number_map",
+            "crate::hooks::string_number",
+        )
+        .serde_with(".test.options.Money", "crate::hooks::money")
+        .serde_with(".test.options.CustomSerde.hex", "crate::hooks::hex");
+
+    builder
+        .include_file("_includes.rs")
+        .build(&[".test", ".NoPackage"])?;
 
     Ok(())
 }
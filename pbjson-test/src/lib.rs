@@ -23,36 +23,198 @@ pub enum ExternEnumeration {
     Unknown = 0,
 }
 
-pub mod test {
-    pub mod syntax3 {
-        include!(concat!(env!("OUT_DIR"), "/test.syntax3.rs"));
-        include!(concat!(env!("OUT_DIR"), "/test.syntax3.serde.rs"));
+// The output of prost and pbjson for every package, including the types
+// declared in files without a package
+include!(concat!(env!("OUT_DIR"), "/_includes.rs"));
+
+/// Storage for the extensions of [`test::syntax2::Extendable`], keyed by id
+///
+/// As prost does not generate storage for extensions, they are stored separately
+#[cfg(feature = "extensions")]
+static EXTENSIONS: std::sync::Mutex<std::collections::BTreeMap<i32, pbjson::ExtensionSet>> =
+    std::sync::Mutex::new(std::collections::BTreeMap::new());
+
+#[cfg(feature = "extensions")]
+impl pbjson::ExtensionStorage for test::syntax2::Extendable {
+    fn extension<T>(&self, name: &str) -> Option<std::borrow::Cow<'_, T>>
+    where
+        T: std::any::Any + Clone + Send + Sync,
+    {
+        let extensions = EXTENSIONS.lock().unwrap();
+        let value = extensions.get(&self.id)?.get::<T>(name)?;
+        Some(std::borrow::Cow::Owned(value.clone()))
     }
 
-    pub mod common {
-        include!(concat!(env!("OUT_DIR"), "/test.common.rs"));
-        include!(concat!(env!("OUT_DIR"), "/test.common.serde.rs"));
+    fn set_extension<T>(&mut self, name: &str, value: T)
+    where
+        T: std::any::Any + Clone + Send + Sync,
+    {
+        let mut extensions = EXTENSIONS.lock().unwrap();
+        extensions.entry(self.id).or_default().set(name, value)
     }
+}
 
-    pub mod duplicate_name {
-        include!(concat!(env!("OUT_DIR"), "/test.duplicate_name.rs"));
-        include!(concat!(env!("OUT_DIR"), "/test.duplicate_name.serde.rs"));
+/// Declarations of the types in `test.options` deriving pbjson-derive, rather than
+/// generated by pbjson-build
+#[cfg(test)]
+mod derived {
+    use pbjson_derive::{PbJsonDeserialize, PbJsonSerialize};
+
+    #[derive(Clone, PartialEq, ::prost::Message, PbJsonSerialize, PbJsonDeserialize)]
+    pub struct Mirror {
+        #[prost(int64, tag = "1")]
+        pub int64_value: i64,
+        #[prost(string, optional, tag = "2")]
+        pub optional_string: Option<String>,
+        #[prost(double, repeated, tag = "3")]
+        pub doubles: Vec<f64>,
+        #[prost(bytes = "vec", tag = "4")]
+        pub bytes_value: Vec<u8>,
+        #[prost(enumeration = "MirrorEnum", tag = "5")]
+        pub kind: i32,
+        #[prost(enumeration = "MirrorEnum", repeated, tag = "6")]
+        pub kinds: Vec<i32>,
+        #[prost(message, optional, boxed, tag = "7")]
+        pub child: Option<Box<Mirror>>,
+        #[prost(map = "string, enumeration(MirrorEnum)", tag = "8")]
+        pub kind_map: std::collections::HashMap<String, i32>,
+        #[prost(map = "uint32, message", tag = "9")]
+        pub children: std::collections::HashMap<u32, Mirror>,
+        #[prost(string, tag = "10")]
+        pub r#type: String,
+        #[prost(btree_map = "string, string", tag = "11")]
+        pub sorted: std::collections::BTreeMap<String, String>,
     }
 
-    pub mod duplicate_number {
-        include!(concat!(env!("OUT_DIR"), "/test.duplicate_number.rs"));
-        include!(concat!(env!("OUT_DIR"), "/test.duplicate_number.serde.rs"));
+    #[derive(
+        Clone,
+        Copy,
+        Debug,
+        PartialEq,
+        Eq,
+        Hash,
+        PartialOrd,
+        Ord,
+        ::prost::Enumeration,
+        PbJsonSerialize,
+        PbJsonDeserialize,
+    )]
+    #[repr(i32)]
+    pub enum MirrorEnum {
+        Unspecified = 0,
+        One = 1,
+        #[pbjson(name = "LEGACY")]
+        Legacy = 2,
     }
 
-    pub mod escape {
-        include!(concat!(
-            env!("OUT_DIR"),
-            "/test.r#abstract.r#type.escape.rs"
-        ));
-        include!(concat!(
-            env!("OUT_DIR"),
-            "/test.r#abstract.r#type.escape.serde.rs"
-        ));
+    /// An enumeration whose protobuf names are not prefixed with its name
+    #[derive(
+        Clone,
+        Copy,
+        Debug,
+        PartialEq,
+        Eq,
+        Hash,
+        PartialOrd,
+        Ord,
+        ::prost::Enumeration,
+        PbJsonSerialize,
+        PbJsonDeserialize,
+    )]
+    #[repr(i32)]
+    #[pbjson(no_prefix)]
+    pub enum Unprefixed {
+        Unspecified = 0,
+        FirstValue = 1,
+    }
+
+    /// An enumeration whose protobuf names are prefixed with something other than its name
+    #[derive(
+        Clone,
+        Copy,
+        Debug,
+        PartialEq,
+        Eq,
+        Hash,
+        PartialOrd,
+        Ord,
+        ::prost::Enumeration,
+        PbJsonSerialize,
+        PbJsonDeserialize,
+    )]
+    #[repr(i32)]
+    #[pbjson(prefix = "STATE")]
+    pub enum Prefixed {
+        Unspecified = 0,
+        FirstValue = 1,
+    }
+}
+
+/// Custom serde `with` modules for the fields of [`test::options::CustomSerde`]
+pub mod hooks {
+    /// Encodes a `string` containing an integer as a JSON number
+    pub mod string_number {
+        use serde::{Deserialize, Deserializer, Serializer};
+
+        // The signature must match the type of the field
+        #[allow(clippy::ptr_arg)]
+        pub fn serialize<S: Serializer>(value: &String, serializer: S) -> Result<S::Ok, S::Error> {
+            let value: i64 = value.parse().map_err(serde::ser::Error::custom)?;
+            serializer.serialize_i64(value)
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+            i64::deserialize(deserializer).map(|value| value.to_string())
+        }
+    }
+
+    /// Encodes a [`Money`] as a string, e.g. `"12 USD"`
+    pub mod money {
+        use serde::de::Error;
+        use serde::{Deserialize, Deserializer, Serializer};
+
+        use crate::test::options::Money;
+
+        pub fn serialize<S: Serializer>(value: &Money, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.collect_str(&format_args!("{} {}", value.units, value.currency))
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Money, D::Error> {
+            let value = String::deserialize(deserializer)?;
+            let (units, currency) = value
+                .split_once(' ')
+                .ok_or_else(|| D::Error::custom("expected units and currency"))?;
+            Ok(Money {
+                units: units.parse().map_err(D::Error::custom)?,
+                currency: currency.to_string(),
+            })
+        }
+    }
+
+    /// Encodes bytes as a hexadecimal string
+    pub mod hex {
+        use prost::bytes::Bytes;
+        use serde::de::Error;
+        use serde::{Deserialize, Deserializer, Serializer};
+
+        pub fn serialize<S: Serializer>(value: &Bytes, serializer: S) -> Result<S::Ok, S::Error> {
+            let value: String = value.iter().map(|b| format!("{:02x}", b)).collect();
+            serializer.serialize_str(&value)
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Bytes, D::Error> {
+            let value = String::deserialize(deserializer)?;
+            let bytes = (0..value.len())
+                .step_by(2)
+                .map(|idx| {
+                    let digits = value.get(idx..idx + 2).ok_or_else(|| {
+                        D::Error::custom("expected an even number of hexadecimal digits")
+                    })?;
+                    u8::from_str_radix(digits, 16).map_err(D::Error::custom)
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(bytes.into())
+        }
     }
 }
 
@@ -174,6 +336,17 @@ mod tests {
             err.to_string().as_str(),
             "unknown field `foo`, there are no fields at line 1 column 6"
         );
+
+        let err = serde_json::from_str::<test::syntax2::Extendable>(
+            r#"{"id":1,"[test.syntax2.unknown]":1}"#,
+        )
+        .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("unknown field `[test.syntax2.unknown]`"),
+            "{}",
+            err
+        );
     }
 
     #[test]
@@ -186,6 +359,85 @@ mod tests {
 
         let empty = serde_json::from_str::<Empty>("{\n \"foo\": \"bar\"\n}").unwrap();
         assert_eq!(empty, Empty {});
+
+        let extendable = serde_json::from_str::<test::syntax2::Extendable>(
+            r#"{"id":1,"[test.syntax2.i64_ext]":"1"}"#,
+        )
+        .unwrap();
+        assert_eq!(extendable, test::syntax2::Extendable { id: 1 });
+    }
+
+    #[test]
+    #[cfg(feature = "ignore-unknown-enum-values")]
+    fn test_ignore_unknown_enum_values() {
+        let decoded: KitchenSink = serde_json::from_str(
+            r#"{
+                "value": "VALUE_C",
+                "optionalValue": "VALUE_C",
+                "repeatedValue": ["VALUE_B", "VALUE_C", 45],
+                "int32Dict": {"1": "C", "2": "A"},
+                "oneOfValue": "VALUE_C"
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(decoded.value, kitchen_sink::Value::Unknown as i32);
+        assert_eq!(decoded.optional_value, None);
+        assert_eq!(
+            decoded.repeated_value,
+            vec![kitchen_sink::Value::B as i32, kitchen_sink::Value::A as i32]
+        );
+        assert_eq!(decoded.int32_dict.len(), 1);
+        assert_eq!(decoded.int32_dict[&2], kitchen_sink::Prefix::A as i32);
+        assert_eq!(decoded.one_of, None);
+
+        // Known values are unaffected
+        let decoded: KitchenSink =
+            serde_json::from_str(r#"{"value":"VALUE_B","optionalValue":63}"#).unwrap();
+        assert_eq!(decoded.value, kitchen_sink::Value::B as i32);
+        assert_eq!(decoded.optional_value, Some(kitchen_sink::Value::B as i32));
+
+        // A field whose unknown value was dropped is still present
+        for json in [
+            r#"{"optionalValue":"VALUE_C","optionalValue":"VALUE_B"}"#,
+            r#"{"oneOfValue":"VALUE_C","oneOfI32":1}"#,
+        ] {
+            let err = serde_json::from_str::<KitchenSink>(json).unwrap_err();
+            assert!(err.to_string().contains("duplicate field"), "{}", err);
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "ignore-unknown-enum-values"))]
+    fn test_unknown_enum_value_error() {
+        let err = serde_json::from_str::<KitchenSink>(r#"{"value":"VALUE_C"}"#).unwrap_err();
+        assert!(
+            err.to_string().contains("unknown variant `VALUE_C`"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_open_and_closed_enums() {
+        use test::syntax2::Defaults;
+
+        // proto3 enumerations are open, preserving integers without a corresponding variant
+        let decoded: KitchenSink =
+            serde_json::from_str(r#"{"value":1234,"repeatedValue":[1234]}"#).unwrap();
+        assert_eq!(decoded.value, 1234);
+        assert_eq!(decoded.repeated_value, vec![1234]);
+        let encoded = serde_json::to_value(&decoded).unwrap();
+        assert_eq!(encoded["value"], 1234);
+
+        // proto2 enumerations are closed
+        let decoded = serde_json::from_str::<Defaults>(r#"{"required_no_default":1,"level":1234}"#);
+        match cfg!(feature = "ignore-unknown-enum-values") {
+            true => assert_eq!(decoded.unwrap().level, None),
+            false => {
+                decoded.unwrap_err();
+            }
+        }
     }
 
     #[test]
@@ -204,6 +456,26 @@ mod tests {
         assert_ne!(serde_json::to_string(&decoded).unwrap().as_str(), "{}");
     }
 
+    #[test]
+    #[cfg(all(feature = "emit-fields", not(feature = "use-integers-for-enums")))]
+    fn test_emit_default_values() {
+        use test::syntax2::Defaults;
+
+        let decoded = Defaults {
+            required_no_default: 1,
+            ..Default::default()
+        };
+        assert_eq!(
+            serde_json::to_string(&decoded).unwrap().as_str(),
+            concat!(
+                r#"{"i32":5,"i64":"-7","u64":"18446744073709551615","f64":"Infinity","f32":1.5,"#,
+                r#""bool":true,"string":"he said \"hi\"\n","bytes":"AQJhYmM=","level":"LEVEL_HIGH","#,
+                r#""requiredI32":3,"requiredString":"foo","requiredLevel":"LEVEL_LOW","#,
+                r#""requiredBytes":"/w==","requiredNoDefault":1}"#
+            )
+        );
+    }
+
     #[test]
     #[cfg(all(not(feature = "emit-fields"), feature = "use-integers-for-enums"))]
     fn test_use_integers_for_enums() {
@@ -211,6 +483,8 @@ mod tests {
         assert_eq!(serde_json::to_string(&decoded).unwrap().as_str(), "{}");
         decoded.value = kitchen_sink::Value::A as i32;
         verify(&decoded, r#"{"value":45}"#);
+        decoded.value = 1000;
+        verify_encode(&decoded, r#"{"value":1000}"#);
     }
 
     #[test]
@@ -333,6 +607,17 @@ mod tests {
         decoded.value = kitchen_sink::Value::Unknown as i32;
         verify_decode(&decoded, "{}");
 
+        // Values without a known variant are encoded as integers
+        decoded.value = 1000;
+        verify_encode(&decoded, r#"{"value":1000}"#);
+
+        decoded.value = kitchen_sink::Value::Unknown as i32;
+        decoded.optional_value = Some(-2);
+        verify_encode(
+            &decoded,
+            (r#"{"optionalValue":-2}"#, r#"{"optional_value":-2}"#),
+        );
+
         decoded.optional_value = Some(kitchen_sink::Value::Unknown as i32);
         verify(
             &decoded,
@@ -386,6 +671,15 @@ mod tests {
             ),
         );
 
+        decoded.int32_dict.insert(343, 1000);
+        verify_encode(
+            &decoded,
+            (
+                r#"{"int32Dict":{"343":1000}}"#,
+                r#"{"int32_dict":{"343":1000}}"#,
+            ),
+        );
+
         decoded.int32_dict = Default::default();
         verify_decode(&decoded, "{}");
 
@@ -443,6 +737,12 @@ mod tests {
         // Can also specify enum variant
         verify_decode(&decoded, (r#"{"oneOfValue":63}"#, r#"{"one_of_value":63}"#));
 
+        decoded.one_of = Some(kitchen_sink::OneOf::OneOfValue(1000));
+        verify_encode(
+            &decoded,
+            (r#"{"oneOfValue":1000}"#, r#"{"one_of_value":1000}"#),
+        );
+
         decoded.one_of = None;
         verify_decode(&decoded, "{}");
 
@@ -466,6 +766,15 @@ mod tests {
             ),
         );
 
+        decoded.repeated_value = vec![kitchen_sink::Value::B as i32, 1000];
+        verify_encode(
+            &decoded,
+            (
+                r#"{"repeatedValue":["VALUE_B",1000]}"#,
+                r#"{"repeated_value":["VALUE_B",1000]}"#,
+            ),
+        );
+
         decoded.repeated_value = Default::default();
         verify_decode(&decoded, "{}");
 
@@ -562,7 +871,7 @@ mod tests {
             nanos: date.timestamp_subsec_nanos() as i32,
         });
 
-        verify(&decoded, r#"{"timestamp":"2072-03-01T05:02:05.030+00:00"}"#);
+        verify(&decoded, r#"{"timestamp":"2072-03-01T05:02:05.030Z"}"#);
 
         decoded.timestamp = None;
         verify_decode(&decoded, "{}");
@@ -684,6 +993,94 @@ mod tests {
         decoded.string_value = None;
         verify(&decoded, r#"{}"#);
 
+        // Non-finite floats are encoded as strings
+        decoded.f64 = f64::NAN;
+        verify_encode(&decoded, r#"{"f64":"NaN"}"#);
+        let nan: KitchenSink = serde_json::from_str(r#"{"f64":"NaN"}"#).unwrap();
+        assert!(nan.f64.is_nan());
+
+        decoded.f64 = f64::INFINITY;
+        verify(&decoded, r#"{"f64":"Infinity"}"#);
+
+        decoded.f64 = 0.;
+        verify_decode(&decoded, "{}");
+
+        decoded.optional_f64 = Some(f64::NEG_INFINITY);
+        verify(
+            &decoded,
+            (
+                r#"{"optionalF64":"-Infinity"}"#,
+                r#"{"optional_f64":"-Infinity"}"#,
+            ),
+        );
+
+        decoded.optional_f64 = None;
+        verify_decode(&decoded, "{}");
+
+        decoded.repeated_f64 = vec![1.5, f64::INFINITY, f64::NEG_INFINITY];
+        verify(
+            &decoded,
+            (
+                r#"{"repeatedF64":[1.5,"Infinity","-Infinity"]}"#,
+                r#"{"repeated_f64":[1.5,"Infinity","-Infinity"]}"#,
+            ),
+        );
+
+        decoded.repeated_f64 = vec![];
+        verify_decode(&decoded, "{}");
+
+        decoded.f32 = f32::NEG_INFINITY;
+        verify(&decoded, r#"{"f32":"-Infinity"}"#);
+
+        decoded.f32 = 0.;
+        verify_decode(&decoded, "{}");
+
+        decoded.f64_dict.insert("foo".to_string(), f64::INFINITY);
+        verify(
+            &decoded,
+            (
+                r#"{"f64Dict":{"foo":"Infinity"}}"#,
+                r#"{"f64_dict":{"foo":"Infinity"}}"#,
+            ),
+        );
+
+        decoded.f64_dict = Default::default();
+        verify_decode(&decoded, "{}");
+
+        decoded.float_one_of = Some(kitchen_sink::FloatOneOf::OneOfF32(f32::INFINITY));
+        verify(
+            &decoded,
+            (r#"{"oneOfF32":"Infinity"}"#, r#"{"one_of_f32":"Infinity"}"#),
+        );
+
+        decoded.float_one_of = None;
+        verify_decode(&decoded, "{}");
+
+        decoded.double_value = Some(f64::NEG_INFINITY.into());
+        verify(
+            &decoded,
+            (
+                r#"{"doubleValue":"-Infinity"}"#,
+                r#"{"double_value":"-Infinity"}"#,
+            ),
+        );
+
+        decoded.double_value = None;
+        decoded.float_value = Some(f32::INFINITY.into());
+        verify(
+            &decoded,
+            (
+                r#"{"floatValue":"Infinity"}"#,
+                r#"{"float_value":"Infinity"}"#,
+            ),
+        );
+
+        decoded.float_value = None;
+        verify_decode(&decoded, "{}");
+
+        // Only the canonical spellings are accepted
+        verify_decode_err(r#"{"f64":"inf"}"#, "invalid number");
+
         // Test explicit null optional scalar
         verify_decode(&decoded, r#"{"optionalU32":null}"#);
         verify_decode(&decoded, r#"{"optionalU64":null}"#);
@@ -712,53 +1109,523 @@ mod tests {
         verify_decode(&decoded, r#"{"uint32Value":null}"#);
         verify_decode(&decoded, r#"{"uint64Value":null}"#);
 
-        // Test primitives are not nullable
-        verify_decode_err(r#"{"i32":null}"#, "data did not match any variant");
-        verify_decode_err(r#"{"u64":null}"#, "data did not match any variant");
-        verify_decode_err(r#"{"value":null}"#, "invalid type: null");
-        verify_decode_err(r#"{"bool":null}"#, "invalid type: null");
-        verify_decode_err(r#"{"string":null}"#, "invalid type: null");
-
-        // Test lists are not nullable
-        verify_decode_err(
-            r#"{"repeatedI32":null}"#,
-            "invalid type: null, expected a sequence",
-        );
+        // Test explicit null primitives decode as the default value
+        verify_decode(&decoded, r#"{"i32":null}"#);
+        verify_decode(&decoded, r#"{"u64":null}"#);
+        verify_decode(&decoded, r#"{"f64":null}"#);
+        verify_decode(&decoded, r#"{"value":null}"#);
+        verify_decode(&decoded, r#"{"bool":null}"#);
+        verify_decode(&decoded, r#"{"string":null}"#);
+        verify_decode(&decoded, r#"{"bytes":null}"#);
+
+        // Test explicit null lists decode as empty, but their elements are not nullable
+        verify_decode(&decoded, r#"{"repeatedI32":null}"#);
+        verify_decode(&decoded, r#"{"repeatedValue":null}"#);
+        verify_decode(&decoded, r#"{"repeatedBytes":null}"#);
+        verify_decode(&decoded, r#"{"repeatedInt32Value":null}"#);
         verify_decode_err(
             r#"{"repeatedI32":[null]}"#,
             "data did not match any variant",
         );
-        verify_decode_err(
-            r#"{"repeatedInt32Value":null}"#,
-            "invalid type: null, expected a sequence",
-        );
         verify_decode_err(
             r#"{"repeatedInt32Value":[null]}"#,
             "data did not match any variant",
         );
 
-        // Test maps are not nullable
-        verify_decode_err(
-            r#"{"stringDict":null}"#,
-            "invalid type: null, expected a map",
-        );
+        // Test explicit null maps decode as empty, but their values are not nullable
+        verify_decode(&decoded, r#"{"stringDict":null}"#);
+        verify_decode(&decoded, r#"{"int32Dict":null}"#);
+        verify_decode(&decoded, r#"{"mapInt32Value":null}"#);
         verify_decode_err(
             r#"{"stringDict": {"foo": null}}"#,
             "invalid type: null, expected a string ",
         );
-        verify_decode_err(
-            r#"{"mapInt32Value":null}"#,
-            "invalid type: null, expected a map",
-        );
         verify_decode_err(
             r#"{"mapInt32Value":{"foo": null}}"#,
             "data did not match any variant",
         );
+
+        // Test null is a value for google.protobuf.Value
+        let null = pbjson_types::Value {
+            kind: Some(pbjson_types::value::Kind::NullValue(0)),
+        };
+
+        decoded.json_value = Some(null.clone());
+        verify(
+            &decoded,
+            (r#"{"jsonValue":null}"#, r#"{"json_value":null}"#),
+        );
+        decoded.json_value = None;
+
+        decoded.repeated_json_value = vec![null.clone(), null.clone()];
+        verify(
+            &decoded,
+            (
+                r#"{"repeatedJsonValue":[null,null]}"#,
+                r#"{"repeated_json_value":[null,null]}"#,
+            ),
+        );
+        decoded.repeated_json_value = vec![];
+        verify_decode(&decoded, r#"{"repeatedJsonValue":null}"#);
+
+        decoded.json_one_of = Some(kitchen_sink::JsonOneOf::OneOfJsonValue(null));
+        verify(
+            &decoded,
+            (
+                r#"{"oneOfJsonValue":null}"#,
+                r#"{"one_of_json_value":null}"#,
+            ),
+        );
+        decoded.json_one_of = None;
+    }
+
+    #[test]
+    #[cfg(not(any(feature = "emit-fields", feature = "use-integers-for-enums")))]
+    fn test_default_values() {
+        use prost::Message;
+        use test::syntax2::Defaults;
+
+        // Absent fields decode the same as prost
+        let decoded: Defaults = serde_json::from_str(r#"{"requiredNoDefault":1}"#).unwrap();
+        assert_eq!(decoded, Defaults::decode([0x78, 0x01].as_slice()).unwrap());
+        assert_eq!(
+            decoded,
+            Defaults {
+                required_no_default: 1,
+                ..Default::default()
+            }
+        );
+
+        assert_eq!(decoded.i32, None);
+        assert_eq!(decoded.i32(), 5);
+        assert_eq!(decoded.required_i32, 3);
+        assert_eq!(decoded.required_string, "foo");
+        assert_eq!(
+            decoded.required_level(),
+            test::syntax2::defaults::Level::Low
+        );
+        assert_eq!(decoded.required_bytes.as_ref(), b"\xff");
+
+        let encoded = serde_json::to_string(&decoded).unwrap();
+        let expected = match cfg!(feature = "preserve-proto-field-names") {
+            true => concat!(
+                r#"{"required_i32":3,"required_string":"foo","required_level":"LEVEL_LOW","#,
+                r#""required_bytes":"/w==","required_no_default":1}"#
+            ),
+            false => concat!(
+                r#"{"requiredI32":3,"requiredString":"foo","requiredLevel":"LEVEL_LOW","#,
+                r#""requiredBytes":"/w==","requiredNoDefault":1}"#
+            ),
+        };
+        assert_eq!(encoded, expected);
+
+        // Null is the same as absent
+        let null: Defaults =
+            serde_json::from_str(r#"{"requiredI32":null,"requiredNoDefault":1}"#).unwrap();
+        assert_eq!(null, decoded);
+
+        // Required fields without a declared default must be present
+        let err = serde_json::from_str::<Defaults>("{}").unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("missing field `requiredNoDefault`"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_no_package() {
+        use test::syntax2::UsesNoPackage;
+
+        let decoded: UsesNoPackage = serde_json::from_str(
+            r#"{"noPackage":{"value":"foo","kind":"KIND_A","commonEnum":"A"}}"#,
+        )
+        .unwrap();
+
+        let expected = UsesNoPackage {
+            no_package: Some(NoPackage {
+                value: "foo".to_string(),
+                kind: no_package::Kind::A as i32,
+                common_enum: test::common::CommonEnumeration::A as i32,
+            }),
+        };
+        assert_eq!(decoded, expected);
+
+        let encoded = serde_json::to_string(&expected).unwrap();
+        assert_eq!(
+            serde_json::from_str::<UsesNoPackage>(&encoded).unwrap(),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_path_options() {
+        use test::options::{IntegerEnum, Lenient, NamedEnum, Overrides, Strict};
+
+        // Only the overridden field is emitted with its default value
+        let mut overrides = Overrides::default();
+        assert_eq!(
+            serde_json::to_string(&overrides).unwrap(),
+            r#"{"emitted":0}"#
+        );
+
+        overrides.proto_name = 1;
+        overrides.camel_name = 2;
+        overrides.integer_enum = IntegerEnum::One as i32;
+        overrides.named_enum = NamedEnum::One as i32;
+        overrides.sorted = [("b".to_string(), 1), ("a".to_string(), 2)]
+            .into_iter()
+            .collect::<std::collections::BTreeMap<_, _>>();
+
+        let encoded = serde_json::to_string(&overrides).unwrap();
+        assert_eq!(
+            encoded,
+            r#"{"emitted":0,"proto_name":1,"camelName":2,"integerEnum":1,"namedEnum":"NAMED_ENUM_ONE","sorted":{"a":2,"b":1}}"#
+        );
+        assert_eq!(
+            serde_json::from_str::<Overrides>(&encoded).unwrap(),
+            overrides
+        );
+
+        // Unknown enumeration values are only ignored for the overridden field
+        let decoded: Overrides =
+            serde_json::from_str(r#"{"lenientEnums":["NAMED_ENUM_ONE","UNKNOWN"]}"#).unwrap();
+        assert_eq!(decoded.lenient_enums, vec![NamedEnum::One as i32]);
+        serde_json::from_str::<Overrides>(r#"{"strictEnums":["NAMED_ENUM_ONE","UNKNOWN"]}"#)
+            .unwrap_err();
+
+        // Unknown fields are only ignored for the overridden message
+        let decoded: Lenient = serde_json::from_str(r#"{"value":1,"unknown":2}"#).unwrap();
+        assert_eq!(decoded, Lenient { value: 1 });
+        serde_json::from_str::<Strict>(r#"{"value":1,"unknown":2}"#).unwrap_err();
+    }
+
+    #[test]
+    fn test_serde_with() {
+        use test::options::{custom_serde, CustomSerde, Money};
+
+        let money = |units, currency: &str| Money {
+            units,
+            currency: currency.to_string(),
+        };
+
+        let mut value = CustomSerde {
+            number: "12".to_string(),
+            optional_number: Some("-3".to_string()),
+            numbers: vec!["1".to_string(), "2".to_string()],
+            number_map: [("a".to_string(), "4".to_string())].into_iter().collect(),
+            price: Some(money(5, "USD")),
+            prices: vec![money(1, "EUR"), money(2, "GBP")],
+            price_map: [(1, money(3, "JPY"))].into_iter().collect(),
+            value: Some(custom_serde::Value::Money(money(7, "CHF"))),
+            hex: vec![0xde, 0xad].into(),
+        };
+
+        let encoded = serde_json::to_string(&value).unwrap();
+        assert_eq!(
+            encoded,
+            r#"{"number":12,"optionalNumber":-3,"numbers":[1,2],"numberMap":{"a":4},"price":"5 USD","prices":["1 EUR","2 GBP"],"priceMap":{"1":"3 JPY"},"hex":"dead","money":"7 CHF"}"#
+        );
+        assert_eq!(
+            serde_json::from_str::<CustomSerde>(&encoded).unwrap(),
+            value
+        );
+
+        // A null value leaves the field unset
+        value = serde_json::from_str(r#"{"number":null,"price":null,"text":"foo"}"#).unwrap();
+        assert_eq!(
+            value,
+            CustomSerde {
+                value: Some(custom_serde::Value::Text("foo".to_string())),
+                ..Default::default()
+            }
+        );
+
+        // The default encoding is rejected
+        serde_json::from_str::<CustomSerde>(r#"{"number":"12"}"#).unwrap_err();
+        serde_json::from_str::<CustomSerde>(r#"{"price":{"units":"5","currency":"USD"}}"#)
+            .unwrap_err();
+        serde_json::from_str::<CustomSerde>(r#"{"hex":"3q0="}"#).unwrap_err();
+    }
+
+    #[test]
+    fn test_int64_encoding() {
+        use pbjson_types::{Int64Value, UInt64Value};
+        use test::options::{int64s, number_int64s, safe_int64s, Int64s, NumberInt64s, SafeInt64s};
+
+        macro_rules! int64s {
+            ($type: ident, $one_of: ident) => {
+                $type {
+                    int64_value: -9007199254740992,
+                    uint64_values: vec![1, u64::MAX],
+                    int64_map: [("a".to_string(), -5)].into_iter().collect(),
+                    wrapper: Some(Int64Value {
+                        value: 9007199254740991,
+                    }),
+                    wrappers: vec![UInt64Value { value: 2 }],
+                    wrapper_map: [(1, Int64Value { value: i64::MIN })].into_iter().collect(),
+                    value: Some($one_of::Value::Fixed64Value(3)),
+                }
+            };
+        }
+
+        let strings = int64s!(Int64s, int64s);
+        let encoded = serde_json::to_string(&strings).unwrap();
+        assert_eq!(
+            encoded,
+            r#"{"int64Value":"-9007199254740992","uint64Values":["1","18446744073709551615"],"int64Map":{"a":"-5"},"wrapper":"9007199254740991","wrappers":["2"],"wrapperMap":{"1":"-9223372036854775808"},"fixed64Value":"3"}"#
+        );
+        assert_eq!(serde_json::from_str::<Int64s>(&encoded).unwrap(), strings);
+
+        let numbers = int64s!(NumberInt64s, number_int64s);
+        let encoded = serde_json::to_string(&numbers).unwrap();
+        assert_eq!(
+            encoded,
+            r#"{"int64Value":-9007199254740992,"uint64Values":[1,18446744073709551615],"int64Map":{"a":-5},"wrapper":9007199254740991,"wrappers":[2],"wrapperMap":{"1":-9223372036854775808},"fixed64Value":3}"#
+        );
+        assert_eq!(
+            serde_json::from_str::<NumberInt64s>(&encoded).unwrap(),
+            numbers
+        );
+
+        // Only integers beyond ±(2^53 - 1) are encoded as strings
+        let safe = int64s!(SafeInt64s, safe_int64s);
+        let encoded = serde_json::to_string(&safe).unwrap();
+        assert_eq!(
+            encoded,
+            r#"{"int64Value":"-9007199254740992","uint64Values":[1,"18446744073709551615"],"int64Map":{"a":-5},"wrapper":9007199254740991,"wrappers":[2],"wrapperMap":{"1":"-9223372036854775808"},"fixed64Value":3}"#
+        );
+        assert_eq!(serde_json::from_str::<SafeInt64s>(&encoded).unwrap(), safe);
+    }
+
+    #[test]
+    fn test_derive() {
+        use test::options::{Mirror, MirrorEnum};
+
+        let child = Mirror {
+            int64_value: -4,
+            ..Default::default()
+        };
+        let generated = Mirror {
+            int64_value: 1 << 60,
+            optional_string: Some(String::new()),
+            doubles: vec![1.5, f64::INFINITY],
+            bytes_value: b"foo".to_vec().into(),
+            kind: MirrorEnum::Legacy as i32,
+            kinds: vec![MirrorEnum::One as i32, 7],
+            child: Some(Box::new(child.clone())),
+            kind_map: [("a".to_string(), MirrorEnum::Legacy as i32)]
+                .into_iter()
+                .collect(),
+            children: [(3, child)].into_iter().collect(),
+            r#type: "bar".to_string(),
+            sorted: [
+                ("b".to_string(), "1".to_string()),
+                ("a".to_string(), "2".to_string()),
+            ]
+            .into_iter()
+            .collect(),
+        };
+
+        let derived_child = derived::Mirror {
+            int64_value: -4,
+            ..Default::default()
+        };
+        let derived = derived::Mirror {
+            int64_value: 1 << 60,
+            optional_string: Some(String::new()),
+            doubles: vec![1.5, f64::INFINITY],
+            bytes_value: b"foo".to_vec(),
+            kind: derived::MirrorEnum::Legacy as i32,
+            kinds: vec![derived::MirrorEnum::One as i32, 7],
+            child: Some(Box::new(derived_child.clone())),
+            kind_map: [("a".to_string(), derived::MirrorEnum::Legacy as i32)]
+                .into_iter()
+                .collect(),
+            children: [(3, derived_child)].into_iter().collect(),
+            r#type: "bar".to_string(),
+            sorted: [
+                ("b".to_string(), "1".to_string()),
+                ("a".to_string(), "2".to_string()),
+            ]
+            .into_iter()
+            .collect(),
+        };
+
+        // The derived implementations match the generated implementations
+        let encoded = serde_json::to_string(&generated).unwrap();
+        assert_eq!(
+            encoded,
+            r#"{"int64Value":"1152921504606846976","optionalString":"","doubles":[1.5,"Infinity"],"bytesValue":"Zm9v","kind":"LEGACY","kinds":["MIRROR_ENUM_ONE",7],"child":{"int64Value":"-4"},"kindMap":{"a":"LEGACY"},"children":{"3":{"int64Value":"-4"}},"type":"bar","sorted":{"a":"2","b":"1"}}"#
+        );
+        assert_eq!(serde_json::to_string(&derived).unwrap(), encoded);
+        assert_eq!(
+            serde_json::from_str::<derived::Mirror>(&encoded).unwrap(),
+            derived
+        );
+
+        let decoded: derived::Mirror =
+            serde_json::from_str(r#"{"int64_value":2,"kind":1,"kinds":["LEGACY"]}"#).unwrap();
+        assert_eq!(decoded.int64_value, 2);
+        assert_eq!(decoded.kind, derived::MirrorEnum::One as i32);
+        assert_eq!(decoded.kinds, vec![derived::MirrorEnum::Legacy as i32]);
+        serde_json::from_str::<derived::Mirror>(r#"{"unknown":1}"#).unwrap_err();
+
+        let encoded = serde_json::to_string(&derived::MirrorEnum::Legacy).unwrap();
+        assert_eq!(encoded, r#""LEGACY""#);
+        assert_eq!(
+            serde_json::from_str::<derived::MirrorEnum>(&encoded).unwrap(),
+            derived::MirrorEnum::Legacy
+        );
+
+        let encoded = serde_json::to_string(&derived::Unprefixed::FirstValue).unwrap();
+        assert_eq!(encoded, r#""FIRST_VALUE""#);
+        assert_eq!(
+            serde_json::from_str::<derived::Unprefixed>(&encoded).unwrap(),
+            derived::Unprefixed::FirstValue
+        );
+        serde_json::from_str::<derived::Unprefixed>(r#""UNPREFIXED_FIRST_VALUE""#).unwrap_err();
+
+        let encoded = serde_json::to_string(&derived::Prefixed::FirstValue).unwrap();
+        assert_eq!(encoded, r#""STATE_FIRST_VALUE""#);
+        assert_eq!(
+            serde_json::from_str::<derived::Prefixed>(&encoded).unwrap(),
+            derived::Prefixed::FirstValue
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "emit-fields"))]
+    fn test_groups() {
+        use test::syntax2::{groups, Groups};
+
+        let verify_groups = |groups: &Groups, expected: &str| {
+            assert_eq!(serde_json::to_string(groups).unwrap(), expected);
+            assert_eq!(&serde_json::from_str::<Groups>(expected).unwrap(), groups);
+        };
+
+        let mut groups = Groups::default();
+        verify_groups(&groups, "{}");
+
+        groups.mygroup = Some(groups::MyGroup {
+            group_value: Some(1),
+        });
+        groups.repeatedgroup = vec![groups::RepeatedGroup {
+            name: Some("foo".to_string()),
+        }];
+        groups.snake_group = Some(groups::SnakeGroup { value: Some(2) });
+
+        // Groups are named by their group name when preserving proto field names
+        let expected = match cfg!(feature = "preserve-proto-field-names") {
+            true => concat!(
+                r#"{"MyGroup":{"group_value":1},"RepeatedGroup":[{"name":"foo"}],"#,
+                r#""Snake_Group":{"value":2}}"#
+            ),
+            false => concat!(
+                r#"{"mygroup":{"groupValue":1},"repeatedgroup":[{"name":"foo"}],"#,
+                r#""snakeGroup":{"value":2}}"#
+            ),
+        };
+        verify_groups(&groups, expected);
+
+        // The field names are accepted as well
+        let decoded: Groups = serde_json::from_str(
+            r#"{"mygroup":{"group_value":1},"repeatedgroup":[{"name":"foo"}],"snake_group":{"value":2}}"#,
+        )
+        .unwrap();
+        assert_eq!(decoded, groups);
+    }
+
+    #[test]
+    #[cfg(feature = "extensions")]
+    fn test_extensions() {
+        use pbjson::ExtensionStorage;
+        use test::syntax2::{Extendable, Extension};
+
+        let decoded: Extendable = serde_json::from_str(
+            r#"{
+                "[test.syntax2.i64_ext]": 3,
+                "[test.syntax2.levels_ext]": ["LEVEL_LOW", 2],
+                "[test.syntax2.bytes_ext]": "AQI=",
+                "[test.syntax2.Extension.message_ext]": {"value": "foo"},
+                "id": 1
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(decoded.id, 1);
+        assert_eq!(
+            decoded
+                .extension::<i64>("test.syntax2.i64_ext")
+                .unwrap()
+                .as_ref(),
+            &3
+        );
+        assert_eq!(
+            decoded
+                .extension::<Vec<i32>>("test.syntax2.levels_ext")
+                .unwrap()
+                .as_slice(),
+            &[1, 2]
+        );
+        assert_eq!(
+            decoded
+                .extension::<Vec<u8>>("test.syntax2.bytes_ext")
+                .unwrap()
+                .as_slice(),
+            &[1, 2]
+        );
+        assert_eq!(
+            decoded
+                .extension::<Extension>("test.syntax2.Extension.message_ext")
+                .unwrap()
+                .as_ref(),
+            &Extension {
+                value: Some("foo".to_string())
+            }
+        );
+
+        let levels = match cfg!(feature = "use-integers-for-enums") {
+            true => "[1,2]",
+            false => r#"["LEVEL_LOW","LEVEL_HIGH"]"#,
+        };
+        assert_eq!(
+            serde_json::to_string(&decoded).unwrap(),
+            format!(
+                concat!(
+                    r#"{{"id":1,"[test.syntax2.Extension.message_ext]":{{"value":"foo"}},"#,
+                    r#""[test.syntax2.i64_ext]":"3","[test.syntax2.levels_ext]":{},"#,
+                    r#""[test.syntax2.bytes_ext]":"AQI="}}"#
+                ),
+                levels
+            )
+        );
+
+        // Unset extensions are not emitted
+        let mut other = Extendable { id: 2 };
+        assert_eq!(serde_json::to_string(&other).unwrap(), r#"{"id":2}"#);
+        other.set_extension("test.syntax2.i64_ext", 5_i64);
+        assert_eq!(
+            serde_json::to_string(&other).unwrap(),
+            r#"{"id":2,"[test.syntax2.i64_ext]":"5"}"#
+        );
+
+        let err = serde_json::from_str::<Extendable>(
+            r#"{"id":3,"[test.syntax2.i64_ext]":1,"[test.syntax2.i64_ext]":2}"#,
+        )
+        .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("duplicate field `[test.syntax2.i64_ext]`"),
+            "{}",
+            err
+        );
     }
 
     #[test]
     fn test_escaped() -> Result<(), Box<dyn Error>> {
-        use super::test::escape::{Abstract, Target, Type};
+        use super::test::r#abstract::r#type::escape::{Abstract, Target, Type};
 
         let r#type = Type { example: true };
         let r#abstract = Abstract {
@@ -16,16 +16,32 @@ fn main() -> Result<()> {
         .compile_well_known_types()
         .disable_comments(["."])
         .bytes([".google"])
+        .type_attribute(".google.protobuf.Duration", "#[derive(Copy, Eq, Hash)]")
+        .type_attribute(".google.protobuf.Timestamp", "#[derive(Copy, Eq, Hash)]")
         .skip_protoc_run();
 
+    // The map type is selected explicitly, rather than by std, so that enabling std does not
+    // change the public types, however prost-build requires BTreeMap without std
+    let btree_map = std::env::var_os("CARGO_FEATURE_BTREE_MAP").is_some();
+    if btree_map {
+        config.btree_map(["."]);
+    }
+
     let empty: &[&str] = &[];
     config.compile_protos(empty, empty)?;
 
     let descriptor_set = std::fs::read(descriptor_path)?;
-    pbjson_build::Builder::new()
+    let mut builder = pbjson_build::Builder::new();
+    if btree_map {
+        builder.btree_map(["."]);
+    }
+
+    builder
         .register_descriptors(&descriptor_set)?
         .exclude([
+            ".google.protobuf.Any",
             ".google.protobuf.Duration",
+            ".google.protobuf.FieldMask",
             ".google.protobuf.Timestamp",
             ".google.protobuf.Value",
             ".google.protobuf.Struct",
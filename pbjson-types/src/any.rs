@@ -0,0 +1,336 @@
+//! Support for the JSON mapping of `google.protobuf.Any`
+//!
+//! An `Any` is encoded as the JSON representation of the embedded message with an
+//! additional `@type` field containing the type URL. Well-known-types with a special
+//! JSON representation, such as `google.protobuf.Duration`, are instead encoded
+//! under a `value` field
+//!
+//! As `Any` only carries the encoded bytes of the embedded message, the message type
+//! must be registered with [`register`] or [`register_with_name`] before an `Any`
+//! containing it can be serialized or deserialized
+//!
+//! ```ignore
+//! pbjson_types::any::register::<mypackage::MyMessage>();
+//! ```
+//!
+//! The registry requires the `std` feature, without it an `Any` can only contain
+//! well-known types
+
+#[cfg(feature = "std")]
+use std::collections::HashMap;
+#[cfg(feature = "std")]
+use std::sync::{OnceLock, RwLock};
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use prost::Message;
+#[cfg(feature = "std")]
+use prost::Name;
+use serde::de::DeserializeOwned;
+use serde::ser::SerializeMap;
+use serde::{Deserialize, Serialize};
+
+use crate::Any;
+
+/// Registers the message type `M` under the full name provided by its [`Name`] implementation
+#[cfg(feature = "std")]
+pub fn register<M>()
+where
+    M: Name + Default + Serialize + DeserializeOwned,
+{
+    register_with_name::<M>(M::full_name())
+}
+
+/// Registers the message type `M` under the fully-qualified protobuf name `full_name`,
+/// e.g. `mypackage.MyMessage`
+///
+/// Registering a name that is already registered replaces the previous registration
+#[cfg(feature = "std")]
+pub fn register_with_name<M>(full_name: impl Into<String>)
+where
+    M: Message + Default + Serialize + DeserializeOwned,
+{
+    registry()
+        .write()
+        .unwrap()
+        .insert(full_name.into(), entry::<M>(false));
+}
+
+/// A registered message type
+#[derive(Debug, Clone, Copy)]
+struct Entry {
+    /// Whether the message has a special JSON representation, and
+    /// must therefore be nested under a `value` field
+    special: bool,
+    to_json: fn(&[u8]) -> Result<serde_json::Value, String>,
+    from_json: fn(serde_json::Value) -> Result<Vec<u8>, String>,
+}
+
+fn to_json<M>(encoded: &[u8]) -> Result<serde_json::Value, String>
+where
+    M: Message + Default + Serialize,
+{
+    let message = M::decode(encoded).map_err(|e| e.to_string())?;
+    serde_json::to_value(message).map_err(|e| e.to_string())
+}
+
+fn from_json<M>(value: serde_json::Value) -> Result<Vec<u8>, String>
+where
+    M: Message + DeserializeOwned,
+{
+    let message: M = serde_json::from_value(value).map_err(|e| e.to_string())?;
+    Ok(message.encode_to_vec())
+}
+
+/// The message types registered with [`register_with_name`]
+#[cfg(feature = "std")]
+fn registry() -> &'static RwLock<HashMap<String, Entry>> {
+    static REGISTRY: OnceLock<RwLock<HashMap<String, Entry>>> = OnceLock::new();
+    REGISTRY.get_or_init(Default::default)
+}
+
+fn entry<M>(special: bool) -> Entry
+where
+    M: Message + Default + Serialize + DeserializeOwned,
+{
+    Entry {
+        special,
+        to_json: to_json::<M>,
+        from_json: from_json::<M>,
+    }
+}
+
+/// Returns the entry of the well-known type `full_name`, if any
+fn well_known_type(full_name: &str) -> Option<Entry> {
+    macro_rules! special {
+        ($($typ: ident),+ $(,)?) => {
+            match full_name {
+                $(
+                    concat!("google.protobuf.", stringify!($typ)) => {
+                        Some(entry::<crate::$typ>(true))
+                    }
+                )+
+                "google.protobuf.Empty" => Some(entry::<crate::Empty>(false)),
+                _ => None,
+            }
+        };
+    }
+
+    special!(
+        Any,
+        BoolValue,
+        BytesValue,
+        DoubleValue,
+        Duration,
+        FieldMask,
+        FloatValue,
+        Int32Value,
+        Int64Value,
+        ListValue,
+        StringValue,
+        Struct,
+        Timestamp,
+        UInt32Value,
+        UInt64Value,
+        Value,
+    )
+}
+
+/// Returns the registered entry for `type_url`
+fn lookup(type_url: &str) -> Result<Entry, String> {
+    let (_, full_name) = type_url
+        .rsplit_once('/')
+        .ok_or_else(|| format!("invalid type URL \"{}\"", type_url))?;
+
+    #[cfg(feature = "std")]
+    if let Some(entry) = registry().read().unwrap().get(full_name) {
+        return Ok(*entry);
+    }
+
+    well_known_type(full_name).ok_or_else(|| {
+            format!(
+                "unknown type URL \"{}\", the message type must be registered with pbjson_types::any::register",
+                type_url
+            )
+        })
+}
+
+impl Serialize for Any {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        if self.type_url.is_empty() && self.value.is_empty() {
+            return serializer.serialize_map(Some(0))?.end();
+        }
+
+        let entry = lookup(&self.type_url).map_err(serde::ser::Error::custom)?;
+        let value = (entry.to_json)(&self.value).map_err(serde::ser::Error::custom)?;
+
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry("@type", &self.type_url)?;
+        match (entry.special, value) {
+            (true, value) => map.serialize_entry("value", &value)?,
+            (false, serde_json::Value::Object(fields)) => {
+                for (k, v) in &fields {
+                    map.serialize_entry(k, v)?;
+                }
+            }
+            (false, _) => {
+                return Err(serde::ser::Error::custom(format!(
+                    "expected \"{}\" to serialize as an object",
+                    self.type_url
+                )))
+            }
+        }
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Any {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        // "@type" may appear anywhere within the object, and so it must be buffered
+        let mut fields = serde_json::Map::deserialize(deserializer)?;
+
+        let type_url = match fields.remove("@type") {
+            Some(serde_json::Value::String(type_url)) => type_url,
+            Some(_) => return Err(serde::de::Error::custom("\"@type\" must be a string")),
+            None if fields.is_empty() => return Ok(Self::default()),
+            None => return Err(serde::de::Error::missing_field("@type")),
+        };
+
+        let entry = lookup(&type_url).map_err(serde::de::Error::custom)?;
+        let value = match entry.special {
+            true => {
+                let value = fields
+                    .remove("value")
+                    .ok_or_else(|| serde::de::Error::missing_field("value"))?;
+
+                if let Some(k) = fields.keys().next() {
+                    return Err(serde::de::Error::unknown_field(k, &["@type", "value"]));
+                }
+                value
+            }
+            false => serde_json::Value::Object(fields),
+        };
+
+        let value = (entry.from_json)(value).map_err(serde::de::Error::custom)?;
+        Ok(Self {
+            type_url,
+            value: value.into(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Duration;
+
+    fn any<M: Message>(full_name: &str, message: &M) -> Any {
+        Any {
+            type_url: format!("type.googleapis.com/{}", full_name),
+            value: message.encode_to_vec().into(),
+        }
+    }
+
+    fn verify(any: &Any, expected: serde_json::Value) {
+        assert_eq!(serde_json::to_value(any).unwrap(), expected);
+        assert_eq!(&serde_json::from_value::<Any>(expected).unwrap(), any);
+    }
+
+    #[test]
+    fn test_empty() {
+        verify(&Any::default(), serde_json::json!({}));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_message() {
+        use crate::{Empty, SourceContext};
+
+        register_with_name::<SourceContext>("google.protobuf.SourceContext");
+
+        let context = SourceContext {
+            file_name: "foo.proto".to_string(),
+        };
+        verify(
+            &any("google.protobuf.SourceContext", &context),
+            serde_json::json!({
+                "@type": "type.googleapis.com/google.protobuf.SourceContext",
+                "fileName": "foo.proto",
+            }),
+        );
+
+        // "@type" need not be the first field
+        let decoded: Any = serde_json::from_str(
+            r#"{"fileName":"foo.proto","@type":"/google.protobuf.SourceContext"}"#,
+        )
+        .unwrap();
+        assert_eq!(decoded.type_url, "/google.protobuf.SourceContext");
+        assert_eq!(SourceContext::decode(decoded.value).unwrap(), context);
+
+        verify(
+            &any("google.protobuf.Empty", &Empty {}),
+            serde_json::json!({"@type": "type.googleapis.com/google.protobuf.Empty"}),
+        );
+    }
+
+    #[test]
+    fn test_special() {
+        let duration = Duration {
+            seconds: 1,
+            nanos: 500_000_000,
+        };
+        let inner = any("google.protobuf.Duration", &duration);
+        verify(
+            &inner,
+            serde_json::json!({
+                "@type": "type.googleapis.com/google.protobuf.Duration",
+                "value": "1.500s",
+            }),
+        );
+
+        verify(
+            &any("google.protobuf.Any", &inner),
+            serde_json::json!({
+                "@type": "type.googleapis.com/google.protobuf.Any",
+                "value": {
+                    "@type": "type.googleapis.com/google.protobuf.Duration",
+                    "value": "1.500s",
+                },
+            }),
+        );
+
+        let err = serde_json::from_str::<Any>(
+            r#"{"@type":"/google.protobuf.Duration","value":"1s","seconds":1}"#,
+        )
+        .unwrap_err();
+        assert!(
+            err.to_string().contains("unknown field `seconds`"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_unknown_type() {
+        let any = Any {
+            type_url: "type.googleapis.com/test.Unknown".to_string(),
+            value: Default::default(),
+        };
+        let err = serde_json::to_string(&any).unwrap_err();
+        assert!(err.to_string().contains("unknown type URL"), "{}", err);
+
+        let err = serde_json::from_str::<Any>(r#"{"@type":"/test.Unknown"}"#).unwrap_err();
+        assert!(err.to_string().contains("unknown type URL"), "{}", err);
+
+        let err = serde_json::from_str::<Any>(r#"{"foo":"bar"}"#).unwrap_err();
+        assert!(err.to_string().contains("missing field `@type`"), "{}", err);
+    }
+}
@@ -1,35 +1,223 @@
 use crate::Duration;
+use alloc::format;
+use alloc::string::ToString;
+use core::cmp::Ordering;
 use serde::de::Visitor;
 use serde::Serialize;
 
-impl TryFrom<Duration> for std::time::Duration {
-    type Error = std::num::TryFromIntError;
+const NANOS_PER_SECOND: i128 = 1_000_000_000;
+
+impl Duration {
+    /// Returns the duration of `nanos` nanoseconds, or `None` if its seconds overflow an `i64`
+    pub(crate) fn checked_from_nanos(nanos: i128) -> Option<Self> {
+        Some(Self {
+            seconds: (nanos / NANOS_PER_SECOND).try_into().ok()?,
+            nanos: (nanos % NANOS_PER_SECOND) as i32,
+        })
+    }
+
+    /// Returns the total number of nanoseconds of this duration
+    pub(crate) fn as_nanos(&self) -> i128 {
+        i128::from(self.seconds) * NANOS_PER_SECOND + i128::from(self.nanos)
+    }
+
+    /// Normalizes this duration to the canonical representation, with nanos in the range
+    /// ±999,999,999 and of the same sign as seconds, saturating if the seconds overflow
+    pub fn normalize(&mut self) {
+        let nanos = self.as_nanos();
+        *self = Self::checked_from_nanos(nanos).unwrap_or(match nanos < 0 {
+            true => Self {
+                seconds: i64::MIN,
+                nanos: -999_999_999,
+            },
+            false => Self {
+                seconds: i64::MAX,
+                nanos: 999_999_999,
+            },
+        });
+    }
+
+    /// Returns true if this duration is less than zero
+    pub fn is_negative(&self) -> bool {
+        self.as_nanos() < 0
+    }
+
+    /// Returns the absolute value of this duration, e.g. to convert a negative duration to
+    /// a [`core::time::Duration`] together with [`Self::is_negative`]
+    pub fn unsigned_abs(&self) -> core::time::Duration {
+        let nanos = self.as_nanos().unsigned_abs();
+        let nanos_per_second = NANOS_PER_SECOND as u128;
+        core::time::Duration::new(
+            (nanos / nanos_per_second) as u64,
+            (nanos % nanos_per_second) as u32,
+        )
+    }
+}
+
+/// Orders durations by their normalized value, and equal values by their representation
+impl Ord for Duration {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.as_nanos()
+            .cmp(&other.as_nanos())
+            .then_with(|| (self.seconds, self.nanos).cmp(&(other.seconds, other.nanos)))
+    }
+}
+
+impl PartialOrd for Duration {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl TryFrom<Duration> for core::time::Duration {
+    type Error = &'static str;
 
     fn try_from(value: Duration) -> Result<Self, Self::Error> {
-        Ok(Self::new(
-            value.seconds.try_into()?,
-            value.nanos.try_into()?,
-        ))
+        match value.is_negative() {
+            true => Err("negative duration"),
+            false => Ok(value.unsigned_abs()),
+        }
     }
 }
 
-impl From<std::time::Duration> for Duration {
-    fn from(value: std::time::Duration) -> Self {
+impl TryFrom<core::time::Duration> for Duration {
+    type Error = &'static str;
+
+    fn try_from(value: core::time::Duration) -> Result<Self, Self::Error> {
+        Ok(Self {
+            seconds: value
+                .as_secs()
+                .try_into()
+                .map_err(|_| "duration out of range")?,
+            nanos: value.subsec_nanos() as i32,
+        })
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl TryFrom<Duration> for chrono::TimeDelta {
+    type Error = &'static str;
+
+    fn try_from(value: Duration) -> Result<Self, Self::Error> {
+        Self::try_seconds(value.seconds)
+            .and_then(|seconds| seconds.checked_add(&Self::nanoseconds(value.nanos.into())))
+            .ok_or("duration out of range")
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl From<chrono::TimeDelta> for Duration {
+    fn from(value: chrono::TimeDelta) -> Self {
+        Self {
+            seconds: value.num_seconds(),
+            nanos: value.subsec_nanos(),
+        }
+    }
+}
+
+#[cfg(feature = "time")]
+impl TryFrom<Duration> for time::Duration {
+    type Error = &'static str;
+
+    fn try_from(value: Duration) -> Result<Self, Self::Error> {
+        Self::seconds(value.seconds)
+            .checked_add(Self::nanoseconds(value.nanos.into()))
+            .ok_or("duration out of range")
+    }
+}
+
+#[cfg(feature = "time")]
+impl From<time::Duration> for Duration {
+    fn from(value: time::Duration) -> Self {
         Self {
-            seconds: value.as_secs() as _,
-            nanos: value.subsec_nanos() as _,
+            seconds: value.whole_seconds(),
+            nanos: value.subsec_nanoseconds(),
         }
     }
 }
 
+/// The largest magnitude of the seconds of a valid duration, approximately 10,000 years
+const MAX_SECONDS: i64 = 315_576_000_000;
+
+/// Returns an error if `duration` is outside of the range ±315,576,000,000 seconds,
+/// has nanos outside of ±999,999,999, or has seconds and nanos of different signs
+fn validate(duration: &Duration) -> Result<(), &'static str> {
+    if !(-MAX_SECONDS..=MAX_SECONDS).contains(&duration.seconds) {
+        return Err("seconds out of range");
+    }
+    if !(-999_999_999..=999_999_999).contains(&duration.nanos) {
+        return Err("nanos out of range");
+    }
+    if duration.seconds != 0
+        && duration.nanos != 0
+        && (duration.nanos < 0) != (duration.seconds < 0)
+    {
+        return Err("inconsistent signs");
+    }
+    Ok(())
+}
+
+/// Parses `s`, an optionally negative decimal number of seconds with at most 9
+/// fractional digits and an "s" suffix, e.g. `-1.5s`
+fn parse_duration(s: &str) -> Result<Duration, &'static str> {
+    let s = s.strip_suffix('s').ok_or("missing 's' suffix")?;
+    let (negative, s) = match s.strip_prefix('-') {
+        Some(s) => (true, s),
+        None => (false, s),
+    };
+
+    let (seconds_str, decimal_str) = match s.split_once('.') {
+        Some((seconds_str, decimal_str)) => (seconds_str, Some(decimal_str)),
+        None => (s, None),
+    };
+
+    let is_digits = |s: &str| s.bytes().all(|c| c.is_ascii_digit());
+    if !is_digits(seconds_str) || (seconds_str.is_empty() && decimal_str.is_none()) {
+        return Err("invalid seconds");
+    }
+
+    let seconds = seconds_str.bytes().try_fold(0_i64, |seconds, c| {
+        let seconds = seconds.checked_mul(10)?.checked_add(i64::from(c - b'0'))?;
+        (seconds <= MAX_SECONDS).then_some(seconds)
+    });
+    let seconds = seconds.ok_or("seconds out of range")?;
+
+    let nanos = match decimal_str {
+        Some(decimal_str) => {
+            if decimal_str.is_empty() || !is_digits(decimal_str) {
+                return Err("invalid fractional seconds");
+            }
+            let exp = 9_u32
+                .checked_sub(decimal_str.len() as u32)
+                .ok_or("too many decimal places")?;
+            let decimal: i32 = decimal_str
+                .parse()
+                .map_err(|_| "invalid fractional seconds")?;
+            decimal * 10_i32.pow(exp)
+        }
+        None => 0,
+    };
+
+    Ok(match negative {
+        true => Duration {
+            seconds: -seconds,
+            nanos: -nanos,
+        },
+        false => Duration { seconds, nanos },
+    })
+}
+
 impl Serialize for Duration {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: serde::Serializer,
     {
-        if self.seconds != 0 && self.nanos != 0 && (self.nanos < 0) != (self.seconds < 0) {
-            return Err(serde::ser::Error::custom("Duration has inconsistent signs"));
-        }
+        validate(self).map_err(|e| {
+            serde::ser::Error::custom(format!(
+                "invalid Duration {{ seconds: {}, nanos: {} }}: {}",
+                self.seconds, self.nanos, e
+            ))
+        })?;
 
         let mut s = if self.seconds == 0 {
             if self.nanos < 0 {
@@ -61,7 +249,7 @@ struct DurationVisitor;
 impl<'de> Visitor<'de> for DurationVisitor {
     type Value = Duration;
 
-    fn expecting(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn expecting(&self, formatter: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         formatter.write_str("a duration string")
     }
 
@@ -69,43 +257,8 @@ impl<'de> Visitor<'de> for DurationVisitor {
     where
         E: serde::de::Error,
     {
-        let s = s
-            .strip_suffix('s')
-            .ok_or_else(|| serde::de::Error::custom("missing 's' suffix"))?;
-
-        let (negative, s) = match s.strip_prefix('-') {
-            Some(s) => (true, s),
-            None => (false, s),
-        };
-
-        let duration = match s.split_once('.') {
-            Some((seconds_str, decimal_str)) => {
-                let exp = 9_u32
-                    .checked_sub(decimal_str.len() as u32)
-                    .ok_or_else(|| serde::de::Error::custom("too many decimal places"))?;
-
-                let pow = 10_u32.pow(exp);
-                let seconds = seconds_str.parse().map_err(serde::de::Error::custom)?;
-                let decimal: u32 = decimal_str.parse().map_err(serde::de::Error::custom)?;
-
-                Duration {
-                    seconds,
-                    nanos: (decimal * pow) as i32,
-                }
-            }
-            None => Duration {
-                seconds: s.parse().map_err(serde::de::Error::custom)?,
-                nanos: 0,
-            },
-        };
-
-        Ok(match negative {
-            true => Duration {
-                seconds: -duration.seconds,
-                nanos: -duration.nanos,
-            },
-            false => duration,
-        })
+        parse_duration(s)
+            .map_err(|e| serde::de::Error::custom(format!("invalid Duration \"{}\": {}", s, e)))
     }
 }
 
@@ -197,4 +350,172 @@ mod tests {
 
         serde_json::from_str::<Duration>("90.1234567891s").unwrap_err();
     }
+
+    #[test]
+    fn test_duration_range() {
+        let encode = |seconds, nanos| serde_json::to_string(&Duration { seconds, nanos });
+        assert_eq!(
+            encode(315_576_000_000, 999_999_999).unwrap(),
+            "\"315576000000.999999999s\""
+        );
+        assert_eq!(
+            encode(-315_576_000_000, -999_999_999).unwrap(),
+            "\"-315576000000.999999999s\""
+        );
+        encode(315_576_000_001, 0).unwrap_err();
+        encode(-315_576_000_001, 0).unwrap_err();
+        encode(0, 1_000_000_000).unwrap_err();
+        encode(0, -1_000_000_000).unwrap_err();
+
+        let err = encode(1, -1).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid Duration { seconds: 1, nanos: -1 }: inconsistent signs"
+        );
+
+        let decode = |s: &str| parse_duration(s).map(|d| (d.seconds, d.nanos));
+        assert_eq!(decode("-0.5s").unwrap(), (0, -500_000_000));
+        assert_eq!(decode("-0s").unwrap(), (0, 0));
+        assert_eq!(decode(".5s").unwrap(), (0, 500_000_000));
+        assert_eq!(decode("007s").unwrap(), (7, 0));
+        assert_eq!(
+            decode("315576000000.999999999s").unwrap(),
+            (315_576_000_000, 999_999_999)
+        );
+        assert_eq!(decode("-315576000000s").unwrap(), (-315_576_000_000, 0));
+
+        for s in [
+            "",
+            "s",
+            "5",
+            "-s",
+            ".s",
+            "+5s",
+            "1.s",
+            "1.-5s",
+            "1.+5s",
+            "--1s",
+            "- 1s",
+            "1e3s",
+            "0x10s",
+            "1.5.5s",
+            "1s ",
+            "315576000001s",
+            "-315576000001s",
+            "99999999999999999999s",
+        ] {
+            decode(s).unwrap_err();
+        }
+
+        let err = serde_json::from_str::<Duration>("\"+5s\"").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid Duration \"+5s\": invalid seconds at line 1 column 5"
+        );
+    }
+
+    #[test]
+    fn test_normalize() {
+        let normalize = |seconds, nanos| {
+            let mut duration = Duration { seconds, nanos };
+            duration.normalize();
+            (duration.seconds, duration.nanos)
+        };
+
+        assert_eq!(normalize(1, 2_000_000_500), (3, 500));
+        assert_eq!(normalize(1, -1), (0, 999_999_999));
+        assert_eq!(normalize(-1, 1), (0, -999_999_999));
+        assert_eq!(normalize(0, -1_000_000_001), (-1, -1));
+        assert_eq!(normalize(i64::MAX, 1_000_000_000), (i64::MAX, 999_999_999));
+        assert_eq!(
+            normalize(i64::MIN, -1_000_000_000),
+            (i64::MIN, -999_999_999)
+        );
+    }
+
+    #[test]
+    fn test_ord() {
+        let duration = |seconds, nanos| Duration { seconds, nanos };
+
+        assert!(duration(-1, 0) < duration(0, -1));
+        assert!(duration(0, 999_999_999) < duration(1, 0));
+        assert!(duration(2, -1) < duration(2, 0));
+
+        // Equal values with different representations are ordered consistently with Eq
+        assert!(duration(0, 1_000_000_000) < duration(1, 0));
+        assert_eq!(duration(1, 0).cmp(&duration(1, 0)), Ordering::Equal);
+    }
+
+    #[test]
+    fn test_conversions() {
+        let duration = Duration {
+            seconds: -2,
+            nanos: -500_000_000,
+        };
+        assert!(duration.is_negative());
+        assert_eq!(
+            duration.unsigned_abs(),
+            core::time::Duration::from_millis(2_500)
+        );
+        core::time::Duration::try_from(duration).unwrap_err();
+
+        let duration = Duration {
+            seconds: 3,
+            nanos: -500_000_000,
+        };
+        assert!(!duration.is_negative());
+        assert_eq!(
+            core::time::Duration::try_from(duration).unwrap(),
+            core::time::Duration::from_millis(2_500)
+        );
+
+        assert_eq!(
+            Duration::try_from(core::time::Duration::from_nanos(1_000_000_007)).unwrap(),
+            Duration {
+                seconds: 1,
+                nanos: 7
+            }
+        );
+        Duration::try_from(core::time::Duration::from_secs(u64::MAX)).unwrap_err();
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn test_chrono() {
+        let delta = chrono::TimeDelta::milliseconds(-1_500);
+        let duration = Duration::from(delta);
+        assert_eq!(
+            duration,
+            Duration {
+                seconds: -1,
+                nanos: -500_000_000
+            }
+        );
+        assert_eq!(chrono::TimeDelta::try_from(duration).unwrap(), delta);
+        chrono::TimeDelta::try_from(Duration {
+            seconds: i64::MAX,
+            nanos: 0,
+        })
+        .unwrap_err();
+    }
+
+    #[cfg(feature = "time")]
+    #[test]
+    fn test_time() {
+        let delta = time::Duration::milliseconds(-1_500);
+        let duration = Duration::from(delta);
+        assert_eq!(
+            duration,
+            Duration {
+                seconds: -1,
+                nanos: -500_000_000
+            }
+        );
+        assert_eq!(time::Duration::try_from(duration).unwrap(), delta);
+        time::Duration::try_from(Duration {
+            seconds: i64::MAX,
+            nanos: 1_000_000_000,
+        })
+        .unwrap_err();
+    }
 }
@@ -0,0 +1,150 @@
+use crate::FieldMask;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use serde::de::Visitor;
+use serde::Serialize;
+
+impl From<Vec<String>> for FieldMask {
+    fn from(paths: Vec<String>) -> Self {
+        Self { paths }
+    }
+}
+
+impl FromIterator<String> for FieldMask {
+    fn from_iter<T>(iter: T) -> Self
+    where
+        T: IntoIterator<Item = String>,
+    {
+        Self {
+            paths: iter.into_iter().collect(),
+        }
+    }
+}
+
+impl Serialize for FieldMask {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let mut s = String::new();
+        for (idx, path) in self.paths.iter().enumerate() {
+            let camel_case = to_camel_case(path);
+            // A path that cannot round-trip would be decoded as a different path
+            if to_snake_case(&camel_case) != *path {
+                return Err(serde::ser::Error::custom(format!(
+                    "field mask path \"{}\" cannot be represented in lowerCamelCase",
+                    path
+                )));
+            }
+
+            if idx != 0 {
+                s.push(',');
+            }
+            s.push_str(&camel_case);
+        }
+        serializer.serialize_str(&s)
+    }
+}
+
+struct FieldMaskVisitor;
+
+impl<'de> Visitor<'de> for FieldMaskVisitor {
+    type Value = FieldMask;
+
+    fn expecting(&self, formatter: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        formatter.write_str("a comma-separated field mask string")
+    }
+
+    fn visit_str<E>(self, s: &str) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        if s.is_empty() {
+            return Ok(FieldMask::default());
+        }
+
+        s.split(',')
+            .map(|path| match path.contains('_') {
+                true => Err(serde::de::Error::custom(format!(
+                    "field mask path \"{}\" must be lowerCamelCase",
+                    path
+                ))),
+                false => Ok(to_snake_case(path)),
+            })
+            .collect()
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for FieldMask {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_str(FieldMaskVisitor)
+    }
+}
+
+/// Converts a snake_case path to lowerCamelCase, removing all underscores and
+/// capitalizing any lowercase letter following an underscore
+fn to_camel_case(path: &str) -> String {
+    let mut ret = String::with_capacity(path.len());
+    let mut was_underscore = false;
+    for c in path.chars() {
+        if c != '_' {
+            match was_underscore {
+                true => ret.push(c.to_ascii_uppercase()),
+                false => ret.push(c),
+            }
+        }
+        was_underscore = c == '_';
+    }
+    ret
+}
+
+/// Converts a lowerCamelCase path to snake_case, replacing each uppercase
+/// letter with an underscore followed by its lowercase equivalent
+fn to_snake_case(path: &str) -> String {
+    let mut ret = String::with_capacity(path.len());
+    for c in path.chars() {
+        if c.is_ascii_uppercase() {
+            ret.push('_');
+            ret.push(c.to_ascii_lowercase());
+        } else {
+            ret.push(c);
+        }
+    }
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+    use alloc::vec;
+
+    #[test]
+    fn test_field_mask() {
+        let verify = |paths: &[&str], expected: &str| {
+            let mask: FieldMask = paths.iter().map(ToString::to_string).collect();
+            assert_eq!(serde_json::to_string(&mask).unwrap().as_str(), expected);
+            assert_eq!(serde_json::from_str::<FieldMask>(expected).unwrap(), mask);
+        };
+
+        verify(&[], "\"\"");
+        verify(&["foo"], "\"foo\"");
+        verify(&["foo_bar"], "\"fooBar\"");
+        verify(&["foo_bar", "baz.qux_quux"], "\"fooBar,baz.quxQuux\"");
+        verify(&["foo.bar_baz.x"], "\"foo.barBaz.x\"");
+
+        // Paths that would not round-trip
+        for path in ["fooBar", "foo__bar", "foo_", "foo_1"] {
+            let mask = FieldMask::from(vec![path.to_string()]);
+            let err = serde_json::to_string(&mask).unwrap_err();
+            assert!(err.to_string().contains(path), "{}", err);
+        }
+
+        serde_json::from_str::<FieldMask>("\"foo_bar\"").unwrap_err();
+        serde_json::from_str::<FieldMask>("{\"paths\":[\"foo\"]}").unwrap_err();
+    }
+}
@@ -5,10 +5,26 @@
 //! __Note: Coverage of all types is currently incomplete,
 //! some may have non-compliant implementations__
 //!
+//! # Features
+//!
+//! - `chrono` (default): conversions between `Timestamp` and `Duration` and
+//!   `chrono::DateTime<Utc>` and `chrono::TimeDelta`
+//! - `time`: conversions between `Timestamp` and `Duration` and
+//!   `time::OffsetDateTime` and `time::Duration`
+//!
+//! The JSON representations of `Timestamp` and `Duration` do not depend on either
+//!
+//! - `std` (default): disable for `no_std` targets, which only require `alloc` and
+//!   `btree-map`. An `Any` can then only contain well-known types
+//! - `btree-map`: map fields, such as the fields of a `Struct`, are a `BTreeMap` rather
+//!   than a `HashMap`. As features are unified, enabling this changes [`Map`] for every
+//!   crate in the build that depends on `pbjson-types`
+//!
 //! [1]: https://docs.rs/serde/1.0.130/serde/trait.Serialize.html
 //! [2]: https://docs.rs/serde/1.0.130/serde/trait.Deserialize.html
 //! [3]: https://developers.google.com/protocol-buffers/docs/proto3#json
 
+#![cfg_attr(not(feature = "std"), no_std)]
 #![deny(rustdoc::broken_intra_doc_links, rustdoc::bare_urls, rust_2018_idioms)]
 #![warn(
     missing_debug_implementations,
@@ -18,6 +34,19 @@
     clippy::future_not_send
 )]
 
+extern crate alloc;
+
+#[cfg(not(any(feature = "std", feature = "btree-map")))]
+compile_error!("pbjson-types requires the `btree-map` feature without `std`");
+
+/// The type of map fields, such as the fields of a [`Struct`]
+#[cfg(not(feature = "btree-map"))]
+pub type Map<K, V> = std::collections::HashMap<K, V>;
+
+/// The type of map fields, such as the fields of a [`Struct`]
+#[cfg(feature = "btree-map")]
+pub type Map<K, V> = alloc::collections::BTreeMap<K, V>;
+
 #[allow(
     unused_imports,
     clippy::redundant_static_lifetimes,
@@ -36,7 +65,9 @@ mod pb {
     }
 }
 
+pub mod any;
 mod duration;
+mod field_mask;
 mod list_value;
 mod null_value;
 mod r#struct;
@@ -1,7 +1,8 @@
 use crate::Struct;
+use alloc::string::String;
 
-impl From<std::collections::HashMap<String, crate::Value>> for Struct {
-    fn from(fields: std::collections::HashMap<String, crate::Value>) -> Self {
+impl From<crate::Map<String, crate::Value>> for Struct {
+    fn from(fields: crate::Map<String, crate::Value>) -> Self {
         Self { fields }
     }
 }
@@ -40,7 +41,7 @@ struct StructVisitor;
 impl<'de> serde::de::Visitor<'de> for StructVisitor {
     type Value = Struct;
 
-    fn expecting(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn expecting(&self, formatter: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         formatter.write_str("google.protobuf.Struct")
     }
 
@@ -48,7 +49,7 @@ impl<'de> serde::de::Visitor<'de> for StructVisitor {
     where
         A: serde::de::MapAccess<'de>,
     {
-        let mut map = std::collections::HashMap::new();
+        let mut map = crate::Map::new();
 
         while let Some((key, value)) = map_access.next_entry()? {
             map.insert(key, value);
@@ -60,9 +61,12 @@ impl<'de> serde::de::Visitor<'de> for StructVisitor {
 
 #[cfg(test)]
 mod tests {
+    use alloc::string::String;
+    use alloc::vec;
+
     #[test]
     fn it_works() {
-        let map: crate::Struct = std::collections::HashMap::from([
+        let map: crate::Struct = crate::Map::from([
             (String::from("bool"), crate::Value::from(true)),
             (
                 String::from("unit"),
@@ -73,7 +77,7 @@ mod tests {
             (String::from("list"), vec![1.0.into(), 2.0.into()].into()),
             (
                 String::from("map"),
-                std::collections::HashMap::from([(String::from("key"), "value".into())]).into(),
+                crate::Map::from([(String::from("key"), "value".into())]).into(),
             ),
         ])
         .into();
@@ -1,26 +1,180 @@
-use crate::Timestamp;
-use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use crate::{Duration, Timestamp};
+use alloc::format;
+use alloc::string::String;
+use core::cmp::Ordering;
+use core::ops::{Add, Sub};
 use serde::de::Visitor;
 use serde::Serialize;
 
-impl TryFrom<Timestamp> for DateTime<Utc> {
+const NANOS_PER_SECOND: i128 = 1_000_000_000;
+
+impl Timestamp {
+    /// Returns the current time
+    #[cfg(feature = "std")]
+    pub fn now() -> Self {
+        std::time::SystemTime::now().into()
+    }
+
+    /// Returns the timestamp `nanos` nanoseconds after the Unix epoch, or `None` if its
+    /// seconds overflow an `i64`
+    fn checked_from_nanos(nanos: i128) -> Option<Self> {
+        Some(Self {
+            seconds: nanos.div_euclid(NANOS_PER_SECOND).try_into().ok()?,
+            nanos: nanos.rem_euclid(NANOS_PER_SECOND) as i32,
+        })
+    }
+
+    /// Returns the timestamp `nanos` nanoseconds after the Unix epoch, saturating if its
+    /// seconds overflow an `i64`
+    fn saturating_from_nanos(nanos: i128) -> Self {
+        Self::checked_from_nanos(nanos).unwrap_or(match nanos < 0 {
+            true => Self {
+                seconds: i64::MIN,
+                nanos: 0,
+            },
+            false => Self {
+                seconds: i64::MAX,
+                nanos: 999_999_999,
+            },
+        })
+    }
+
+    /// Returns the number of nanoseconds since the Unix epoch
+    fn as_nanos(&self) -> i128 {
+        i128::from(self.seconds) * NANOS_PER_SECOND + i128::from(self.nanos)
+    }
+
+    /// Normalizes this timestamp to the canonical representation, with nanos in the range
+    /// 0 to 999,999,999, saturating if the seconds overflow
+    pub fn normalize(&mut self) {
+        *self = Self::saturating_from_nanos(self.as_nanos());
+    }
+
+    /// Returns `self + duration`, or `None` if the result is outside of the range
+    /// 0001-01-01T00:00:00Z to 9999-12-31T23:59:59.999999999Z
+    pub fn checked_add(self, duration: Duration) -> Option<Self> {
+        let timestamp = Self::checked_from_nanos(self.as_nanos() + duration.as_nanos())?;
+        validate(&timestamp).ok()?;
+        Some(timestamp)
+    }
+
+    /// Returns `self - duration`, or `None` if the result is outside of the range
+    /// 0001-01-01T00:00:00Z to 9999-12-31T23:59:59.999999999Z
+    pub fn checked_sub(self, duration: Duration) -> Option<Self> {
+        let timestamp = Self::checked_from_nanos(self.as_nanos() - duration.as_nanos())?;
+        validate(&timestamp).ok()?;
+        Some(timestamp)
+    }
+
+    /// Returns the normalized duration from `other` to `self`, or `None` if its seconds
+    /// overflow an `i64`
+    pub fn checked_duration_since(self, other: Self) -> Option<Duration> {
+        Duration::checked_from_nanos(self.as_nanos() - other.as_nanos())
+    }
+}
+
+/// Orders timestamps by their normalized value, and equal values by their representation
+impl Ord for Timestamp {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.as_nanos()
+            .cmp(&other.as_nanos())
+            .then_with(|| (self.seconds, self.nanos).cmp(&(other.seconds, other.nanos)))
+    }
+}
+
+impl PartialOrd for Timestamp {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Add<Duration> for Timestamp {
+    type Output = Self;
+
+    /// # Panics
+    ///
+    /// Panics if the result is out of range, see [`Timestamp::checked_add`]
+    fn add(self, duration: Duration) -> Self {
+        self.checked_add(duration)
+            .expect("overflow when adding duration to timestamp")
+    }
+}
+
+impl Sub<Duration> for Timestamp {
+    type Output = Self;
+
+    /// # Panics
+    ///
+    /// Panics if the result is out of range, see [`Timestamp::checked_sub`]
+    fn sub(self, duration: Duration) -> Self {
+        self.checked_sub(duration)
+            .expect("overflow when subtracting duration from timestamp")
+    }
+}
+
+impl Sub for Timestamp {
+    type Output = Duration;
+
+    /// Returns the normalized duration from `other` to `self`
+    ///
+    /// # Panics
+    ///
+    /// Panics if the seconds of the result overflow an `i64`, which is possible for
+    /// timestamps outside of the range supported by the protobuf JSON mapping, see
+    /// [`Timestamp::checked_duration_since`]
+    fn sub(self, other: Self) -> Duration {
+        self.checked_duration_since(other)
+            .expect("overflow when subtracting timestamps")
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<std::time::SystemTime> for Timestamp {
+    fn from(value: std::time::SystemTime) -> Self {
+        let nanos = match value.duration_since(std::time::UNIX_EPOCH) {
+            Ok(duration) => duration.as_nanos() as i128,
+            Err(e) => -(e.duration().as_nanos() as i128),
+        };
+        Self::saturating_from_nanos(nanos)
+    }
+}
+
+#[cfg(feature = "std")]
+impl TryFrom<Timestamp> for std::time::SystemTime {
+    type Error = &'static str;
+
+    fn try_from(value: Timestamp) -> Result<Self, Self::Error> {
+        use std::time::UNIX_EPOCH;
+
+        let nanos = value.as_nanos();
+        let duration = Duration::checked_from_nanos(nanos).map(|d| d.unsigned_abs());
+        match nanos < 0 {
+            true => duration.and_then(|d| UNIX_EPOCH.checked_sub(d)),
+            false => duration.and_then(|d| UNIX_EPOCH.checked_add(d)),
+        }
+        .ok_or("timestamp out of range of SystemTime")
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl TryFrom<Timestamp> for chrono::DateTime<chrono::Utc> {
     type Error = &'static str;
     fn try_from(value: Timestamp) -> Result<Self, Self::Error> {
         let Timestamp { seconds, nanos } = value;
 
-        let dt = NaiveDateTime::from_timestamp_opt(
+        Self::from_timestamp(
             seconds,
             nanos
                 .try_into()
                 .map_err(|_| "out of range integral type conversion attempted")?,
         )
-        .ok_or("invalid or out-of-range datetime")?;
-        Ok(Utc.from_utc_datetime(&dt))
+        .ok_or("invalid or out-of-range datetime")
     }
 }
 
-impl From<DateTime<Utc>> for Timestamp {
-    fn from(value: DateTime<Utc>) -> Self {
+#[cfg(feature = "chrono")]
+impl From<chrono::DateTime<chrono::Utc>> for Timestamp {
+    fn from(value: chrono::DateTime<chrono::Utc>) -> Self {
         Self {
             seconds: value.timestamp(),
             nanos: value.timestamp_subsec_nanos() as i32,
@@ -28,13 +182,246 @@ impl From<DateTime<Utc>> for Timestamp {
     }
 }
 
+#[cfg(feature = "time")]
+impl TryFrom<Timestamp> for time::OffsetDateTime {
+    type Error = &'static str;
+    fn try_from(value: Timestamp) -> Result<Self, Self::Error> {
+        if !(0..1_000_000_000).contains(&value.nanos) {
+            return Err("out of range integral type conversion attempted");
+        }
+        Self::from_unix_timestamp_nanos(value.as_nanos())
+            .map_err(|_| "invalid or out-of-range datetime")
+    }
+}
+
+/// Converts the datetime to UTC, discarding its offset
+#[cfg(feature = "time")]
+impl From<time::OffsetDateTime> for Timestamp {
+    fn from(value: time::OffsetDateTime) -> Self {
+        Self {
+            seconds: value.unix_timestamp(),
+            nanos: value.nanosecond() as i32,
+        }
+    }
+}
+
+/// The seconds of 0001-01-01T00:00:00Z, the earliest valid timestamp
+const MIN_SECONDS: i64 = -62_135_596_800;
+
+/// The seconds of 9999-12-31T23:59:59Z, the latest valid timestamp
+const MAX_SECONDS: i64 = 253_402_300_799;
+
+const SECONDS_PER_DAY: i64 = 86_400;
+
+/// Returns an error if `timestamp` is outside of the range 0001-01-01T00:00:00Z
+/// to 9999-12-31T23:59:59.999999999Z, or has nanos outside of 0 to 999,999,999
+fn validate(timestamp: &Timestamp) -> Result<(), &'static str> {
+    if !(MIN_SECONDS..=MAX_SECONDS).contains(&timestamp.seconds) {
+        return Err("timestamp out of range");
+    }
+    if !(0..1_000_000_000).contains(&timestamp.nanos) {
+        return Err("timestamp nanos out of range");
+    }
+    Ok(())
+}
+
+/// Returns the number of days since 1970-01-01 of the date `year`-`month`-`day`
+/// of the proleptic Gregorian calendar
+///
+/// See <http://howardhinnant.github.io/date_algorithms.html#days_from_civil>
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let month = i64::from(month);
+    let day_of_year =
+        (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Returns the year, month and day of the date `days` since 1970-01-01
+/// of the proleptic Gregorian calendar
+///
+/// See <http://howardhinnant.github.io/date_algorithms.html#civil_from_days>
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+fn is_leap_year(year: i64) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Formats `timestamp` as an RFC 3339 string in UTC, with a "Z" suffix and
+/// 0, 3, 6 or 9 fractional digits, as required by the protobuf JSON mapping
+fn format_timestamp(timestamp: &Timestamp) -> Result<String, &'static str> {
+    validate(timestamp)?;
+
+    let (year, month, day) = civil_from_days(timestamp.seconds.div_euclid(SECONDS_PER_DAY));
+    let seconds_of_day = timestamp.seconds.rem_euclid(SECONDS_PER_DAY);
+    let mut s = format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        seconds_of_day / 3600,
+        seconds_of_day / 60 % 60,
+        seconds_of_day % 60
+    );
+
+    let nanos = timestamp.nanos;
+    if nanos != 0 {
+        let fraction = if nanos % 1_000_000 == 0 {
+            format!(".{:03}", nanos / 1_000_000)
+        } else if nanos % 1_000 == 0 {
+            format!(".{:06}", nanos / 1_000)
+        } else {
+            format!(".{:09}", nanos)
+        };
+        s.push_str(&fraction);
+    }
+
+    s.push('Z');
+    Ok(s)
+}
+
+/// A cursor over the bytes of an RFC 3339 string
+struct Parser<'a> {
+    s: &'a [u8],
+}
+
+impl Parser<'_> {
+    /// Consumes exactly `len` ascii digits, returning their value
+    fn digits(&mut self, len: usize) -> Option<u32> {
+        if self.s.len() < len {
+            return None;
+        }
+        let (digits, rest) = self.s.split_at(len);
+        self.s = rest;
+        digits.iter().try_fold(0, |value, c| {
+            c.is_ascii_digit().then(|| value * 10 + u32::from(c - b'0'))
+        })
+    }
+
+    /// Consumes the next byte if it is one of `expected`, returning it
+    fn byte(&mut self, expected: &[u8]) -> Option<u8> {
+        let (c, rest) = self.s.split_first()?;
+        self.s = rest;
+        expected.contains(c).then_some(*c)
+    }
+
+    /// Consumes one or more ascii digits, returning their number and value
+    fn fraction(&mut self) -> (usize, u64) {
+        let len = self.s.iter().take_while(|c| c.is_ascii_digit()).count();
+        let (digits, rest) = self.s.split_at(len);
+        self.s = rest;
+        let value = digits
+            .iter()
+            .take(9)
+            .fold(0, |value, c| value * 10 + u64::from(c - b'0'));
+        (len, value)
+    }
+}
+
+/// Parses the RFC 3339 string `s`, with any UTC offset and an optional fraction
+/// of at most 9 digits, e.g. `1972-01-01T10:00:20.021-05:00`
+fn parse_timestamp(s: &str) -> Result<Timestamp, &'static str> {
+    const INVALID: &str = "invalid RFC 3339 timestamp";
+
+    let mut parser = Parser { s: s.as_bytes() };
+    let mut parse = || {
+        let year = parser.digits(4)?;
+        parser.byte(b"-")?;
+        let month = parser.digits(2)?;
+        parser.byte(b"-")?;
+        let day = parser.digits(2)?;
+        parser.byte(b"Tt")?;
+        let hour = parser.digits(2)?;
+        parser.byte(b":")?;
+        let minute = parser.digits(2)?;
+        parser.byte(b":")?;
+        let second = parser.digits(2)?;
+
+        let mut nanos = 0;
+        let mut offset = parser.byte(b"Zz+-.")?;
+        if offset == b'.' {
+            let (len, fraction) = parser.fraction();
+            if !(1..=9).contains(&len) {
+                return None;
+            }
+            nanos = fraction * 10_u64.pow(9 - len as u32);
+            offset = parser.byte(b"Zz+-")?;
+        }
+
+        let offset_seconds = match offset {
+            b'+' | b'-' => {
+                let hours = parser.digits(2)?;
+                parser.byte(b":")?;
+                let minutes = parser.digits(2)?;
+                if hours > 23 || minutes > 59 {
+                    return None;
+                }
+                let seconds = i64::from(hours * 3600 + minutes * 60);
+                if offset == b'-' {
+                    -seconds
+                } else {
+                    seconds
+                }
+            }
+            _ => 0,
+        };
+
+        let year = i64::from(year);
+        let valid = parser.s.is_empty()
+            && (1..=12).contains(&month)
+            && (1..=days_in_month(year, month)).contains(&day)
+            && hour < 24
+            && minute < 60
+            && second < 60;
+        if !valid {
+            return None;
+        }
+
+        let seconds = days_from_civil(year, month, day) * SECONDS_PER_DAY
+            + i64::from(hour * 3600 + minute * 60 + second)
+            - offset_seconds;
+        Some(Timestamp {
+            seconds,
+            nanos: nanos as i32,
+        })
+    };
+
+    let timestamp = parse().ok_or(INVALID)?;
+    validate(&timestamp)?;
+    Ok(timestamp)
+}
+
 impl Serialize for Timestamp {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: serde::Serializer,
     {
-        let t: DateTime<Utc> = self.clone().try_into().map_err(serde::ser::Error::custom)?;
-        serializer.serialize_str(t.to_rfc3339().as_str())
+        let s = format_timestamp(self).map_err(serde::ser::Error::custom)?;
+        serializer.serialize_str(&s)
     }
 }
 
@@ -43,7 +430,7 @@ struct TimestampVisitor;
 impl<'de> Visitor<'de> for TimestampVisitor {
     type Value = Timestamp;
 
-    fn expecting(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn expecting(&self, formatter: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         formatter.write_str("a date string")
     }
 
@@ -51,9 +438,7 @@ impl<'de> Visitor<'de> for TimestampVisitor {
     where
         E: serde::de::Error,
     {
-        let d = DateTime::parse_from_rfc3339(s).map_err(serde::de::Error::custom)?;
-        let d: DateTime<Utc> = d.into();
-        Ok(d.into())
+        parse_timestamp(s).map_err(|e| serde::de::Error::custom(format!("{} \"{}\"", e, s)))
     }
 }
 
@@ -69,12 +454,14 @@ impl<'de> serde::Deserialize<'de> for Timestamp {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use chrono::{FixedOffset, TimeZone};
-    use serde::de::value::{BorrowedStrDeserializer, Error};
-    use serde::Deserialize;
 
+    #[cfg(feature = "chrono")]
     #[test]
     fn test_date() {
+        use chrono::{DateTime, FixedOffset, TimeZone, Utc};
+        use serde::de::value::{BorrowedStrDeserializer, Error};
+        use serde::Deserialize;
+
         let datetime = FixedOffset::east_opt(5 * 3600)
             .expect("time zone offset should be valid")
             .with_ymd_and_hms(2016, 11, 8, 21, 7, 9)
@@ -83,8 +470,6 @@ mod tests {
         assert_eq!(&encoded, "2016-11-08T21:07:09+05:00");
 
         let utc: DateTime<Utc> = datetime.into();
-        let utc_encoded = utc.to_rfc3339();
-        assert_eq!(&utc_encoded, "2016-11-08T16:07:09+00:00");
 
         let deserializer = BorrowedStrDeserializer::<'_, Error>::new(&encoded);
         let a: Timestamp = Timestamp::deserialize(deserializer).unwrap();
@@ -92,6 +477,230 @@ mod tests {
         assert_eq!(a.nanos, utc.timestamp_subsec_nanos() as i32);
 
         let encoded = serde_json::to_string(&a).unwrap();
-        assert_eq!(encoded, format!("\"{}\"", utc_encoded));
+        assert_eq!(encoded, "\"2016-11-08T16:07:09Z\"");
+    }
+
+    #[test]
+    fn test_format() {
+        let format = |seconds, nanos| format_timestamp(&Timestamp { seconds, nanos });
+
+        assert_eq!(format(0, 0).unwrap(), "1970-01-01T00:00:00Z");
+        assert_eq!(format(0, 10_000_000).unwrap(), "1970-01-01T00:00:00.010Z");
+        assert_eq!(format(0, 10_000).unwrap(), "1970-01-01T00:00:00.000010Z");
+        assert_eq!(format(0, 10).unwrap(), "1970-01-01T00:00:00.000000010Z");
+        assert_eq!(
+            format(-1, 999_999_999).unwrap(),
+            "1969-12-31T23:59:59.999999999Z"
+        );
+        assert_eq!(format(951_782_400, 0).unwrap(), "2000-02-29T00:00:00Z");
+        assert_eq!(format(MIN_SECONDS, 0).unwrap(), "0001-01-01T00:00:00Z");
+        assert_eq!(format(MAX_SECONDS, 0).unwrap(), "9999-12-31T23:59:59Z");
+
+        format(MIN_SECONDS - 1, 0).unwrap_err();
+        format(MAX_SECONDS + 1, 0).unwrap_err();
+        format(0, -1).unwrap_err();
+        format(0, 1_000_000_000).unwrap_err();
+
+        // Every formatted timestamp parses to itself
+        for seconds in (MIN_SECONDS..=MAX_SECONDS).step_by(7_777_777) {
+            let timestamp = parse_timestamp(&format(seconds, 0).unwrap()).unwrap();
+            assert_eq!(timestamp.seconds, seconds);
+        }
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn test_chrono() {
+        use chrono::{DateTime, Utc};
+
+        // Every formatted timestamp matches chrono
+        for seconds in (MIN_SECONDS..=MAX_SECONDS).step_by(7_777_777) {
+            let chrono = DateTime::from_timestamp(seconds, 0).unwrap();
+            assert_eq!(
+                format_timestamp(&Timestamp { seconds, nanos: 0 }).unwrap(),
+                chrono.format("%Y-%m-%dT%H:%M:%SZ").to_string()
+            );
+        }
+
+        let timestamp = Timestamp {
+            seconds: -2,
+            nanos: 500_000_000,
+        };
+        let datetime = DateTime::<Utc>::try_from(timestamp).unwrap();
+        assert_eq!(datetime.timestamp_millis(), -1_500);
+        assert_eq!(Timestamp::from(datetime), timestamp);
+
+        DateTime::<Utc>::try_from(Timestamp {
+            seconds: 0,
+            nanos: -1,
+        })
+        .unwrap_err();
+    }
+
+    #[cfg(feature = "time")]
+    #[test]
+    fn test_time() {
+        use time::{OffsetDateTime, UtcOffset};
+
+        let timestamp = Timestamp {
+            seconds: -2,
+            nanos: 500_000_000,
+        };
+        let datetime = OffsetDateTime::try_from(timestamp).unwrap();
+        assert_eq!(datetime.unix_timestamp_nanos(), -1_500_000_000);
+        assert_eq!(Timestamp::from(datetime), timestamp);
+
+        let offset = UtcOffset::from_hms(5, 30, 0).unwrap();
+        assert_eq!(Timestamp::from(datetime.to_offset(offset)), timestamp);
+
+        OffsetDateTime::try_from(Timestamp {
+            seconds: 0,
+            nanos: -1,
+        })
+        .unwrap_err();
+        OffsetDateTime::try_from(Timestamp {
+            seconds: i64::MAX,
+            nanos: 0,
+        })
+        .unwrap_err();
+    }
+
+    #[test]
+    fn test_parse() {
+        let parse = |s| parse_timestamp(s).map(|t| (t.seconds, t.nanos));
+
+        assert_eq!(parse("1970-01-01T00:00:00Z").unwrap(), (0, 0));
+        assert_eq!(parse("1970-01-01t00:00:00z").unwrap(), (0, 0));
+        assert_eq!(parse("1970-01-01T00:00:00.5Z").unwrap(), (0, 500_000_000));
+        assert_eq!(parse("1970-01-01T00:00:00.000000001Z").unwrap(), (0, 1));
+        assert_eq!(parse("1970-01-01T01:30:00+01:30").unwrap(), (0, 0));
+        assert_eq!(
+            parse("1969-12-31T22:00:00.25-02:00").unwrap(),
+            (0, 250_000_000)
+        );
+        assert_eq!(parse("2000-02-29T00:00:00Z").unwrap(), (951_782_400, 0));
+        assert_eq!(parse("0001-01-01T00:00:00Z").unwrap(), (MIN_SECONDS, 0));
+        assert_eq!(
+            parse("9999-12-31T23:59:59.999999999Z").unwrap(),
+            (MAX_SECONDS, 999_999_999)
+        );
+
+        for s in [
+            "",
+            "1970-01-01",
+            "1970-01-01T00:00:00",
+            "1970-01-01 00:00:00Z",
+            "1970-1-01T00:00:00Z",
+            "1970-01-01T00:00:00.Z",
+            "1970-01-01T00:00:00.0000000001Z",
+            "1970-01-01T00:00:00+0100",
+            "1970-01-01T00:00:00+24:00",
+            "1970-01-01T00:00:00Zx",
+            "1970-13-01T00:00:00Z",
+            "1970-00-01T00:00:00Z",
+            "1900-02-29T00:00:00Z",
+            "1970-04-31T00:00:00Z",
+            "1970-01-01T24:00:00Z",
+            "1970-01-01T00:60:00Z",
+            "1970-01-01T00:00:60Z",
+            "+1970-01-01T00:00:00Z",
+            // Out of range once converted to UTC
+            "0000-12-31T23:59:59Z",
+            "0001-01-01T00:00:00+00:01",
+            "9999-12-31T23:59:59-00:01",
+        ] {
+            parse(s).unwrap_err();
+        }
+    }
+
+    #[test]
+    fn test_normalize() {
+        let normalize = |seconds, nanos| {
+            let mut timestamp = Timestamp { seconds, nanos };
+            timestamp.normalize();
+            (timestamp.seconds, timestamp.nanos)
+        };
+
+        assert_eq!(normalize(1, 2_000_000_500), (3, 500));
+        assert_eq!(normalize(1, -1), (0, 999_999_999));
+        assert_eq!(normalize(0, -1_000_000_001), (-2, 999_999_999));
+        assert_eq!(normalize(i64::MAX, 1_000_000_000), (i64::MAX, 999_999_999));
+        assert_eq!(normalize(i64::MIN, -1), (i64::MIN, 0));
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let timestamp = |seconds, nanos| Timestamp { seconds, nanos };
+        let duration = |seconds, nanos| Duration { seconds, nanos };
+
+        assert_eq!(
+            timestamp(10, 600_000_000) + duration(1, 500_000_000),
+            timestamp(12, 100_000_000)
+        );
+        assert_eq!(
+            timestamp(10, 0) + duration(-10, -1),
+            timestamp(-1, 999_999_999)
+        );
+        assert_eq!(timestamp(10, 0) - duration(0, 1), timestamp(9, 999_999_999));
+        assert_eq!(
+            timestamp(10, 0) - timestamp(12, 100_000_000),
+            duration(-2, -100_000_000)
+        );
+        assert_eq!(
+            timestamp(MAX_SECONDS, 999_999_999) - timestamp(MIN_SECONDS, 0),
+            duration(MAX_SECONDS - MIN_SECONDS, 999_999_999)
+        );
+
+        // The result must be in the range supported by the JSON mapping
+        assert_eq!(timestamp(MAX_SECONDS, 0).checked_add(duration(1, 0)), None);
+        assert_eq!(timestamp(MIN_SECONDS, 0).checked_sub(duration(0, 1)), None);
+        assert_eq!(timestamp(0, 0).checked_add(duration(i64::MAX, 0)), None);
+        assert_eq!(
+            timestamp(MIN_SECONDS, 1).checked_sub(duration(0, 1)),
+            Some(timestamp(MIN_SECONDS, 0))
+        );
+
+        // Timestamps built in code need not be in that range
+        assert_eq!(
+            timestamp(i64::MAX, 0).checked_duration_since(timestamp(i64::MIN, 0)),
+            None
+        );
+        assert_eq!(
+            timestamp(i64::MAX, 0).checked_duration_since(timestamp(i64::MAX, -1)),
+            Some(duration(0, 1))
+        );
+
+        assert!(timestamp(0, 999_999_999) < timestamp(1, 0));
+        assert!(timestamp(-1, 0) < timestamp(0, -1));
+        assert!(timestamp(0, 1_000_000_000) < timestamp(1, 0));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_system_time() {
+        use std::time::{SystemTime, UNIX_EPOCH};
+
+        let now = Timestamp::now();
+        assert!(now > Timestamp::default());
+
+        let time = SystemTime::try_from(now).unwrap();
+        assert_eq!(Timestamp::from(time), now);
+
+        let before = UNIX_EPOCH - std::time::Duration::from_millis(1_500);
+        let timestamp = Timestamp::from(before);
+        assert_eq!(
+            timestamp,
+            Timestamp {
+                seconds: -2,
+                nanos: 500_000_000
+            }
+        );
+        assert_eq!(SystemTime::try_from(timestamp).unwrap(), before);
+
+        SystemTime::try_from(Timestamp {
+            seconds: i64::MAX,
+            nanos: 1_000_000_000,
+        })
+        .unwrap_err();
     }
 }
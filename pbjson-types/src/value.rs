@@ -1,5 +1,7 @@
 pub use crate::pb::google::protobuf::value::Kind;
 
+use alloc::string::String;
+use alloc::vec::Vec;
 use serde::{
     de::{self, MapAccess, SeqAccess},
     ser, Deserialize, Deserializer, Serialize, Serializer,
@@ -30,7 +32,7 @@ from! {
         crate::ListValue => Kind::from(value).into(),
         crate::Struct => Kind::from(value).into(),
         f64 => Kind::from(value).into(),
-        std::collections::HashMap<String, Self> => Kind::from(value).into(),
+        crate::Map<String, Self> => Kind::from(value).into(),
     }
 
     Kind[value] => {
@@ -42,7 +44,7 @@ from! {
         crate::ListValue => Self::ListValue(value),
         crate::Struct => Self::StructValue(value),
         f64 => Self::NumberValue(value),
-        std::collections::HashMap<String, crate::Value> => Self::StructValue(value.into()),
+        crate::Map<String, crate::Value> => Self::StructValue(value.into()),
     }
 }
 
@@ -66,8 +68,9 @@ impl<'de> Deserialize<'de> for crate::Value {
     where
         D: Deserializer<'de>,
     {
+        // null is a value, and so is decoded as NullValue rather than no kind
         Ok(Self {
-            kind: <_>::deserialize(deserializer)?,
+            kind: Some(Kind::deserialize(deserializer)?),
         })
     }
 }
@@ -116,7 +119,7 @@ struct KindVisitor;
 impl<'de> serde::de::Visitor<'de> for KindVisitor {
     type Value = Kind;
 
-    fn expecting(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn expecting(&self, formatter: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         formatter.write_str("google.protobuf.Value")
     }
 
@@ -289,7 +292,7 @@ impl<'de> serde::de::Visitor<'de> for KindVisitor {
     where
         A: MapAccess<'de>,
     {
-        let mut map = std::collections::HashMap::new();
+        let mut map = crate::Map::new();
 
         while let Some((key, value)) = map_access.next_entry()? {
             map.insert(key, value);
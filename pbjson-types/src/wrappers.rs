@@ -1,3 +1,4 @@
+use alloc::string::String;
 use prost::bytes::Bytes;
 
 macro_rules! ser_scalar_value {
@@ -12,6 +13,18 @@ macro_rules! ser_scalar_value {
         }
     };
 }
+macro_rules! ser_float_value {
+    ($typ: ty) => {
+        impl serde::Serialize for $typ {
+            fn serialize<S>(&self, ser: S) -> Result<S::Ok, S::Error>
+            where
+                S: serde::Serializer,
+            {
+                pbjson::private::FloatSerialize(self.value).serialize(ser)
+            }
+        }
+    };
+}
 macro_rules! deser_scalar_value {
     ($typ: ty) => {
         impl<'de> serde::Deserialize<'de> for $typ {
@@ -32,10 +45,7 @@ macro_rules! ser_bytes_value {
             where
                 S: serde::Serializer,
             {
-                use pbjson::private::base64::engine::Engine;
-                let value =
-                    pbjson::private::base64::engine::general_purpose::STANDARD.encode(&self.value);
-                value.serialize(ser)
+                pbjson::private::BytesSerialize(&self.value).serialize(ser)
             }
         }
     };
@@ -60,8 +70,7 @@ macro_rules! ser_long_value {
             where
                 S: serde::Serializer,
             {
-                let value = self.value.to_string();
-                value.serialize(ser)
+                pbjson::private::Int64Serialize(self.value).serialize(ser)
             }
         }
     };
@@ -94,9 +103,9 @@ ser_scalar_value!(crate::BoolValue);
 deser_scalar_value!(crate::BoolValue);
 ser_bytes_value!(crate::BytesValue);
 deser_bytes_value!(crate::BytesValue);
-ser_scalar_value!(crate::DoubleValue);
+ser_float_value!(crate::DoubleValue);
 deser_number_value!(crate::DoubleValue);
-ser_scalar_value!(crate::FloatValue);
+ser_float_value!(crate::FloatValue);
 deser_number_value!(crate::FloatValue);
 ser_scalar_value!(crate::Int32Value);
 deser_number_value!(crate::Int32Value);
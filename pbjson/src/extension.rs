@@ -0,0 +1,120 @@
+//! Support for proto2 extensions
+//!
+//! The JSON mapping encodes set extensions as fields named by the bracketed
+//! fully-qualified name of the extension, e.g. `"[mypackage.my_extension]"`
+//!
+//! As prost does not generate storage for extensions, code generated with
+//! extensions enabled reads and writes extension values through [`ExtensionStorage`]
+
+use alloc::borrow::Cow;
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use core::any::Any;
+
+/// Storage for the proto2 extensions set on a message
+///
+/// Values are keyed by the fully-qualified name of the extension, without brackets,
+/// and have the rust type prost would use for a field of the same type, i.e. `i32`
+/// for enumerations, `Vec<u8>` for bytes and `Vec<T>` for repeated extensions
+pub trait ExtensionStorage {
+    /// Returns the value of the extension `name` if set
+    fn extension<T>(&self, name: &str) -> Option<Cow<'_, T>>
+    where
+        T: Any + Clone + Send + Sync;
+
+    /// Sets the value of the extension `name`
+    fn set_extension<T>(&mut self, name: &str, value: T)
+    where
+        T: Any + Clone + Send + Sync;
+}
+
+/// A set of extension values, that can be used to implement [`ExtensionStorage`]
+#[derive(Debug, Default)]
+pub struct ExtensionSet {
+    values: BTreeMap<String, Box<dyn Any + Send + Sync>>,
+}
+
+impl ExtensionSet {
+    /// Create a new, empty, `ExtensionSet`
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the value of the extension `name` if set, and of type `T`
+    pub fn get<T: Any>(&self, name: &str) -> Option<&T> {
+        self.values.get(name)?.downcast_ref()
+    }
+
+    /// Sets the value of the extension `name`, replacing any previous value
+    pub fn set<T: Any + Send + Sync>(&mut self, name: impl Into<String>, value: T) {
+        self.values.insert(name.into(), Box::new(value));
+    }
+
+    /// Clears the value of the extension `name`, returning true if it was set
+    pub fn clear(&mut self, name: &str) -> bool {
+        self.values.remove(name).is_some()
+    }
+
+    /// Returns an iterator over the names of the set extensions
+    pub fn names(&self) -> impl Iterator<Item = &str> + '_ {
+        self.values.keys().map(String::as_str)
+    }
+
+    /// Returns the number of set extensions
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Returns true if no extensions are set
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+}
+
+impl ExtensionStorage for ExtensionSet {
+    fn extension<T>(&self, name: &str) -> Option<Cow<'_, T>>
+    where
+        T: Any + Clone + Send + Sync,
+    {
+        self.get(name).map(Cow::Borrowed)
+    }
+
+    fn set_extension<T>(&mut self, name: &str, value: T)
+    where
+        T: Any + Clone + Send + Sync,
+    {
+        self.set(name, value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn test_extension_set() {
+        let mut set = ExtensionSet::new();
+        assert!(set.is_empty());
+
+        set.set("foo.bar", 1_i32);
+        set.set_extension("foo.baz", vec![String::from("a")]);
+
+        assert_eq!(set.len(), 2);
+        assert_eq!(set.get::<i32>("foo.bar"), Some(&1));
+        // A value of a different type is not returned
+        assert_eq!(set.get::<i64>("foo.bar"), None);
+        assert_eq!(
+            set.extension::<Vec<String>>("foo.baz").unwrap().as_slice(),
+            &["a".to_string()]
+        );
+        assert_eq!(set.names().collect::<Vec<_>>(), vec!["foo.bar", "foo.baz"]);
+
+        assert!(set.clear("foo.bar"));
+        assert!(!set.clear("foo.bar"));
+        assert_eq!(set.get::<i32>("foo.bar"), None);
+    }
+}
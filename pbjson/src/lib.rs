@@ -8,6 +8,11 @@
 //! [2]: https://developers.google.com/protocol-buffers/docs/proto3#json
 //! [3]: https://docs.rs/pbjson-build
 //!
+//! # Features
+//!
+//! - `std` (default): disable for `no_std` targets, which only require `alloc`
+//!
+#![cfg_attr(not(feature = "std"), no_std)]
 #![deny(rustdoc::broken_intra_doc_links, rustdoc::bare_urls, rust_2018_idioms)]
 #![warn(
     missing_debug_implementations,
@@ -17,18 +22,32 @@
     clippy::future_not_send
 )]
 
+extern crate alloc;
+
+pub use extension::{ExtensionSet, ExtensionStorage};
+
+pub mod extension;
+
 #[doc(hidden)]
 pub mod private {
+    /// Re-export alloc, for the code generated for `no_std` crates
+    pub extern crate alloc;
     /// Re-export base64
     pub use base64;
 
+    use alloc::borrow::Cow;
+    use alloc::format;
+    use alloc::vec::Vec;
+    use base64::display::Base64Display;
     use base64::engine::DecodePaddingMode;
     use base64::engine::{GeneralPurpose, GeneralPurposeConfig};
     use base64::Engine;
-    use serde::de::Visitor;
+    use core::fmt::Write;
+    use core::marker::PhantomData;
+    use core::str::FromStr;
+    use serde::de::value::{I64Deserializer, StrDeserializer, U64Deserializer};
+    use serde::de::{Unexpected, Visitor};
     use serde::Deserialize;
-    use std::borrow::Cow;
-    use std::str::FromStr;
 
     /// Used to parse a number from either a string or its raw representation
     #[derive(Debug, Copy, Clone, PartialOrd, PartialEq, Hash, Ord, Eq)]
@@ -45,7 +64,7 @@ pub mod private {
     impl<'de, T> serde::Deserialize<'de> for NumberDeserialize<T>
     where
         T: FromStr + serde::Deserialize<'de>,
-        <T as FromStr>::Err: std::error::Error,
+        <T as FromStr>::Err: core::fmt::Display,
     {
         fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
         where
@@ -53,18 +72,332 @@ pub mod private {
         {
             let content = Content::deserialize(deserializer)?;
             Ok(Self(match content {
-                Content::Str(v) => v.parse().map_err(serde::de::Error::custom)?,
+                Content::Str(v) => {
+                    // FromStr for floats accepts spellings such as "inf" or "nan", whereas
+                    // the JSON mapping only permits "NaN", "Infinity" and "-Infinity"
+                    let unsigned = v.strip_prefix(['+', '-']).unwrap_or(&v);
+                    let special = unsigned.starts_with(|c: char| c.is_ascii_alphabetic());
+                    if special && !matches!(v.as_ref(), "NaN" | "Infinity" | "-Infinity") {
+                        return Err(serde::de::Error::custom(format!("invalid number: {}", v)));
+                    }
+                    v.parse().map_err(serde::de::Error::custom)?
+                }
                 Content::Number(v) => v,
             }))
         }
     }
 
+    /// Used to serialize a float, encoding NaN and infinite values as strings
+    #[derive(Debug, Copy, Clone, PartialOrd, PartialEq)]
+    pub struct FloatSerialize<T>(pub T);
+
+    macro_rules! float_serialize {
+        ($typ: ty, $serialize: ident) => {
+            impl serde::Serialize for FloatSerialize<$typ> {
+                fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+                where
+                    S: serde::Serializer,
+                {
+                    match self.0 {
+                        v if v.is_nan() => serializer.serialize_str("NaN"),
+                        v if v == <$typ>::INFINITY => serializer.serialize_str("Infinity"),
+                        v if v == <$typ>::NEG_INFINITY => serializer.serialize_str("-Infinity"),
+                        v => serializer.$serialize(v),
+                    }
+                }
+            }
+        };
+    }
+
+    float_serialize!(f32, serialize_f32);
+    float_serialize!(f64, serialize_f64);
+
+    /// A fixed-size buffer on the stack, used to format a value without allocating
+    struct StackBuffer<const N: usize> {
+        buf: [u8; N],
+        len: usize,
+    }
+
+    impl<const N: usize> StackBuffer<N> {
+        fn new() -> Self {
+            Self {
+                buf: [0; N],
+                len: 0,
+            }
+        }
+
+        fn as_str(&self) -> &str {
+            // Only complete strings are written to the buffer
+            core::str::from_utf8(&self.buf[..self.len]).expect("valid UTF-8")
+        }
+    }
+
+    impl<const N: usize> Write for StackBuffer<N> {
+        fn write_str(&mut self, s: &str) -> core::fmt::Result {
+            let end = self.len + s.len();
+            self.buf
+                .get_mut(self.len..end)
+                .ok_or(core::fmt::Error)?
+                .copy_from_slice(s.as_bytes());
+            self.len = end;
+            Ok(())
+        }
+    }
+
+    /// The length of the longest 64-bit integer, `i64::MIN`
+    const MAX_INTEGER_LEN: usize = 20;
+
+    /// Serializes the 64-bit integer `v` as a string, formatted on the stack
+    fn serialize_integer_str<S, T>(serializer: S, v: T) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+        T: core::fmt::Display,
+    {
+        let mut buffer = StackBuffer::<MAX_INTEGER_LEN>::new();
+        write!(buffer, "{}", v).expect("64-bit integer fits in the buffer");
+        serializer.serialize_str(buffer.as_str())
+    }
+
+    /// Used to serialize a 64-bit integer as a string, as required by the JSON mapping
+    #[derive(Debug, Copy, Clone, PartialOrd, PartialEq, Hash, Ord, Eq)]
+    pub struct Int64Serialize<T>(pub T);
+
+    macro_rules! int64_serialize {
+        ($typ: ty) => {
+            impl serde::Serialize for Int64Serialize<$typ> {
+                fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+                where
+                    S: serde::Serializer,
+                {
+                    serialize_integer_str(serializer, self.0)
+                }
+            }
+        };
+    }
+
+    int64_serialize!(i64);
+    int64_serialize!(u64);
+
+    /// The largest integer n such that n and n + 1 are exactly representable by an f64
+    const MAX_SAFE_INTEGER: i128 = (1 << 53) - 1;
+
+    /// Used to serialize a 64-bit integer as a number if it is exactly representable
+    /// by an IEEE 754 double, and as a string otherwise
+    #[derive(Debug, Copy, Clone, PartialOrd, PartialEq, Hash, Ord, Eq)]
+    pub struct SafeIntegerSerialize<T>(pub T);
+
+    macro_rules! safe_integer_serialize {
+        ($typ: ty, $serialize: ident) => {
+            impl serde::Serialize for SafeIntegerSerialize<$typ> {
+                fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+                where
+                    S: serde::Serializer,
+                {
+                    match self.0 {
+                        v if i128::from(v).abs() <= MAX_SAFE_INTEGER => serializer.$serialize(v),
+                        v => serialize_integer_str(serializer, v),
+                    }
+                }
+            }
+        };
+    }
+
+    safe_integer_serialize!(i64, serialize_i64);
+    safe_integer_serialize!(u64, serialize_u64);
+
+    /// Used to serialize the integer value of an enumeration `T`
+    ///
+    /// Values that are not a known variant of `T`, such as those added in a newer
+    /// version of the schema, are serialized as their integer value
+    #[derive(Debug, Copy, Clone, PartialOrd, PartialEq, Hash, Ord, Eq)]
+    pub struct EnumSerialize<T>(pub i32, pub PhantomData<T>);
+
+    impl<T> EnumSerialize<T> {
+        pub fn new(value: i32) -> Self {
+            Self(value, PhantomData)
+        }
+    }
+
+    impl<T> serde::Serialize for EnumSerialize<T>
+    where
+        T: TryFrom<i32> + serde::Serialize,
+    {
+        fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where
+            S: serde::Serializer,
+        {
+            match T::try_from(self.0) {
+                Ok(variant) => variant.serialize(serializer),
+                Err(_) => serializer.serialize_i32(self.0),
+            }
+        }
+    }
+
+    /// Used to parse an enumeration `T` from either its name or its integer value,
+    /// yielding `None` instead of an error if it is not a known variant
+    #[derive(Debug, Copy, Clone, PartialOrd, PartialEq, Hash, Ord, Eq)]
+    pub struct EnumDeserialize<T>(pub Option<T>);
+
+    struct EnumVisitor<T>(PhantomData<T>);
+
+    impl<'de, T> Visitor<'de> for EnumVisitor<T>
+    where
+        T: Deserialize<'de>,
+    {
+        type Value = Option<T>;
+
+        fn expecting(&self, formatter: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+            formatter.write_str("an enumeration variant name or integer")
+        }
+
+        fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+        where
+            E: serde::de::Error,
+        {
+            Ok(T::deserialize(StrDeserializer::<serde::de::value::Error>::new(v)).ok())
+        }
+
+        fn visit_i64<E>(self, v: i64) -> Result<Self::Value, E>
+        where
+            E: serde::de::Error,
+        {
+            Ok(T::deserialize(I64Deserializer::<serde::de::value::Error>::new(v)).ok())
+        }
+
+        fn visit_u64<E>(self, v: u64) -> Result<Self::Value, E>
+        where
+            E: serde::de::Error,
+        {
+            Ok(T::deserialize(U64Deserializer::<serde::de::value::Error>::new(v)).ok())
+        }
+    }
+
+    impl<'de, T> Deserialize<'de> for EnumDeserialize<T>
+    where
+        T: Deserialize<'de>,
+    {
+        fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where
+            D: serde::Deserializer<'de>,
+        {
+            Ok(Self(
+                deserializer.deserialize_any(EnumVisitor(PhantomData))?,
+            ))
+        }
+    }
+
+    /// Used to parse the integer value of an open enumeration `T` from either the
+    /// name of a variant or an integer, which need not correspond to a variant
+    #[derive(Debug, Copy, Clone, PartialOrd, PartialEq, Hash, Ord, Eq)]
+    pub struct OpenEnumDeserialize<T>(pub i32, pub PhantomData<T>);
+
+    struct OpenEnumVisitor<T>(PhantomData<T>);
+
+    impl<'de, T> Visitor<'de> for OpenEnumVisitor<T>
+    where
+        T: Deserialize<'de> + Into<i32>,
+    {
+        type Value = i32;
+
+        fn expecting(&self, formatter: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+            formatter.write_str("an enumeration variant name or integer")
+        }
+
+        fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+        where
+            E: serde::de::Error,
+        {
+            T::deserialize(StrDeserializer::<E>::new(v)).map(Into::into)
+        }
+
+        fn visit_i64<E>(self, v: i64) -> Result<Self::Value, E>
+        where
+            E: serde::de::Error,
+        {
+            i32::try_from(v).map_err(|_| E::invalid_value(Unexpected::Signed(v), &self))
+        }
+
+        fn visit_u64<E>(self, v: u64) -> Result<Self::Value, E>
+        where
+            E: serde::de::Error,
+        {
+            i32::try_from(v).map_err(|_| E::invalid_value(Unexpected::Unsigned(v), &self))
+        }
+    }
+
+    impl<'de, T> Deserialize<'de> for OpenEnumDeserialize<T>
+    where
+        T: Deserialize<'de> + Into<i32>,
+    {
+        fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where
+            D: serde::Deserializer<'de>,
+        {
+            let value = deserializer.deserialize_any(OpenEnumVisitor::<T>(PhantomData))?;
+            Ok(Self(value, PhantomData))
+        }
+    }
+
+    /// Used to serialize bytes as a standard base64 string, encoded in chunks on the stack
+    #[derive(Debug, Copy, Clone, PartialOrd, PartialEq, Hash, Ord, Eq)]
+    pub struct BytesSerialize<T>(pub T);
+
+    impl<T> serde::Serialize for BytesSerialize<T>
+    where
+        T: AsRef<[u8]>,
+    {
+        fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where
+            S: serde::Serializer,
+        {
+            let engine = &base64::engine::general_purpose::STANDARD;
+            serializer.collect_str(&Base64Display::new(self.0.as_ref(), engine))
+        }
+    }
+
+    /// Used to serialize the items of the iterator `I` as a sequence, without first
+    /// collecting them into a container
+    #[derive(Debug, Copy, Clone)]
+    pub struct SeqSerialize<I>(pub I);
+
+    impl<I> serde::Serialize for SeqSerialize<I>
+    where
+        I: Iterator + Clone,
+        I::Item: serde::Serialize,
+    {
+        fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where
+            S: serde::Serializer,
+        {
+            serializer.collect_seq(self.0.clone())
+        }
+    }
+
+    /// Used to serialize the key-value pairs of the iterator `I` as a map, without first
+    /// collecting them into a container
+    #[derive(Debug, Copy, Clone)]
+    pub struct MapSerialize<I>(pub I);
+
+    impl<I, K, V> serde::Serialize for MapSerialize<I>
+    where
+        I: Iterator<Item = (K, V)> + Clone,
+        K: serde::Serialize,
+        V: serde::Serialize,
+    {
+        fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where
+            S: serde::Serializer,
+        {
+            serializer.collect_map(self.0.clone())
+        }
+    }
+
     struct Base64Visitor;
 
     impl<'de> Visitor<'de> for Base64Visitor {
         type Value = Vec<u8>;
 
-        fn expecting(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fn expecting(&self, formatter: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
             formatter.write_str("a base64 string")
         }
 
@@ -116,17 +449,112 @@ pub mod private {
     #[cfg(test)]
     mod tests {
         use super::*;
+        use alloc::string::String;
         use base64::Engine;
         use bytes::Bytes;
         use rand::prelude::*;
-        use serde::de::value::{BorrowedStrDeserializer, Error};
+        use serde::de::value::{BorrowedStrDeserializer, Error, F64Deserializer};
+
+        #[test]
+        fn test_float() {
+            let parse = |s: &str| {
+                let deserializer = BorrowedStrDeserializer::<'_, Error>::new(s);
+                NumberDeserialize::<f64>::deserialize(deserializer).map(|x| x.0)
+            };
+
+            assert!(parse("NaN").unwrap().is_nan());
+            assert_eq!(parse("Infinity").unwrap(), f64::INFINITY);
+            assert_eq!(parse("-Infinity").unwrap(), f64::NEG_INFINITY);
+            assert_eq!(parse("-1.5e3").unwrap(), -1500.);
+
+            for s in ["nan", "inf", "-inf", "infinity", "+Infinity", "+NaN"] {
+                parse(s).unwrap_err();
+            }
+
+            let deserializer = F64Deserializer::<Error>::new(f64::INFINITY);
+            let v = NumberDeserialize::<f64>::deserialize(deserializer)
+                .unwrap()
+                .0;
+            assert_eq!(v, f64::INFINITY);
+
+            let encode = |v| serde_json::to_string(&v).unwrap();
+            assert_eq!(encode(FloatSerialize(f64::NAN)), "\"NaN\"");
+            assert_eq!(encode(FloatSerialize(f64::INFINITY)), "\"Infinity\"");
+            assert_eq!(encode(FloatSerialize(f64::NEG_INFINITY)), "\"-Infinity\"");
+            assert_eq!(encode(FloatSerialize(1.5)), "1.5");
+            assert_eq!(
+                serde_json::to_string(&FloatSerialize(f32::INFINITY)).unwrap(),
+                "\"Infinity\""
+            );
+        }
+
+        #[test]
+        fn test_safe_integer() {
+            fn encode(v: impl serde::Serialize) -> String {
+                serde_json::to_string(&v).unwrap()
+            }
+
+            assert_eq!(encode(SafeIntegerSerialize(0_i64)), "0");
+            assert_eq!(
+                encode(SafeIntegerSerialize(-9007199254740991_i64)),
+                "-9007199254740991"
+            );
+            assert_eq!(
+                encode(SafeIntegerSerialize(-9007199254740992_i64)),
+                "\"-9007199254740992\""
+            );
+            assert_eq!(
+                encode(SafeIntegerSerialize(i64::MIN)),
+                "\"-9223372036854775808\""
+            );
+            assert_eq!(
+                encode(SafeIntegerSerialize(9007199254740991_u64)),
+                "9007199254740991"
+            );
+            assert_eq!(
+                encode(SafeIntegerSerialize(u64::MAX)),
+                "\"18446744073709551615\""
+            );
+        }
+
+        #[test]
+        fn test_int64() {
+            fn encode(v: impl serde::Serialize) -> String {
+                serde_json::to_string(&v).unwrap()
+            }
+
+            assert_eq!(encode(Int64Serialize(0_i64)), "\"0\"");
+            assert_eq!(encode(Int64Serialize(i64::MIN)), "\"-9223372036854775808\"");
+            assert_eq!(encode(Int64Serialize(u64::MAX)), "\"18446744073709551615\"");
+        }
+
+        #[test]
+        fn test_adapters() {
+            let values = [1_i64, -2];
+            let seq = SeqSerialize(values.iter().map(|v| Int64Serialize(*v)));
+            assert_eq!(serde_json::to_string(&seq).unwrap(), r#"["1","-2"]"#);
+
+            let empty = SeqSerialize(core::iter::empty::<u8>());
+            assert_eq!(serde_json::to_string(&empty).unwrap(), "[]");
+
+            let entries = [("a", &b"hello"[..]), ("b", &[][..])];
+            let map = MapSerialize(entries.iter().map(|(k, v)| (k, BytesSerialize(v))));
+            assert_eq!(
+                serde_json::to_string(&map).unwrap(),
+                r#"{"a":"aGVsbG8=","b":""}"#
+            );
+        }
 
         #[test]
         fn test_bytes() {
             for _ in 0..20 {
                 let mut rng = thread_rng();
                 let len = rng.gen_range(50..100);
-                let raw: Vec<_> = std::iter::from_fn(|| Some(rng.gen())).take(len).collect();
+                let raw: Vec<_> = core::iter::from_fn(|| Some(rng.gen())).take(len).collect();
+
+                let encoded = serde_json::to_string(&BytesSerialize(&raw)).unwrap();
+                let expected = base64::engine::general_purpose::STANDARD.encode(&raw);
+                assert_eq!(encoded, format!("\"{}\"", expected));
 
                 for config in [
                     base64::engine::general_purpose::STANDARD,